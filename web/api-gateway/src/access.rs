@@ -0,0 +1,358 @@
+use ipnet::IpNet;
+use rocket::http::HeaderMap;
+use serde::{Deserialize, Deserializer};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+// CIDR 网段，也接受单个 IP（等价于 /32 或 /128）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cidr(IpNet);
+
+impl Cidr {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        // IPv4 映射的 IPv6 地址（::ffff:a.b.c.d）按 IPv4 处理
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(*ip),
+            IpAddr::V4(_) => *ip,
+        };
+        self.0.contains(&ip)
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(net) = s.parse::<IpNet>() {
+            return Ok(Cidr(net));
+        }
+        s.parse::<IpAddr>()
+            .map(|ip| Cidr(IpNet::from(ip)))
+            .map_err(|_| format!("invalid CIDR or IP address: {}", s))
+    }
+}
+
+impl<'de> Deserialize<'de> for Cidr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+// IP 访问控制：deny 优先；allow 不为空时只放行命中的地址
+#[derive(Debug, Default, Deserialize)]
+pub struct AccessConfig {
+    #[serde(default)]
+    pub allow: Vec<Cidr>,
+    #[serde(default)]
+    pub deny: Vec<Cidr>,
+}
+
+impl AccessConfig {
+    pub fn permits(&self, ip: Option<IpAddr>) -> bool {
+        let Some(ip) = ip else {
+            // 拿不到客户端 IP 时，只有没有配置白名单才放行
+            return self.allow.is_empty();
+        };
+
+        if self.deny.iter().any(|net| net.contains(&ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|net| net.contains(&ip))
+    }
+}
+
+// 受信代理用哪个头传递客户端链路。只读取这一个：代理只追加 X-Forwarded-For 时，
+// 客户端自带的 Forwarded 会被原样转发过来，不能用来判断客户端地址
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+pub enum ForwardedHeader {
+    #[default]
+    #[serde(rename = "x-forwarded-for")]
+    XForwardedFor,
+    #[serde(rename = "forwarded")]
+    Forwarded,
+}
+
+pub fn is_trusted(ip: &IpAddr, trusted_proxies: &[Cidr]) -> bool {
+    trusted_proxies.iter().any(|net| net.contains(ip))
+}
+
+// 计算真实客户端 IP：
+// 直连方不是受信代理时，直接使用直连地址（忽略可伪造的转发头）；
+// 否则从右往左遍历配置的转发头，跳过受信代理，第一个不受信的就是客户端
+pub fn resolve_client_ip(
+    peer: Option<IpAddr>,
+    headers: &HeaderMap<'_>,
+    trusted_proxies: &[Cidr],
+    forwarded_header: ForwardedHeader,
+) -> Option<IpAddr> {
+    let peer = peer?;
+    if !is_trusted(&peer, trusted_proxies) {
+        return Some(peer);
+    }
+
+    let chain = forwarded_chain(headers, forwarded_header);
+    chain
+        .iter()
+        .rev()
+        .find(|ip| !is_trusted(ip, trusted_proxies))
+        .or(chain.first())
+        .copied()
+        .or(Some(peer))
+}
+
+fn forwarded_chain(headers: &HeaderMap<'_>, forwarded_header: ForwardedHeader) -> Vec<IpAddr> {
+    match forwarded_header {
+        ForwardedHeader::Forwarded => headers
+            .get("Forwarded")
+            .flat_map(|value| value.split(','))
+            .filter_map(|element| {
+                element
+                    .split(';')
+                    .find_map(|pair| {
+                        let (key, value) = pair.trim().split_once('=')?;
+                        key.eq_ignore_ascii_case("for").then_some(value)
+                    })
+                    .and_then(parse_forwarded_node)
+            })
+            .collect(),
+        ForwardedHeader::XForwardedFor => headers
+            .get("X-Forwarded-For")
+            .flat_map(|value| value.split(','))
+            .filter_map(|ip| ip.trim().parse().ok())
+            .collect(),
+    }
+}
+
+// 解析 Forwarded 里的节点：192.0.2.60、"192.0.2.60:8080"、"[2001:db8::1]:4711"
+fn parse_forwarded_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split(']').next()?.parse().ok();
+    }
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    node.rsplit_once(':')?.0.parse().ok()
+}
+
+// 发往上游的转发头：X-Forwarded-For 只有直连方是受信代理时才保留原有链路，
+// 链路和计算客户端 IP 时一样取自配置的转发头
+pub fn forwarding_headers(
+    client_ip: Option<IpAddr>,
+    peer: Option<IpAddr>,
+    headers: &HeaderMap<'_>,
+    trusted_proxies: &[Cidr],
+    forwarded_header: ForwardedHeader,
+) -> Vec<(String, String)> {
+    let mut forwarded = Vec::new();
+    let Some(peer) = peer else {
+        return forwarded;
+    };
+
+    let mut chain: Vec<String> = Vec::new();
+    if is_trusted(&peer, trusted_proxies) {
+        chain.extend(
+            forwarded_chain(headers, forwarded_header)
+                .iter()
+                .map(IpAddr::to_string),
+        );
+    }
+    chain.push(peer.to_string());
+
+    let proto = headers
+        .get_one("X-Forwarded-Proto")
+        .filter(|_| is_trusted(&peer, trusted_proxies))
+        .unwrap_or("http");
+
+    forwarded.push(("X-Forwarded-For".to_string(), chain.join(", ")));
+    forwarded.push(("X-Forwarded-Proto".to_string(), proto.to_string()));
+    if let Some(host) = headers.get_one("Host") {
+        forwarded.push(("X-Forwarded-Host".to_string(), host.to_string()));
+    }
+    if let Some(client_ip) = client_ip {
+        forwarded.push(("X-Real-IP".to_string(), client_ip.to_string()));
+    }
+    forwarded
+}
+
+// 转发头都由网关重新生成，客户端传入的同名头不直接透传
+pub fn is_forwarding_header(name: &str) -> bool {
+    [
+        "Forwarded",
+        "X-Forwarded-For",
+        "X-Forwarded-Proto",
+        "X-Forwarded-Host",
+        "X-Real-IP",
+    ]
+    .iter()
+    .any(|h| h.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::http::Header;
+
+    const XFF: ForwardedHeader = ForwardedHeader::XForwardedFor;
+
+    fn cidrs(list: &[&str]) -> Vec<Cidr> {
+        list.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn headers(list: &[(&'static str, &'static str)]) -> HeaderMap<'static> {
+        let mut map = HeaderMap::new();
+        for (name, value) in list {
+            map.add(Header::new(*name, *value));
+        }
+        map
+    }
+
+    #[test]
+    fn test_cidr_contains() {
+        let v4: Cidr = "10.0.0.0/8".parse().unwrap();
+        assert!(v4.contains(&ip("10.1.2.3")));
+        assert!(!v4.contains(&ip("11.0.0.1")));
+        assert!(v4.contains(&ip("::ffff:10.1.2.3")));
+
+        let v6: Cidr = "2001:db8::/32".parse().unwrap();
+        assert!(v6.contains(&ip("2001:db8::1")));
+        assert!(!v6.contains(&ip("2001:db9::1")));
+
+        let single: Cidr = "192.168.1.1".parse().unwrap();
+        assert!(single.contains(&ip("192.168.1.1")));
+        assert!(!single.contains(&ip("192.168.1.2")));
+
+        assert!("not-an-ip".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_permits() {
+        let open = AccessConfig::default();
+        assert!(open.permits(Some(ip("1.2.3.4"))));
+        assert!(open.permits(None));
+
+        let config = AccessConfig {
+            allow: cidrs(&["10.0.0.0/8", "2001:db8::/32"]),
+            deny: cidrs(&["10.0.0.5"]),
+        };
+        assert!(config.permits(Some(ip("10.0.0.1"))));
+        assert!(config.permits(Some(ip("2001:db8::42"))));
+        assert!(!config.permits(Some(ip("10.0.0.5"))));
+        assert!(!config.permits(Some(ip("192.168.0.1"))));
+        assert!(!config.permits(None));
+    }
+
+    #[test]
+    fn test_resolve_client_ip_ignores_untrusted_peer_headers() {
+        let trusted = cidrs(&["127.0.0.1"]);
+        let h = headers(&[("X-Forwarded-For", "6.6.6.6")]);
+        assert_eq!(
+            resolve_client_ip(Some(ip("203.0.113.9")), &h, &trusted, XFF),
+            Some(ip("203.0.113.9"))
+        );
+    }
+
+    #[test]
+    fn test_resolve_client_ip_through_trusted_proxies() {
+        let trusted = cidrs(&["127.0.0.1", "10.0.0.0/8"]);
+        // 客户端伪造了最左边的地址，受信代理追加了真实地址
+        let h = headers(&[("X-Forwarded-For", "6.6.6.6, 198.51.100.7, 10.0.0.2")]);
+        assert_eq!(
+            resolve_client_ip(Some(ip("127.0.0.1")), &h, &trusted, XFF),
+            Some(ip("198.51.100.7"))
+        );
+
+        let h = headers(&[(
+            "Forwarded",
+            "for=\"[2001:db8::1]:4711\";proto=https, for=10.0.0.2",
+        )]);
+        assert_eq!(
+            resolve_client_ip(
+                Some(ip("127.0.0.1")),
+                &h,
+                &trusted,
+                ForwardedHeader::Forwarded
+            ),
+            Some(ip("2001:db8::1"))
+        );
+    }
+
+    #[test]
+    fn test_only_configured_forwarded_header_is_read() {
+        let trusted = cidrs(&["127.0.0.1"]);
+        // 前置代理只追加 X-Forwarded-For，Forwarded 是客户端自己带的
+        let h = headers(&[
+            ("Forwarded", "for=10.0.0.1"),
+            ("X-Forwarded-For", "198.51.100.7"),
+        ]);
+        let peer = Some(ip("127.0.0.1"));
+        assert_eq!(
+            resolve_client_ip(peer, &h, &trusted, XFF),
+            Some(ip("198.51.100.7"))
+        );
+        assert_eq!(
+            resolve_client_ip(peer, &h, &trusted, ForwardedHeader::Forwarded),
+            Some(ip("10.0.0.1"))
+        );
+        // 上游收到的链路和客户端 IP 来自同一个头
+        let forwarded = forwarding_headers(Some(ip("198.51.100.7")), peer, &h, &trusted, XFF);
+        assert!(forwarded.contains(&(
+            "X-Forwarded-For".to_string(),
+            "198.51.100.7, 127.0.0.1".to_string()
+        )));
+        let forwarded = forwarding_headers(
+            Some(ip("10.0.0.1")),
+            peer,
+            &h,
+            &trusted,
+            ForwardedHeader::Forwarded,
+        );
+        assert!(forwarded.contains(&(
+            "X-Forwarded-For".to_string(),
+            "10.0.0.1, 127.0.0.1".to_string()
+        )));
+    }
+
+    #[test]
+    fn test_forwarding_headers() {
+        let trusted = cidrs(&["127.0.0.1"]);
+        let h = headers(&[
+            ("X-Forwarded-For", "198.51.100.7"),
+            ("Host", "api.example.com"),
+        ]);
+
+        let trusted_peer = forwarding_headers(
+            Some(ip("198.51.100.7")),
+            Some(ip("127.0.0.1")),
+            &h,
+            &trusted,
+            XFF,
+        );
+        assert!(trusted_peer.contains(&(
+            "X-Forwarded-For".to_string(),
+            "198.51.100.7, 127.0.0.1".to_string()
+        )));
+        assert!(trusted_peer.contains(&("X-Real-IP".to_string(), "198.51.100.7".to_string())));
+
+        let untrusted_peer = forwarding_headers(
+            Some(ip("203.0.113.9")),
+            Some(ip("203.0.113.9")),
+            &h,
+            &trusted,
+            XFF,
+        );
+        assert!(
+            untrusted_peer.contains(&("X-Forwarded-For".to_string(), "203.0.113.9".to_string()))
+        );
+        assert!(untrusted_peer.contains(&(
+            "X-Forwarded-Host".to_string(),
+            "api.example.com".to_string()
+        )));
+    }
+}
@@ -0,0 +1,52 @@
+use crate::secret;
+use crate::AppConfig;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use serde::Deserialize;
+use std::sync::Arc;
+
+// 管理接口配置
+#[derive(Debug, Default, Deserialize)]
+pub struct AdminConfig {
+    #[serde(default)]
+    pub token: Option<String>, // 调用方需在 X-Admin-Token 中携带；"env:NAME" 表示从环境变量读取
+}
+
+impl AdminConfig {
+    pub fn resolve_secrets(&mut self) -> Result<(), String> {
+        self.token.as_mut().map_or(Ok(()), secret::resolve)
+    }
+}
+
+// 逐字节比较全部内容，耗时与不匹配的位置无关
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// 管理接口的请求守卫：配置了 token 时校验 X-Admin-Token，否则只允许本机访问
+pub struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let token = req
+            .rocket()
+            .state::<Arc<AppConfig>>()
+            .and_then(|config| config.admin.token.as_deref());
+
+        match token {
+            Some(token) => match req.headers().get_one("X-Admin-Token") {
+                Some(given) if constant_time_eq(given.as_bytes(), token.as_bytes()) => {
+                    Outcome::Success(Admin)
+                }
+                _ => Outcome::Error((Status::Unauthorized, ())),
+            },
+            None => match req.remote() {
+                Some(addr) if addr.ip().is_loopback() => Outcome::Success(Admin),
+                _ => Outcome::Error((Status::Forbidden, ())),
+            },
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use crate::error::GatewayError;
+use crate::metrics::{metric_key, Metrics};
+use crate::proxy::UpstreamRequest;
+use crate::transform::{insert_path, TemplateContext};
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+// 聚合路由：并发调用多个上游，把 JSON 响应合并成一个文档
+#[derive(Debug, Deserialize)]
+pub struct AggregateConfig {
+    pub calls: Vec<AggregateCall>,
+    #[serde(default)]
+    pub on_failure: FailurePolicy,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AggregateCall {
+    pub key: String, // 结果放在合并文档中的字段路径（用 . 分隔）
+    pub url: String, // 支持模板，例如 "http://users/api/users/${jwt.sub}"
+    #[serde(default = "default_method")]
+    pub method: String,
+    #[serde(default)]
+    pub timeout: Option<u64>, // 单个调用的超时（秒），不配置则使用路由的 timeout
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+// 部分调用失败时的处理方式
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailurePolicy {
+    #[default]
+    Fail, // 整个请求返回 502
+    Omit, // 合并结果中去掉该字段
+    Null, // 该字段置为 null
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CallError {
+    Timeout,
+    Status(u16),
+    InvalidJson,
+    Failed,
+}
+
+impl CallError {
+    // 用作指标标签
+    fn label(&self) -> String {
+        match self {
+            CallError::Timeout => "timeout".to_string(),
+            CallError::Status(status) => status.to_string(),
+            CallError::InvalidJson => "invalid_json".to_string(),
+            CallError::Failed => "error".to_string(),
+        }
+    }
+}
+
+async fn fetch(
+    client: &reqwest::Client,
+    request: &UpstreamRequest,
+    url: &str,
+    timeout: Duration,
+) -> Result<Value, CallError> {
+    let response = request
+        .build(client, url, timeout)
+        .send()
+        .await
+        .map_err(|e| {
+            if e.is_timeout() {
+                CallError::Timeout
+            } else {
+                log::debug!("Aggregate call to {} failed: {}", url, e);
+                CallError::Failed
+            }
+        })?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(CallError::Status(status.as_u16()));
+    }
+    let body = response.bytes().await.map_err(|e| {
+        if e.is_timeout() {
+            CallError::Timeout
+        } else {
+            CallError::Failed
+        }
+    })?;
+    serde_json::from_slice(&body).map_err(|_| CallError::InvalidJson)
+}
+
+// 按配置的字段合并各调用的结果；policy 为 fail 时遇到第一个失败就返回错误
+pub fn merge(
+    results: Vec<(&str, Result<Value, CallError>)>,
+    policy: FailurePolicy,
+) -> Result<Value, CallError> {
+    let mut merged = Value::Object(Default::default());
+    for (key, result) in results {
+        let value = match (result, policy) {
+            (Ok(value), _) => value,
+            (Err(e), FailurePolicy::Fail) => return Err(e),
+            (Err(_), FailurePolicy::Omit) => continue,
+            (Err(_), FailurePolicy::Null) => Value::Null,
+        };
+        insert_path(&mut merged, key, value);
+    }
+    Ok(merged)
+}
+
+impl AggregateConfig {
+    // request 是已经过请求转换的客户端请求，各调用沿用它的头
+    pub async fn execute(
+        &self,
+        client: &reqwest::Client,
+        route: &str,
+        route_timeout: u64,
+        request: &UpstreamRequest,
+        ctx: &TemplateContext,
+        metrics: &Metrics,
+    ) -> Result<Vec<u8>, GatewayError> {
+        let mut base = request.clone();
+        // 需要解析 JSON，不让上游返回压缩内容
+        base.headers
+            .retain(|(name, _)| !name.eq_ignore_ascii_case("Accept-Encoding"));
+
+        let handles: Vec<_> = self
+            .calls
+            .iter()
+            .map(|call| {
+                let client = client.clone();
+                let url = ctx.render_url(&call.url);
+                let timeout = Duration::from_secs(call.timeout.unwrap_or(route_timeout));
+                let mut request = base.clone();
+                let method = reqwest::Method::from_bytes(call.method.to_uppercase().as_bytes());
+                tokio::spawn(async move {
+                    let start = Instant::now();
+                    let result = match method {
+                        Ok(method) => {
+                            if method == reqwest::Method::GET || method == reqwest::Method::HEAD {
+                                request.body.clear();
+                            }
+                            request.method = method;
+                            fetch(&client, &request, &url, timeout).await
+                        }
+                        Err(_) => Err(CallError::Failed),
+                    };
+                    (result, start.elapsed())
+                })
+            })
+            .collect();
+
+        let mut results = Vec::with_capacity(handles.len());
+        for (call, handle) in self.calls.iter().zip(handles) {
+            let (result, elapsed) = handle
+                .await
+                .unwrap_or((Err(CallError::Failed), Duration::ZERO));
+            let status = match &result {
+                Ok(_) => "ok".to_string(),
+                Err(e) => e.label(),
+            };
+            metrics.incr(metric_key(
+                "aggregate_calls_total",
+                &[("route", route), ("key", &call.key), ("status", &status)],
+            ));
+            metrics.observe(
+                metric_key("aggregate_latency", &[("route", route), ("key", &call.key)]),
+                elapsed,
+            );
+            results.push((call.key.as_str(), result));
+        }
+
+        let merged = merge(results, self.on_failure).map_err(|e| {
+            log::debug!("Aggregate route {} failed: {:?}", route, e);
+            match e {
+                CallError::Timeout => GatewayError::UpstreamTimeout,
+                other => {
+                    GatewayError::UpstreamError(format!("Aggregate call failed: {}", other.label()))
+                }
+            }
+        })?;
+        serde_json::to_vec(&merged).map_err(|_| GatewayError::Internal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn results() -> Vec<(&'static str, Result<Value, CallError>)> {
+        vec![
+            ("user", Ok(json!({"id": 1}))),
+            ("orders", Err(CallError::Timeout)),
+            ("profile.avatar", Ok(json!("a.png"))),
+        ]
+    }
+
+    #[test]
+    fn test_merge_fail() {
+        assert_eq!(
+            merge(results(), FailurePolicy::Fail),
+            Err(CallError::Timeout)
+        );
+        let ok = vec![("user", Ok(json!({"id": 1})))];
+        assert_eq!(
+            merge(ok, FailurePolicy::Fail),
+            Ok(json!({"user": {"id": 1}}))
+        );
+    }
+
+    #[test]
+    fn test_merge_omit_and_null() {
+        assert_eq!(
+            merge(results(), FailurePolicy::Omit),
+            Ok(json!({"user": {"id": 1}, "profile": {"avatar": "a.png"}}))
+        );
+        assert_eq!(
+            merge(results(), FailurePolicy::Null),
+            Ok(json!({"user": {"id": 1}, "orders": null, "profile": {"avatar": "a.png"}}))
+        );
+    }
+}
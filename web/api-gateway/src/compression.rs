@@ -0,0 +1,276 @@
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::Deserialize;
+use std::io::{Read, Write};
+
+// 响应压缩配置
+#[derive(Debug, Deserialize)]
+pub struct CompressionConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default = "default_min_size")]
+    pub min_size: usize, // 小于这个字节数的响应不压缩
+    #[serde(default = "default_gzip_level")]
+    pub gzip_level: u32, // 0-9
+    #[serde(default = "default_brotli_quality")]
+    pub brotli_quality: u32, // 0-11
+    #[serde(default = "default_content_types")]
+    pub content_types: Vec<String>, // 允许压缩的 Content-Type（前缀匹配）
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            enabled: default_enabled(),
+            min_size: default_min_size(),
+            gzip_level: default_gzip_level(),
+            brotli_quality: default_brotli_quality(),
+            content_types: default_content_types(),
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_min_size() -> usize {
+    1024
+}
+
+fn default_gzip_level() -> u32 {
+    6
+}
+
+fn default_brotli_quality() -> u32 {
+    5
+}
+
+fn default_content_types() -> Vec<String> {
+    [
+        "text/",
+        "application/json",
+        "application/javascript",
+        "application/xml",
+        "image/svg+xml",
+    ]
+    .iter()
+    .map(|t| t.to_string())
+    .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+}
+
+// 解析 Accept-Encoding，选出 q 值最高的可用编码；q 值相同时优先 br
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let mut best: Option<(Encoding, f32)> = None;
+
+    for item in accept_encoding.split(',') {
+        let mut parts = item.trim().split(';');
+        let name = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let q = parts
+            .find_map(|p| p.trim().strip_prefix("q="))
+            .and_then(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if q <= 0.0 {
+            continue;
+        }
+
+        let candidates: &[Encoding] = match name.as_str() {
+            "br" => &[Encoding::Brotli],
+            "gzip" | "x-gzip" => &[Encoding::Gzip],
+            "*" => &[Encoding::Brotli, Encoding::Gzip],
+            _ => &[],
+        };
+        for &encoding in candidates {
+            let better = match best {
+                None => true,
+                Some((current, best_q)) => {
+                    q > best_q
+                        || (q == best_q && encoding == Encoding::Brotli && current != encoding)
+                }
+            };
+            if better {
+                best = Some((encoding, q));
+            }
+        }
+    }
+
+    best.map(|(encoding, _)| encoding)
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+impl CompressionConfig {
+    fn compressible(&self, headers: &[(String, String)], body: &[u8]) -> bool {
+        if !self.enabled || body.len() < self.min_size {
+            return false;
+        }
+        // 上游已经压缩过的响应不再处理
+        let encoding = header(headers, "Content-Encoding").unwrap_or("identity");
+        if !encoding.eq_ignore_ascii_case("identity") {
+            return false;
+        }
+
+        let content_type = header(headers, "Content-Type")
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        self.content_types
+            .iter()
+            .any(|t| content_type.starts_with(&t.to_ascii_lowercase()))
+    }
+
+    // 按客户端的 Accept-Encoding 压缩响应体，并改写相关响应头
+    pub fn compress_response(
+        &self,
+        accept_encoding: Option<&str>,
+        headers: &mut Vec<(String, String)>,
+        body: &mut Vec<u8>,
+    ) {
+        if !self.compressible(headers, body) {
+            return;
+        }
+        let Some(encoding) = accept_encoding.and_then(negotiate) else {
+            return;
+        };
+
+        let compressed = match encoding {
+            Encoding::Gzip => gzip(body, self.gzip_level),
+            Encoding::Brotli => brotli_compress(body, self.brotli_quality),
+        };
+        let Ok(compressed) = compressed else {
+            return;
+        };
+
+        *body = compressed;
+        headers.retain(|(k, _)| !k.eq_ignore_ascii_case("Content-Encoding"));
+        headers.push(("Content-Encoding".to_string(), encoding.name().to_string()));
+        headers.push(("Vary".to_string(), "Accept-Encoding".to_string()));
+    }
+}
+
+fn gzip(body: &[u8], level: u32) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::new(level.min(9)));
+    encoder.write_all(body)?;
+    encoder.finish()
+}
+
+fn brotli_compress(body: &[u8], quality: u32) -> std::io::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    {
+        let mut writer = brotli::CompressorWriter::new(&mut output, 4096, quality.min(11), 22);
+        writer.write_all(body)?;
+    }
+    Ok(output)
+}
+
+// 解压 gzip 请求体；解压后的大小不能超过 limit，防止压缩炸弹
+pub fn gunzip(body: &[u8], limit: u64) -> Result<Vec<u8>, std::io::Error> {
+    let mut output = Vec::new();
+    GzDecoder::new(body)
+        .take(limit + 1)
+        .read_to_end(&mut output)?;
+    if output.len() as u64 > limit {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "decompressed body too large",
+        ));
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json_headers() -> Vec<(String, String)> {
+        vec![("Content-Type".to_string(), "application/json".to_string())]
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate("gzip, deflate, br"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=0.5, gzip;q=0.8"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=0, gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("*"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("deflate, identity"), None);
+    }
+
+    #[test]
+    fn test_gzip_round_trip() {
+        let config = CompressionConfig::default();
+        let original = br#"{"message":"hello"}"#.repeat(200);
+        let mut headers = json_headers();
+        let mut body = original.clone();
+
+        config.compress_response(Some("gzip"), &mut headers, &mut body);
+
+        assert!(body.len() < original.len());
+        assert_eq!(header(&headers, "Content-Encoding"), Some("gzip"));
+        assert_eq!(gunzip(&body, 1 << 20).unwrap(), original);
+    }
+
+    #[test]
+    fn test_brotli_round_trip() {
+        let config = CompressionConfig::default();
+        let original = b"<html>hello</html>".repeat(200);
+        let mut headers = vec![("Content-Type".to_string(), "text/html".to_string())];
+        let mut body = original.clone();
+
+        config.compress_response(Some("br"), &mut headers, &mut body);
+        assert_eq!(header(&headers, "Content-Encoding"), Some("br"));
+
+        let mut decoded = Vec::new();
+        brotli::Decompressor::new(body.as_slice(), 4096)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, original);
+    }
+
+    #[test]
+    fn test_skip_small_encoded_or_disallowed() {
+        let config = CompressionConfig::default();
+
+        let mut headers = json_headers();
+        let mut body = b"{}".to_vec();
+        config.compress_response(Some("gzip"), &mut headers, &mut body);
+        assert_eq!(body, b"{}");
+
+        let mut headers = json_headers();
+        headers.push(("Content-Encoding".to_string(), "gzip".to_string()));
+        let mut body = vec![b'a'; 4096];
+        config.compress_response(Some("gzip"), &mut headers, &mut body);
+        assert_eq!(body.len(), 4096);
+
+        let mut headers = vec![("Content-Type".to_string(), "image/png".to_string())];
+        let mut body = vec![b'a'; 4096];
+        config.compress_response(Some("gzip"), &mut headers, &mut body);
+        assert_eq!(body.len(), 4096);
+    }
+
+    #[test]
+    fn test_gunzip_limit() {
+        let body = gzip(&vec![0u8; 10_000], 6).unwrap();
+        assert!(gunzip(&body, 1_000).is_err());
+        assert_eq!(gunzip(&body, 10_000).unwrap().len(), 10_000);
+    }
+}
@@ -0,0 +1,329 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+// 路由级并发限制（舱壁隔离）：超过上限的请求排队，队列满或等待超时直接拒绝
+#[derive(Debug, Deserialize)]
+pub struct ConcurrencyConfig {
+    pub max_concurrent: usize, // 固定上限；开启自适应时为初始上限
+    #[serde(default)]
+    pub queue_size: usize, // 排队上限，0 表示不排队
+    #[serde(default = "default_queue_timeout")]
+    pub queue_timeout_ms: u64,
+    #[serde(default)]
+    pub adaptive: Option<AdaptiveConfig>,
+    #[serde(skip)]
+    state: Mutex<LimiterState>,
+    #[serde(skip)]
+    notify: Notify,
+}
+
+fn default_queue_timeout() -> u64 {
+    1000
+}
+
+// 根据观测到的延迟自动调整上限
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "snake_case")]
+pub enum AdaptiveConfig {
+    // 延迟正常时每次加 1，超过阈值或失败时按比例缩小
+    Aimd {
+        #[serde(default = "default_min_limit")]
+        min_limit: usize,
+        max_limit: usize,
+        latency_threshold_ms: u64,
+        #[serde(default = "default_backoff_ratio")]
+        backoff_ratio: f64,
+    },
+    // 比较长期平均延迟和本次延迟，延迟升高时按比例收缩
+    Gradient {
+        #[serde(default = "default_min_limit")]
+        min_limit: usize,
+        max_limit: usize,
+        #[serde(default = "default_smoothing")]
+        smoothing: f64, // 上限变化的平滑系数 0-1
+        #[serde(default = "default_tolerance")]
+        tolerance: f64, // 延迟升高到长期平均的多少倍以内不收缩
+    },
+}
+
+fn default_min_limit() -> usize {
+    1
+}
+
+fn default_backoff_ratio() -> f64 {
+    0.9
+}
+
+fn default_smoothing() -> f64 {
+    0.2
+}
+
+fn default_tolerance() -> f64 {
+    1.5
+}
+
+#[derive(Debug, Default)]
+struct LimiterState {
+    limit: Option<f64>, // 首次使用时从 max_concurrent 初始化
+    in_flight: usize,
+    queued: usize,
+    long_rtt_ms: Option<f64>, // gradient 的长期平均延迟
+}
+
+// 当前上限和占用情况，供 /health/details 展示
+#[derive(Debug, Serialize)]
+pub struct Usage {
+    pub limit: usize,
+    pub in_flight: usize,
+    pub queued: usize,
+}
+
+// 拒绝原因，作为指标标签
+#[derive(Debug, PartialEq)]
+pub enum Rejection {
+    QueueFull,
+    QueueTimeout,
+}
+
+impl Rejection {
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Rejection::QueueFull => "queue_full",
+            Rejection::QueueTimeout => "queue_timeout",
+        }
+    }
+}
+
+// 持有期间占用一个并发名额，drop 时归还并唤醒一个排队的请求
+pub struct Permit<'a> {
+    limiter: &'a ConcurrencyConfig,
+    start: Instant,
+    pub waited: Duration,
+}
+
+impl Permit<'_> {
+    // 请求结束后上报结果，自适应算法据此调整上限
+    pub fn finish(self, ok: bool) {
+        self.limiter.sample(self.start.elapsed(), ok);
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.limiter.state.lock().unwrap().in_flight -= 1;
+        self.limiter.notify.notify_one();
+    }
+}
+
+impl LimiterState {
+    fn current(&mut self, initial: usize) -> f64 {
+        *self.limit.get_or_insert(initial.max(1) as f64)
+    }
+}
+
+impl ConcurrencyConfig {
+    pub fn usage(&self) -> Usage {
+        let mut state = self.state.lock().unwrap();
+        Usage {
+            limit: state.current(self.max_concurrent) as usize,
+            in_flight: state.in_flight,
+            queued: state.queued,
+        }
+    }
+
+    fn try_take(&self, state: &mut LimiterState) -> bool {
+        if state.in_flight < state.current(self.max_concurrent) as usize {
+            state.in_flight += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub async fn acquire(&self) -> Result<Permit<'_>, Rejection> {
+        let start = Instant::now();
+        let permit = |start: Instant| Permit {
+            limiter: self,
+            start: Instant::now(),
+            waited: start.elapsed(),
+        };
+
+        {
+            let mut state = self.state.lock().unwrap();
+            if self.try_take(&mut state) {
+                return Ok(permit(start));
+            }
+            if state.queued >= self.queue_size {
+                return Err(Rejection::QueueFull);
+            }
+            state.queued += 1;
+        }
+
+        let wait = async {
+            loop {
+                // 先登记等待再检查，避免检查和等待之间的唤醒丢失
+                let notified = self.notify.notified();
+                tokio::pin!(notified);
+                notified.as_mut().enable();
+                {
+                    let mut state = self.state.lock().unwrap();
+                    if self.try_take(&mut state) {
+                        state.queued -= 1;
+                        return;
+                    }
+                }
+                notified.await;
+            }
+        };
+
+        match tokio::time::timeout(Duration::from_millis(self.queue_timeout_ms), wait).await {
+            Ok(()) => Ok(permit(start)),
+            Err(_) => {
+                let mut state = self.state.lock().unwrap();
+                state.queued -= 1;
+                // 超时前可能刚被唤醒，把名额让给下一个排队的请求
+                if state.in_flight < state.current(self.max_concurrent) as usize {
+                    self.notify.notify_one();
+                }
+                Err(Rejection::QueueTimeout)
+            }
+        }
+    }
+
+    fn sample(&self, rtt: Duration, ok: bool) {
+        let Some(adaptive) = &self.adaptive else {
+            return;
+        };
+        let rtt_ms = rtt.as_secs_f64() * 1000.0;
+        let mut state = self.state.lock().unwrap();
+        let current = state.current(self.max_concurrent);
+
+        let (next, min, max) = match adaptive {
+            AdaptiveConfig::Aimd {
+                min_limit,
+                max_limit,
+                latency_threshold_ms,
+                backoff_ratio,
+            } => {
+                let next = if !ok || rtt_ms > *latency_threshold_ms as f64 {
+                    current * backoff_ratio
+                } else {
+                    current + 1.0
+                };
+                (next, *min_limit, *max_limit)
+            }
+            AdaptiveConfig::Gradient {
+                min_limit,
+                max_limit,
+                smoothing,
+                tolerance,
+            } => {
+                let long = *state.long_rtt_ms.get_or_insert(rtt_ms);
+                state.long_rtt_ms = Some(long * 0.95 + rtt_ms * 0.05);
+                // 失败按最差情况处理
+                let gradient = if ok {
+                    (tolerance * long / rtt_ms.max(f64::EPSILON)).clamp(0.5, 1.0)
+                } else {
+                    0.5
+                };
+                // 留出 sqrt(limit) 的余量用于探测更高的上限
+                let target = current * gradient + current.sqrt();
+                let next = current * (1.0 - smoothing) + target * smoothing;
+                (next, *min_limit, *max_limit)
+            }
+        };
+
+        let min = min.max(1);
+        let next = next.clamp(min as f64, max.max(min) as f64);
+        state.limit = Some(next);
+        drop(state);
+        // 上限变大时排队的请求可以进入
+        if next as usize > current as usize {
+            self.notify.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    #[tokio::test]
+    async fn test_queue_and_rejection() {
+        let limiter: ConcurrencyConfig =
+            parse("max_concurrent = 1\nqueue_size = 1\nqueue_timeout_ms = 1000");
+        let first = limiter.acquire().await.unwrap();
+
+        let queued = limiter.acquire();
+        tokio::pin!(queued);
+        // 排队的请求还拿不到名额
+        assert!(
+            tokio::time::timeout(Duration::from_millis(20), queued.as_mut())
+                .await
+                .is_err()
+        );
+        // 队列已满
+        assert_eq!(limiter.acquire().await.err(), Some(Rejection::QueueFull));
+
+        drop(first);
+        let second = queued.await.unwrap();
+        assert!(second.waited >= Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    async fn test_queue_timeout() {
+        let limiter: ConcurrencyConfig =
+            parse("max_concurrent = 1\nqueue_size = 5\nqueue_timeout_ms = 30");
+        let _held = limiter.acquire().await.unwrap();
+        assert_eq!(limiter.acquire().await.err(), Some(Rejection::QueueTimeout));
+        assert_eq!(limiter.state.lock().unwrap().queued, 0);
+    }
+
+    #[test]
+    fn test_aimd() {
+        let limiter: ConcurrencyConfig = parse(
+            r#"
+            max_concurrent = 10
+            adaptive = { algorithm = "aimd", min_limit = 2, max_limit = 12, latency_threshold_ms = 100 }
+            "#,
+        );
+        limiter.sample(Duration::from_millis(10), true);
+        limiter.sample(Duration::from_millis(10), true);
+        limiter.sample(Duration::from_millis(10), true);
+        assert_eq!(limiter.usage().limit, 12);
+
+        for _ in 0..50 {
+            limiter.sample(Duration::from_millis(500), true);
+        }
+        assert_eq!(limiter.usage().limit, 2);
+        limiter.sample(Duration::from_millis(10), false);
+        assert_eq!(limiter.usage().limit, 2);
+    }
+
+    #[test]
+    fn test_gradient_shrinks_when_latency_rises() {
+        let limiter: ConcurrencyConfig = parse(
+            r#"
+            max_concurrent = 20
+            adaptive = { algorithm = "gradient", min_limit = 1, max_limit = 100 }
+            "#,
+        );
+        for _ in 0..20 {
+            limiter.sample(Duration::from_millis(10), true);
+        }
+        let steady = limiter.usage().limit;
+        assert!(steady >= 20, "steady = {}", steady);
+
+        for _ in 0..20 {
+            limiter.sample(Duration::from_millis(200), true);
+        }
+        assert!(
+            limiter.usage().limit < steady,
+            "limit = {}",
+            limiter.usage().limit
+        );
+    }
+}
@@ -0,0 +1,446 @@
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+// 请求/响应体格式转换：客户端始终使用 JSON，上游使用 XML 或表单
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConvertConfig {
+    pub upstream: BodyFormat,
+    #[serde(default = "default_xml_root")]
+    pub xml_root: String, // 请求体转成 XML 时的根元素名
+}
+
+fn default_xml_root() -> String {
+    "request".to_string()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BodyFormat {
+    Xml,
+    Form,
+}
+
+impl BodyFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BodyFormat::Xml => "XML",
+            BodyFormat::Form => "form",
+        }
+    }
+
+    fn mime(&self) -> &'static str {
+        match self {
+            BodyFormat::Xml => "application/xml",
+            BodyFormat::Form => "application/x-www-form-urlencoded",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            BodyFormat::Xml => "application/xml; charset=utf-8",
+            BodyFormat::Form => "application/x-www-form-urlencoded",
+        }
+    }
+
+    // 按 Content-Type 判断格式，application/soap+xml 这类也算 XML
+    fn detect(content_type: &str) -> Option<Self> {
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        if mime == "application/xml" || mime == "text/xml" || mime.ends_with("+xml") {
+            Some(BodyFormat::Xml)
+        } else if mime == "application/x-www-form-urlencoded" {
+            Some(BodyFormat::Form)
+        } else {
+            None
+        }
+    }
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+fn set_header(headers: &mut Vec<(String, String)>, name: &str, value: &str) {
+    headers.retain(|(k, _)| !k.eq_ignore_ascii_case(name));
+    headers.push((name.to_string(), value.to_string()));
+}
+
+fn is_json(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    mime.eq_ignore_ascii_case("application/json") || mime.to_ascii_lowercase().ends_with("+json")
+}
+
+impl ConvertConfig {
+    // 客户端的 JSON 请求体转成上游格式；不是 JSON 的请求体原样转发
+    pub fn convert_request(
+        &self,
+        headers: &mut Vec<(String, String)>,
+        body: &mut Vec<u8>,
+    ) -> Result<(), String> {
+        set_header(headers, "Accept", self.upstream.mime());
+        // 响应要解析后转换，不让上游返回压缩内容
+        headers.retain(|(k, _)| !k.eq_ignore_ascii_case("Accept-Encoding"));
+        if body.is_empty() || header(headers, "Content-Type").is_some_and(|ct| !is_json(ct)) {
+            return Ok(());
+        }
+
+        let value: Value =
+            serde_json::from_slice(body).map_err(|_| "body is not valid JSON".to_string())?;
+        *body = match self.upstream {
+            BodyFormat::Xml => json_to_xml(&self.xml_root, &value)?,
+            BodyFormat::Form => json_to_form(&value)?,
+        }
+        .into_bytes();
+        set_header(headers, "Content-Type", self.upstream.content_type());
+        Ok(())
+    }
+
+    // 上游返回 XML 或表单时转成 JSON，其它内容不处理
+    pub fn convert_response(
+        &self,
+        headers: &mut Vec<(String, String)>,
+        body: &mut Vec<u8>,
+    ) -> Result<(), (BodyFormat, String)> {
+        let Some(format) = header(headers, "Content-Type").and_then(BodyFormat::detect) else {
+            return Ok(());
+        };
+        // 上游仍然返回了压缩内容时原样转发，不当作格式错误
+        let encoded = header(headers, "Content-Encoding")
+            .is_some_and(|e| !e.trim().eq_ignore_ascii_case("identity"));
+        if body.is_empty() || encoded {
+            return Ok(());
+        }
+
+        let value = match format {
+            BodyFormat::Xml => xml_to_json(body),
+            BodyFormat::Form => Ok(form_to_json(body)),
+        }
+        .map_err(|e| (format, e))?;
+        *body = serde_json::to_vec(&value).map_err(|e| (format, e.to_string()))?;
+        set_header(headers, "Content-Type", "application/json");
+        Ok(())
+    }
+}
+
+// JSON -> XML：对象的键作为子元素，"@" 开头的键作为属性，"#text" 作为文本，数组展开为同名元素
+pub fn json_to_xml(root: &str, value: &Value) -> Result<String, String> {
+    if !value.is_object() {
+        return Err("top-level value must be a JSON object".to_string());
+    }
+    let mut out = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    write_element(&mut out, root, value)?;
+    Ok(out)
+}
+
+fn valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+fn scalar_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Null => Some(String::new()),
+        _ => None,
+    }
+}
+
+fn write_element(out: &mut String, name: &str, value: &Value) -> Result<(), String> {
+    if !valid_name(name) {
+        return Err(format!("\"{}\" is not a valid XML element name", name));
+    }
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                write_element(out, name, item)?;
+            }
+        }
+        Value::Object(fields) => {
+            out.push('<');
+            out.push_str(name);
+            for (key, value) in fields {
+                if let Some(attr) = key.strip_prefix('@') {
+                    if !valid_name(attr) {
+                        return Err(format!("\"{}\" is not a valid XML attribute name", attr));
+                    }
+                    let text = scalar_text(value)
+                        .ok_or_else(|| format!("attribute \"{}\" must be a scalar", attr))?;
+                    out.push_str(&format!(
+                        " {}=\"{}\"",
+                        attr,
+                        quick_xml::escape::escape(&text)
+                    ));
+                }
+            }
+            out.push('>');
+            for (key, value) in fields {
+                if key == "#text" {
+                    let text = scalar_text(value)
+                        .ok_or_else(|| "\"#text\" must be a scalar".to_string())?;
+                    out.push_str(&quick_xml::escape::escape(&text));
+                } else if !key.starts_with('@') {
+                    write_element(out, key, value)?;
+                }
+            }
+            out.push_str(&format!("</{}>", name));
+        }
+        Value::Null => out.push_str(&format!("<{}/>", name)),
+        scalar => {
+            let text = scalar_text(scalar).unwrap_or_default();
+            out.push_str(&format!(
+                "<{}>{}</{}>",
+                name,
+                quick_xml::escape::escape(&text),
+                name
+            ));
+        }
+    }
+    Ok(())
+}
+
+// 解析中的元素：属性和子元素放在 fields，文本单独累积
+struct Node {
+    fields: Map<String, Value>,
+    text: String,
+}
+
+impl Node {
+    fn start(element: &BytesStart) -> Result<Self, String> {
+        let mut fields = Map::new();
+        for attr in element.attributes() {
+            let attr = attr.map_err(|e| e.to_string())?;
+            let key = String::from_utf8_lossy(attr.key.as_ref()).to_string();
+            let value = attr.unescape_value().map_err(|e| e.to_string())?;
+            fields.insert(format!("@{}", key), Value::String(value.to_string()));
+        }
+        Ok(Node {
+            fields,
+            text: String::new(),
+        })
+    }
+
+    // 只有文本的元素转成字符串，否则转成对象
+    fn into_value(mut self) -> Value {
+        if self.fields.is_empty() {
+            return Value::String(self.text);
+        }
+        if !self.text.is_empty() {
+            self.fields
+                .insert("#text".to_string(), Value::String(self.text));
+        }
+        Value::Object(self.fields)
+    }
+}
+
+// 同名子元素合并为数组
+fn insert_child(fields: &mut Map<String, Value>, name: String, value: Value) {
+    match fields.get_mut(&name) {
+        Some(Value::Array(items)) => items.push(value),
+        Some(existing) => {
+            let first = existing.take();
+            *existing = Value::Array(vec![first, value]);
+        }
+        None => {
+            fields.insert(name, value);
+        }
+    }
+}
+
+// XML -> JSON：json_to_xml 的逆过程，根元素本身不出现在结果里；文本一律保留为字符串
+pub fn xml_to_json(xml: &[u8]) -> Result<Value, String> {
+    let xml = std::str::from_utf8(xml).map_err(|_| "body is not valid UTF-8".to_string())?;
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut stack: Vec<(String, Node)> = Vec::new();
+    let mut root = None;
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("invalid XML at position {}: {}", reader.error_position(), e))?;
+        let finished = match event {
+            Event::Start(element) => {
+                let name = String::from_utf8_lossy(element.name().as_ref()).to_string();
+                stack.push((name, Node::start(&element)?));
+                None
+            }
+            Event::Empty(element) => {
+                let name = String::from_utf8_lossy(element.name().as_ref()).to_string();
+                Some((name, Node::start(&element)?))
+            }
+            Event::End(_) => stack.pop(),
+            Event::Text(text) => {
+                let text = text.unescape().map_err(|e| e.to_string())?;
+                match stack.last_mut() {
+                    Some((_, node)) => node.text.push_str(&text),
+                    None => return Err("text outside of the root element".to_string()),
+                }
+                None
+            }
+            Event::CData(data) => {
+                if let Some((_, node)) = stack.last_mut() {
+                    node.text.push_str(&String::from_utf8_lossy(&data));
+                }
+                None
+            }
+            Event::Eof => break,
+            _ => None, // 声明、注释、处理指令
+        };
+
+        if let Some((name, node)) = finished {
+            let value = node.into_value();
+            match stack.last_mut() {
+                Some((_, parent)) => insert_child(&mut parent.fields, name, value),
+                None if root.is_none() => root = Some(value),
+                None => return Err("multiple root elements".to_string()),
+            }
+        }
+    }
+
+    if !stack.is_empty() {
+        return Err("unexpected end of document".to_string());
+    }
+    root.ok_or_else(|| "document has no root element".to_string())
+}
+
+// JSON -> 表单：只支持一层对象，数组展开为重复的键
+pub fn json_to_form(value: &Value) -> Result<String, String> {
+    let fields = value
+        .as_object()
+        .ok_or_else(|| "top-level value must be a JSON object".to_string())?;
+    let mut form = form_urlencoded::Serializer::new(String::new());
+    for (key, value) in fields {
+        let values = match value {
+            Value::Array(items) => items.iter().collect(),
+            other => vec![other],
+        };
+        for value in values {
+            let text = scalar_text(value)
+                .ok_or_else(|| format!("field \"{}\" is nested and cannot be form-encoded", key))?;
+            form.append_pair(key, &text);
+        }
+    }
+    Ok(form.finish())
+}
+
+// 表单 -> JSON：值都是字符串，重复的键合并为数组
+pub fn form_to_json(body: &[u8]) -> Value {
+    let mut fields = Map::new();
+    for (key, value) in form_urlencoded::parse(body) {
+        insert_child(
+            &mut fields,
+            key.into_owned(),
+            Value::String(value.into_owned()),
+        );
+    }
+    Value::Object(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_json_xml_round_trip() {
+        let value = json!({
+            "id": 7,
+            "name": "A & B",
+            "tags": ["x", "y"],
+            "owner": { "@type": "user", "#text": "alice" },
+            "note": null,
+        });
+        let xml = json_to_xml("order", &value).unwrap();
+        assert!(xml.contains("<name>A &amp; B</name>"));
+        assert!(xml.contains("<tags>x</tags><tags>y</tags>"));
+        assert!(xml.contains(r#"<owner type="user">alice</owner>"#));
+        assert!(xml.contains("<note/>"));
+
+        // 转回来时数字变成字符串
+        assert_eq!(
+            xml_to_json(xml.as_bytes()).unwrap(),
+            json!({
+                "id": "7",
+                "name": "A & B",
+                "tags": ["x", "y"],
+                "owner": { "@type": "user", "#text": "alice" },
+                "note": "",
+            })
+        );
+    }
+
+    #[test]
+    fn test_xml_errors() {
+        assert!(json_to_xml("r", &json!(["a"])).is_err());
+        assert!(json_to_xml("r", &json!({ "bad name": 1 })).is_err());
+        assert!(xml_to_json(b"<a><b></a>").is_err());
+        assert!(xml_to_json(b"<a>").is_err());
+        assert!(xml_to_json(b"").is_err());
+        assert!(xml_to_json(b"<a/><b/>").is_err());
+    }
+
+    #[test]
+    fn test_form() {
+        let form = json_to_form(&json!({ "q": "a b&c", "n": 1, "ids": [1, 2] })).unwrap();
+        assert_eq!(form, "ids=1&ids=2&n=1&q=a+b%26c");
+        assert_eq!(
+            form_to_json(form.as_bytes()),
+            json!({ "ids": ["1", "2"], "n": "1", "q": "a b&c" })
+        );
+        assert!(json_to_form(&json!({ "nested": { "a": 1 } })).is_err());
+    }
+
+    #[test]
+    fn test_headers_rewritten() {
+        let config = ConvertConfig {
+            upstream: BodyFormat::Xml,
+            xml_root: "request".to_string(),
+        };
+        let mut headers = vec![
+            ("content-type".to_string(), "application/json".to_string()),
+            ("Accept-Encoding".to_string(), "gzip, br".to_string()),
+        ];
+        let mut body = br#"{"a":1}"#.to_vec();
+        config.convert_request(&mut headers, &mut body).unwrap();
+        assert_eq!(header(&headers, "Accept-Encoding"), None);
+        assert_eq!(
+            header(&headers, "Content-Type"),
+            Some("application/xml; charset=utf-8")
+        );
+        assert_eq!(header(&headers, "Accept"), Some("application/xml"));
+        assert!(String::from_utf8(body)
+            .unwrap()
+            .ends_with("<request><a>1</a></request>"));
+
+        let mut headers = vec![("Content-Type".to_string(), "text/xml".to_string())];
+        let mut body = b"<response><ok>true</ok></response>".to_vec();
+        config.convert_response(&mut headers, &mut body).unwrap();
+        assert_eq!(header(&headers, "Content-Type"), Some("application/json"));
+        assert_eq!(body, br#"{"ok":"true"}"#);
+
+        let mut body = b"<response>".to_vec();
+        let mut headers = vec![("Content-Type".to_string(), "application/xml".to_string())];
+        assert!(config.convert_response(&mut headers, &mut body).is_err());
+
+        let mut body = vec![0x1f, 0x8b, 0x08];
+        let mut headers = vec![
+            ("Content-Type".to_string(), "application/xml".to_string()),
+            ("Content-Encoding".to_string(), "gzip".to_string()),
+        ];
+        config.convert_response(&mut headers, &mut body).unwrap();
+        assert_eq!(header(&headers, "Content-Type"), Some("application/xml"));
+        assert_eq!(body, vec![0x1f, 0x8b, 0x08]);
+    }
+}
@@ -0,0 +1,215 @@
+use crate::versioning::find_versioned_route_by_path;
+use crate::AppConfig;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Method, Status};
+use rocket::{options, Request, Response, State};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+// CORS 策略：可以全局配置，也可以在单个路由上覆盖
+#[derive(Debug, Default, Deserialize)]
+pub struct CorsConfig {
+    #[serde(default)]
+    pub allowed_origins: Vec<String>, // 精确匹配，或带通配符，例如 "https://*.example.com"、"*"
+    #[serde(default = "default_allowed_methods")]
+    pub allowed_methods: Vec<String>,
+    #[serde(default)]
+    pub allowed_headers: Vec<String>, // 为空时回显预检请求里的 Access-Control-Request-Headers
+    #[serde(default)]
+    pub exposed_headers: Vec<String>,
+    #[serde(default)]
+    pub allow_credentials: bool,
+    #[serde(default)]
+    pub max_age: Option<u64>, // 预检结果缓存时间（秒）
+}
+
+fn default_allowed_methods() -> Vec<String> {
+    ["GET", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"]
+        .iter()
+        .map(|m| m.to_string())
+        .collect()
+}
+
+impl CorsConfig {
+    // 任意来源加上凭据等于允许所有网站带 Cookie 读取响应，启动时拒绝
+    pub fn validate(&self) -> Result<(), String> {
+        if self.allow_credentials && self.allowed_origins.iter().any(|o| o == "*") {
+            return Err(
+                "allowed_origins = [\"*\"] cannot be combined with allow_credentials".to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.allowed_origins
+            .iter()
+            .any(|pattern| wildcard_match(pattern, origin))
+    }
+}
+
+// 简单通配符匹配：* 匹配任意长度的字符
+fn wildcard_match(pattern: &str, value: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == value;
+    }
+
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !value.starts_with(first) || value.len() < first.len() + last.len() {
+        return false;
+    }
+
+    let mut rest = &value[first.len()..value.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    value.ends_with(last)
+}
+
+// 找到请求路径适用的策略：代理路由优先使用路由自己的策略，
+// 路由查找与代理一致，/proxy/v2/users 同样使用 /users 的策略
+fn policy_for<'a>(config: &'a AppConfig, path: &str) -> Option<&'a CorsConfig> {
+    let route_policy = path
+        .strip_prefix("/proxy")
+        .filter(|p| p.starts_with('/'))
+        .and_then(|p| find_versioned_route_by_path(&config.routes, p))
+        .and_then(|route| route.cors.as_ref());
+
+    route_policy.or(config.cors.as_ref())
+}
+
+// CORS fairing：为允许的来源添加响应头
+pub struct Cors;
+
+#[rocket::async_trait]
+impl Fairing for Cors {
+    fn info(&self) -> Info {
+        Info {
+            name: "CORS",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let Some(config) = req.rocket().state::<Arc<AppConfig>>() else {
+            return;
+        };
+        let Some(policy) = policy_for(config, req.uri().path().as_str()) else {
+            return;
+        };
+        // 响应随 Origin 变化，无论是否允许都要告诉缓存
+        res.adjoin_raw_header("Vary", "Origin");
+        let Some(origin) = req.headers().get_one("Origin") else {
+            return;
+        };
+        if !policy.allows_origin(origin) {
+            return;
+        }
+
+        res.set_raw_header("Access-Control-Allow-Origin", origin.to_string());
+        if policy.allow_credentials {
+            res.set_raw_header("Access-Control-Allow-Credentials", "true");
+        }
+
+        let preflight = req.method() == Method::Options
+            && req.headers().contains("Access-Control-Request-Method");
+        if preflight {
+            res.set_raw_header(
+                "Access-Control-Allow-Methods",
+                policy.allowed_methods.join(", "),
+            );
+
+            let headers = if policy.allowed_headers.is_empty() {
+                req.headers()
+                    .get_one("Access-Control-Request-Headers")
+                    .map(str::to_string)
+            } else {
+                Some(policy.allowed_headers.join(", "))
+            };
+            if let Some(headers) = headers {
+                res.set_raw_header("Access-Control-Allow-Headers", headers);
+            }
+
+            if let Some(max_age) = policy.max_age {
+                res.set_raw_header("Access-Control-Max-Age", max_age.to_string());
+            }
+        } else if !policy.exposed_headers.is_empty() {
+            res.set_raw_header(
+                "Access-Control-Expose-Headers",
+                policy.exposed_headers.join(", "),
+            );
+        }
+    }
+}
+
+// 预检请求直接在网关应答，不转发到上游；响应头由 Cors fairing 添加
+#[options("/proxy/<path..>")]
+pub fn preflight(path: PathBuf, config: &State<Arc<AppConfig>>) -> Status {
+    let request_path = format!("/proxy/{}", path.display());
+    match policy_for(config, &request_path) {
+        Some(_) => Status::NoContent,
+        None => Status::NotFound,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*", "https://app.example.com"));
+        assert!(wildcard_match(
+            "https://app.example.com",
+            "https://app.example.com"
+        ));
+        assert!(wildcard_match(
+            "https://*.example.com",
+            "https://app.example.com"
+        ));
+        assert!(!wildcard_match(
+            "https://*.example.com",
+            "https://example.com"
+        ));
+        assert!(!wildcard_match(
+            "https://*.example.com",
+            "https://app.example.com.evil.io"
+        ));
+        assert!(wildcard_match(
+            "http://localhost:*",
+            "http://localhost:3000"
+        ));
+    }
+
+    #[test]
+    fn test_allows_origin() {
+        let policy = CorsConfig {
+            allowed_origins: vec![
+                "https://app.example.com".to_string(),
+                "https://*.internal.example.com".to_string(),
+            ],
+            ..Default::default()
+        };
+        assert!(policy.allows_origin("https://app.example.com"));
+        assert!(policy.allows_origin("https://admin.internal.example.com"));
+        assert!(!policy.allows_origin("https://other.com"));
+    }
+
+    #[test]
+    fn test_wildcard_with_credentials_rejected() {
+        let mut policy = CorsConfig {
+            allowed_origins: vec!["*".to_string()],
+            ..Default::default()
+        };
+        assert!(policy.validate().is_ok());
+        policy.allow_credentials = true;
+        assert!(policy.validate().is_err());
+        policy.allowed_origins = vec!["https://*.example.com".to_string()];
+        assert!(policy.validate().is_ok());
+    }
+}
@@ -0,0 +1,454 @@
+use crate::load_balancer::Upstreams;
+use crate::{AppConfig, UpstreamServer};
+use hickory_resolver::TokioAsyncResolver;
+use rocket::fairing::AdHoc;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+// 服务发现配置：路由通过 service 引用服务名，上游列表由提供方定期刷新
+#[derive(Debug, Clone, Deserialize)]
+pub struct DiscoveryConfig {
+    #[serde(default = "default_refresh_interval")]
+    pub refresh_interval: u64, // 刷新间隔（秒）
+    #[serde(default)]
+    pub file: Option<FileRegistryConfig>,
+    #[serde(default)]
+    pub dns: BTreeMap<String, DnsService>, // 服务名 = DNS 解析规则
+}
+
+fn default_refresh_interval() -> u64 {
+    10
+}
+
+// 注册表文件，按扩展名识别 JSON / TOML，文件修改后下一轮刷新生效
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileRegistryConfig {
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DnsService {
+    pub name: String, // 要解析的域名；SRV 记录例如 "_http._tcp.users.svc.cluster.local"
+    #[serde(default)]
+    pub record: RecordType,
+    #[serde(default = "default_port")]
+    pub port: u16, // A 记录使用的端口，SRV 记录自带端口
+    #[serde(default = "default_scheme")]
+    pub scheme: String,
+    #[serde(default)]
+    pub path: String, // 拼在地址后面的路径前缀，例如 "/api"
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordType {
+    #[default]
+    A,
+    Srv,
+}
+
+fn default_port() -> u16 {
+    80
+}
+
+fn default_scheme() -> String {
+    "http".to_string()
+}
+
+// 发现到的一个服务实例
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct Endpoint {
+    pub url: String,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+impl From<Endpoint> for UpstreamServer {
+    fn from(endpoint: Endpoint) -> Self {
+        UpstreamServer {
+            url: endpoint.url,
+            weight: endpoint.weight,
+            ..Default::default()
+        }
+    }
+}
+
+// 服务发现的扩展点，Consul、etcd 等实现这个 trait 即可接入
+#[rocket::async_trait]
+pub trait DiscoveryProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // Ok(None) 表示该提供方不负责这个服务，交给下一个提供方
+    async fn resolve(&self, service: &str) -> Result<Option<Vec<Endpoint>>, String>;
+}
+
+type Services = BTreeMap<String, Vec<Endpoint>>;
+
+#[derive(Debug, Default, Deserialize)]
+struct Registry {
+    #[serde(default)]
+    services: Services,
+}
+
+// 基于注册表文件的提供方：按修改时间缓存解析结果
+pub struct FileProvider {
+    path: PathBuf,
+    cache: Mutex<Option<(SystemTime, u64, Services)>>, // (修改时间, 文件大小, 解析结果)
+}
+
+impl FileProvider {
+    pub fn new(path: PathBuf) -> Self {
+        FileProvider {
+            path,
+            cache: Mutex::new(None),
+        }
+    }
+
+    fn load(&self) -> Result<Services, String> {
+        let metadata =
+            std::fs::metadata(&self.path).map_err(|e| format!("{}: {}", self.path.display(), e))?;
+        let modified = metadata.modified().map_err(|e| e.to_string())?;
+
+        let mut cache = self.cache.lock().unwrap();
+        if let Some((mtime, len, services)) = cache.as_ref() {
+            if *mtime == modified && *len == metadata.len() {
+                return Ok(services.clone());
+            }
+        }
+
+        let registry: Registry = config::Config::builder()
+            .add_source(config::File::from(self.path.as_path()))
+            .build()
+            .and_then(|c| c.try_deserialize())
+            .map_err(|e| format!("{}: {}", self.path.display(), e))?;
+        *cache = Some((modified, metadata.len(), registry.services.clone()));
+        Ok(registry.services)
+    }
+}
+
+#[rocket::async_trait]
+impl DiscoveryProvider for FileProvider {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    async fn resolve(&self, service: &str) -> Result<Option<Vec<Endpoint>>, String> {
+        Ok(self.load()?.remove(service))
+    }
+}
+
+// 基于 DNS A / SRV 记录的提供方
+pub struct DnsProvider {
+    resolver: TokioAsyncResolver,
+    services: BTreeMap<String, DnsService>,
+}
+
+impl DnsProvider {
+    pub fn new(services: BTreeMap<String, DnsService>) -> Self {
+        // 读不到系统配置时使用默认的公共 DNS
+        let resolver = TokioAsyncResolver::tokio_from_system_conf().unwrap_or_else(|e| {
+            log::warn!("Failed to read system DNS config, using defaults: {}", e);
+            TokioAsyncResolver::tokio(Default::default(), Default::default())
+        });
+        DnsProvider { resolver, services }
+    }
+}
+
+fn endpoint_url(service: &DnsService, host: &str, port: u16) -> String {
+    // IPv6 地址需要加方括号
+    let host = if host.contains(':') {
+        format!("[{}]", host)
+    } else {
+        host.to_string()
+    };
+    format!("{}://{}:{}{}", service.scheme, host, port, service.path)
+}
+
+#[rocket::async_trait]
+impl DiscoveryProvider for DnsProvider {
+    fn name(&self) -> &'static str {
+        "dns"
+    }
+
+    async fn resolve(&self, service: &str) -> Result<Option<Vec<Endpoint>>, String> {
+        let Some(config) = self.services.get(service) else {
+            return Ok(None);
+        };
+
+        let mut endpoints = match config.record {
+            RecordType::A => self
+                .resolver
+                .lookup_ip(config.name.as_str())
+                .await
+                .map_err(|e| e.to_string())?
+                .iter()
+                .map(|ip| Endpoint {
+                    url: endpoint_url(config, &ip.to_string(), config.port),
+                    weight: 1,
+                })
+                .collect::<Vec<_>>(),
+            RecordType::Srv => {
+                let lookup = self
+                    .resolver
+                    .srv_lookup(config.name.as_str())
+                    .await
+                    .map_err(|e| e.to_string())?;
+                // 只使用优先级最高（数值最小）的一组记录
+                let priority = lookup.iter().map(|srv| srv.priority()).min();
+                lookup
+                    .iter()
+                    .filter(|srv| Some(srv.priority()) == priority)
+                    .map(|srv| {
+                        let target = srv.target().to_utf8();
+                        Endpoint {
+                            url: endpoint_url(config, target.trim_end_matches('.'), srv.port()),
+                            weight: (srv.weight() as u32).max(1),
+                        }
+                    })
+                    .collect()
+            }
+        };
+        // 解析结果的顺序不固定，排序后避免无意义的替换
+        endpoints.sort_by(|a, b| a.url.cmp(&b.url));
+        Ok(Some(endpoints))
+    }
+}
+
+// 路由引用的服务必须有提供方负责：没有 [discovery] 时无从解析；
+// 只有 DNS 提供方时服务名必须配置在 dns 下，注册表文件的内容运行时才知道，不在这里检查
+pub fn check_service(config: Option<&DiscoveryConfig>, service: &str) -> Result<(), String> {
+    let Some(config) = config else {
+        return Err(format!(
+            "service {} requires [discovery] to be configured",
+            service
+        ));
+    };
+    if config.file.is_none() && !config.dns.contains_key(service) {
+        return Err(format!(
+            "service {} is not configured in discovery.dns",
+            service
+        ));
+    }
+    Ok(())
+}
+
+pub struct Discovery {
+    providers: Vec<Box<dyn DiscoveryProvider>>,
+}
+
+impl Discovery {
+    pub fn from_config(config: &DiscoveryConfig) -> Self {
+        let mut providers: Vec<Box<dyn DiscoveryProvider>> = Vec::new();
+        if let Some(file) = &config.file {
+            providers.push(Box::new(FileProvider::new(file.path.clone())));
+        }
+        if !config.dns.is_empty() {
+            providers.push(Box::new(DnsProvider::new(config.dns.clone())));
+        }
+        Discovery { providers }
+    }
+
+    // 按配置顺序询问提供方，第一个认识该服务的提供方的结果生效
+    pub async fn resolve(&self, service: &str) -> Result<Vec<Endpoint>, String> {
+        for provider in &self.providers {
+            match provider.resolve(service).await {
+                Ok(Some(endpoints)) => return Ok(endpoints),
+                Ok(None) => continue,
+                Err(e) => return Err(format!("{} provider: {}", provider.name(), e)),
+            }
+        }
+        Err("no provider knows this service".to_string())
+    }
+
+    // 刷新失败时保留上一次的上游列表
+    pub async fn refresh(&self, service: &str, upstreams: &Upstreams) {
+        match self.resolve(service).await {
+            Ok(endpoints) => {
+                let servers = endpoints.into_iter().map(UpstreamServer::from).collect();
+                if upstreams.replace(servers) {
+                    let urls: Vec<_> = upstreams
+                        .snapshot()
+                        .servers
+                        .iter()
+                        .map(|s| s.url.clone())
+                        .collect();
+                    log::info!("Service {} upstreams updated: {:?}", service, urls);
+                }
+            }
+            Err(e) => log::warn!("Failed to resolve service {}: {}", service, e),
+        }
+    }
+}
+
+// 启动后立即解析一次，之后按间隔刷新所有引用了服务名的路由
+pub fn fairing(config: Option<DiscoveryConfig>) -> AdHoc {
+    AdHoc::on_liftoff("Service Discovery", move |rocket| {
+        Box::pin(async move {
+            let Some(app) = rocket.state::<Arc<AppConfig>>() else {
+                return;
+            };
+            let targets: Vec<(String, Upstreams)> = app
+                .routes
+                .iter()
+                .filter_map(|route| Some((route.service.clone()?, route.upstreams.clone())))
+                .collect();
+            if targets.is_empty() {
+                return;
+            }
+            // 配置加载时已经检查过引用服务的路由都有 [discovery]
+            let Some(config) = config else {
+                return;
+            };
+
+            let discovery = Discovery::from_config(&config);
+            tokio::spawn(async move {
+                let mut interval =
+                    tokio::time::interval(Duration::from_secs(config.refresh_interval.max(1)));
+                loop {
+                    interval.tick().await;
+                    for (service, upstreams) in &targets {
+                        discovery.refresh(service, upstreams).await;
+                    }
+                }
+            });
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(extension: &str, content: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("registry-{}.{}", rand::random::<u64>(), extension));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn urls(upstreams: &Upstreams) -> Vec<String> {
+        upstreams
+            .snapshot()
+            .servers
+            .iter()
+            .map(|s| s.url.clone())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_file_provider_formats() {
+        let toml = registry(
+            "toml",
+            r#"
+            [[services.users]]
+            url = "http://10.0.0.1:8080"
+            weight = 3
+
+            [[services.users]]
+            url = "http://10.0.0.2:8080"
+            "#,
+        );
+        let json = registry(
+            "json",
+            r#"{"services": {"orders": [{"url": "http://10.0.1.1:9000"}]}}"#,
+        );
+
+        let provider = FileProvider::new(toml.clone());
+        let users = provider.resolve("users").await.unwrap().unwrap();
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[0].weight, 3);
+        assert_eq!(users[1].weight, 1);
+        assert_eq!(provider.resolve("orders").await.unwrap(), None);
+
+        let orders = FileProvider::new(json.clone())
+            .resolve("orders")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(orders[0].url, "http://10.0.1.1:9000");
+
+        std::fs::remove_file(toml).unwrap();
+        std::fs::remove_file(json).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_refresh_follows_file_changes() {
+        let path = registry(
+            "json",
+            r#"{"services": {"users": [{"url": "http://a:80"}]}}"#,
+        );
+        let discovery = Discovery {
+            providers: vec![Box::new(FileProvider::new(path.clone()))],
+        };
+        let upstreams = Upstreams::default();
+
+        discovery.refresh("users", &upstreams).await;
+        assert_eq!(urls(&upstreams), ["http://a:80"]);
+
+        std::fs::write(
+            &path,
+            r#"{"services": {"users": [{"url": "http://a:80"}, {"url": "http://b:80"}]}}"#,
+        )
+        .unwrap();
+        discovery.refresh("users", &upstreams).await;
+        assert_eq!(urls(&upstreams), ["http://a:80", "http://b:80"]);
+
+        // 文件损坏或服务消失时保留上一次的结果
+        std::fs::write(&path, "{ not json").unwrap();
+        discovery.refresh("users", &upstreams).await;
+        discovery.refresh("orders", &upstreams).await;
+        assert_eq!(urls(&upstreams), ["http://a:80", "http://b:80"]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_check_service() {
+        assert!(check_service(None, "users").is_err());
+
+        let mut config = DiscoveryConfig {
+            refresh_interval: 10,
+            file: None,
+            dns: BTreeMap::from([(
+                "users".to_string(),
+                DnsService {
+                    name: "users.svc".to_string(),
+                    record: RecordType::A,
+                    port: 80,
+                    scheme: "http".to_string(),
+                    path: String::new(),
+                },
+            )]),
+        };
+        assert!(check_service(Some(&config), "users").is_ok());
+        assert!(check_service(Some(&config), "orders").is_err());
+
+        config.file = Some(FileRegistryConfig {
+            path: PathBuf::from("registry.json"),
+        });
+        assert!(check_service(Some(&config), "orders").is_ok());
+    }
+
+    #[test]
+    fn test_endpoint_url() {
+        let service = DnsService {
+            name: "_http._tcp.users".to_string(),
+            record: RecordType::Srv,
+            port: 80,
+            scheme: "https".to_string(),
+            path: "/api".to_string(),
+        };
+        assert_eq!(
+            endpoint_url(&service, "users-0.svc", 8443),
+            "https://users-0.svc:8443/api"
+        );
+        assert_eq!(endpoint_url(&service, "::1", 80), "https://[::1]:80/api");
+    }
+}
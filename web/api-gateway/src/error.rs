@@ -0,0 +1,222 @@
+use crate::request::request_id;
+use rocket::http::{ContentType, Status};
+use rocket::response::{self, Responder, Response};
+use rocket::{catch, Request};
+use serde::Serialize;
+use std::io::Cursor;
+
+// 网关自身产生的错误，统一返回 JSON：{"code", "message", "request_id"}
+#[derive(Debug, PartialEq)]
+pub enum GatewayError {
+    RouteNotFound,
+    NoUpstream,
+    UpstreamTimeout,
+    UpstreamConnect,
+    UpstreamError(String), // 上游返回了无法处理的响应，或聚合调用失败
+    RateLimited,
+    QuotaExceeded,      // 租户当日配额已用完
+    ConcurrencyLimited, // 路由并发已满且排队失败
+    Unauthorized,
+    Forbidden,
+    InvalidRequest(String),
+    PayloadTooLarge,
+    MethodNotAllowed,
+    VersionRetired(String),    // 请求的 API 版本已过下线日期
+    UploadNotFound,            // 上传不存在、已完成或已过期
+    UploadOffsetMismatch(u64), // PATCH 的 Upload-Offset 与已接收的字节数不一致
+    ChecksumMismatch,          // 分块的 Upload-Checksum 校验失败
+    FaultInjected(u16),        // 故障注入的中止，状态码由策略决定
+    Internal,
+    Http(Status), // 其它没有专门分类的状态码，例如 Rocket 捕获的错误
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    code: &'a str,
+    message: String,
+    request_id: &'a str,
+}
+
+impl GatewayError {
+    pub fn status(&self) -> Status {
+        match self {
+            GatewayError::RouteNotFound => Status::NotFound,
+            GatewayError::NoUpstream => Status::ServiceUnavailable,
+            GatewayError::UpstreamTimeout => Status::GatewayTimeout,
+            GatewayError::UpstreamConnect => Status::BadGateway,
+            GatewayError::UpstreamError(_) => Status::BadGateway,
+            GatewayError::RateLimited => Status::TooManyRequests,
+            GatewayError::QuotaExceeded => Status::TooManyRequests,
+            GatewayError::ConcurrencyLimited => Status::ServiceUnavailable,
+            GatewayError::Unauthorized => Status::Unauthorized,
+            GatewayError::Forbidden => Status::Forbidden,
+            GatewayError::InvalidRequest(_) => Status::BadRequest,
+            GatewayError::PayloadTooLarge => Status::PayloadTooLarge,
+            GatewayError::MethodNotAllowed => Status::MethodNotAllowed,
+            GatewayError::VersionRetired(_) => Status::Gone,
+            GatewayError::UploadNotFound => Status::NotFound,
+            GatewayError::UploadOffsetMismatch(_) => Status::Conflict,
+            GatewayError::ChecksumMismatch => Status::new(460), // tus 约定的 Checksum Mismatch
+            GatewayError::FaultInjected(status) => Status::new(*status),
+            GatewayError::Internal => Status::InternalServerError,
+            GatewayError::Http(status) => *status,
+        }
+    }
+
+    // 机器可读的错误码，客户端应以它而不是 message 做判断
+    pub fn code(&self) -> &'static str {
+        match self {
+            GatewayError::RouteNotFound => "route_not_found",
+            GatewayError::NoUpstream => "no_upstream_available",
+            GatewayError::UpstreamTimeout => "upstream_timeout",
+            GatewayError::UpstreamConnect => "upstream_connect_error",
+            GatewayError::UpstreamError(_) => "upstream_error",
+            GatewayError::RateLimited => "rate_limited",
+            GatewayError::QuotaExceeded => "quota_exceeded",
+            GatewayError::ConcurrencyLimited => "concurrency_limit_exceeded",
+            GatewayError::Unauthorized => "unauthorized",
+            GatewayError::Forbidden => "forbidden",
+            GatewayError::InvalidRequest(_) => "invalid_request",
+            GatewayError::PayloadTooLarge => "payload_too_large",
+            GatewayError::MethodNotAllowed => "method_not_allowed",
+            GatewayError::VersionRetired(_) => "version_retired",
+            GatewayError::UploadNotFound => "upload_not_found",
+            GatewayError::UploadOffsetMismatch(_) => "upload_offset_mismatch",
+            GatewayError::ChecksumMismatch => "checksum_mismatch",
+            GatewayError::FaultInjected(_) => "fault_injected",
+            GatewayError::Internal => "internal_error",
+            GatewayError::Http(status) => match status.code {
+                401 => "unauthorized",
+                403 => "forbidden",
+                404 => "not_found",
+                503 => "service_unavailable",
+                s if s >= 500 => "internal_error",
+                _ => "http_error",
+            },
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            GatewayError::RouteNotFound => "No route matches this request".to_string(),
+            GatewayError::NoUpstream => "No upstream available for this route".to_string(),
+            GatewayError::UpstreamTimeout => "Upstream did not respond in time".to_string(),
+            GatewayError::UpstreamConnect => "Failed to connect to upstream".to_string(),
+            GatewayError::UpstreamError(detail) => detail.clone(),
+            GatewayError::RateLimited => "Too many requests".to_string(),
+            GatewayError::QuotaExceeded => "Daily request quota exceeded".to_string(),
+            GatewayError::ConcurrencyLimited => {
+                "Too many concurrent requests for this route".to_string()
+            }
+            GatewayError::Unauthorized => "Authentication required".to_string(),
+            GatewayError::Forbidden => "Access denied".to_string(),
+            GatewayError::InvalidRequest(detail) => detail.clone(),
+            GatewayError::PayloadTooLarge => "Request body too large".to_string(),
+            GatewayError::MethodNotAllowed => "Method not allowed".to_string(),
+            GatewayError::VersionRetired(version) => {
+                format!("API version {} has been retired", version)
+            }
+            GatewayError::UploadNotFound => "Upload not found or expired".to_string(),
+            GatewayError::UploadOffsetMismatch(offset) => {
+                format!("Upload-Offset does not match current offset {}", offset)
+            }
+            GatewayError::ChecksumMismatch => "Chunk checksum does not match".to_string(),
+            GatewayError::FaultInjected(_) => "Injected fault".to_string(),
+            GatewayError::Internal => "Internal gateway error".to_string(),
+            GatewayError::Http(status) => status.reason_lossy().to_string(),
+        }
+    }
+
+    // 请求上游失败时按原因区分超时和连接错误
+    pub fn from_upstream(error: &reqwest::Error) -> Self {
+        if error.is_timeout() {
+            GatewayError::UpstreamTimeout
+        } else if error.is_connect() {
+            GatewayError::UpstreamConnect
+        } else {
+            GatewayError::UpstreamError("Upstream request failed".to_string())
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for GatewayError {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let id = request_id(req);
+        let body = serde_json::to_vec(&ErrorBody {
+            code: self.code(),
+            message: self.message(),
+            request_id: id,
+        })
+        .map_err(|_| Status::InternalServerError)?;
+
+        Response::build()
+            .status(self.status())
+            .header(ContentType::JSON)
+            .raw_header("X-Request-Id", id.to_string())
+            .sized_body(body.len(), Cursor::new(body))
+            .ok()
+    }
+}
+
+// 替换 Rocket 默认的 HTML 错误页
+#[catch(404)]
+pub fn not_found() -> GatewayError {
+    GatewayError::Http(Status::NotFound)
+}
+
+#[catch(500)]
+pub fn internal_error() -> GatewayError {
+    GatewayError::Internal
+}
+
+#[catch(default)]
+pub fn default_catcher(status: Status, _: &Request) -> GatewayError {
+    GatewayError::Http(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_and_code_mapping() {
+        assert_eq!(
+            GatewayError::UpstreamTimeout.status(),
+            Status::GatewayTimeout
+        );
+        assert_eq!(GatewayError::UpstreamConnect.status(), Status::BadGateway);
+        assert_eq!(GatewayError::NoUpstream.code(), "no_upstream_available");
+        assert_eq!(
+            GatewayError::ConcurrencyLimited.status(),
+            Status::ServiceUnavailable
+        );
+        assert_eq!(GatewayError::FaultInjected(418).status(), Status::ImATeapot);
+        assert_eq!(GatewayError::ChecksumMismatch.status().code, 460);
+        assert_eq!(
+            GatewayError::UploadOffsetMismatch(6).status(),
+            Status::Conflict
+        );
+        assert_eq!(GatewayError::Http(Status::NotFound).code(), "not_found");
+        assert_eq!(
+            GatewayError::Http(Status::ServiceUnavailable).code(),
+            "service_unavailable"
+        );
+        assert_eq!(GatewayError::Http(Status::Conflict).code(), "http_error");
+    }
+
+    #[test]
+    fn test_json_body_carries_request_id() {
+        let rocket = rocket::build().register("/", rocket::catchers![not_found]);
+        let client = rocket::local::blocking::Client::untracked(rocket).unwrap();
+        let response = client
+            .get("/missing")
+            .header(rocket::http::Header::new("X-Request-Id", "req-7"))
+            .dispatch();
+
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        let body: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(body["code"], "not_found");
+        assert_eq!(body["request_id"], "req-7");
+    }
+}
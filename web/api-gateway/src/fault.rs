@@ -0,0 +1,359 @@
+use crate::admin::Admin;
+use crate::request::RequestInfo;
+use crate::AppConfig;
+use rand::Rng;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::{delete, get, put, State};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+// 路由级故障注入策略，用于混沌测试
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FaultPolicy {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub delay: Option<DelayFault>,
+    #[serde(default)]
+    pub abort: Option<AbortFault>,
+    #[serde(default)]
+    pub truncate: Option<TruncateFault>,
+    #[serde(default)]
+    pub match_header: Option<HeaderMatch>, // 只对带有指定请求头的请求生效
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DelayFault {
+    pub percentage: f64, // 0-100
+    #[serde(default)]
+    pub distribution: Distribution,
+    #[serde(default)]
+    pub delay_ms: u64, // fixed 的延迟；normal / exponential 的均值；uniform 的下限
+    #[serde(default)]
+    pub max_ms: u64, // uniform 的上限
+    #[serde(default)]
+    pub stddev_ms: u64, // normal 的标准差
+}
+
+// 延迟的分布
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Distribution {
+    #[default]
+    Fixed,
+    Uniform,
+    Normal,
+    Exponential,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AbortFault {
+    pub percentage: f64,
+    #[serde(default = "default_abort_status")]
+    pub status: u16,
+}
+
+fn default_abort_status() -> u16 {
+    503
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TruncateFault {
+    pub percentage: f64, // 响应没发完就关闭连接，客户端最多收到响应头
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HeaderMatch {
+    pub name: String,
+    #[serde(default)]
+    pub value: Option<String>, // 不配置时只要求请求头存在
+}
+
+// 本次请求要注入的故障
+#[derive(Debug, Default, PartialEq)]
+pub struct Injection {
+    pub delay: Option<Duration>,
+    pub action: Option<FaultAction>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FaultAction {
+    Abort(u16),
+    Truncate,
+}
+
+fn hit<R: Rng + ?Sized>(percentage: f64, rng: &mut R) -> bool {
+    percentage > 0.0 && rng.gen::<f64>() * 100.0 < percentage
+}
+
+impl DelayFault {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Duration {
+        let ms = match self.distribution {
+            Distribution::Fixed => self.delay_ms as f64,
+            Distribution::Uniform if self.max_ms > self.delay_ms => {
+                rng.gen_range(self.delay_ms..self.max_ms) as f64
+            }
+            Distribution::Uniform => self.delay_ms as f64,
+            Distribution::Normal => {
+                // Box-Muller 变换
+                let u1: f64 = 1.0 - rng.gen::<f64>();
+                let u2: f64 = rng.gen();
+                let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                self.delay_ms as f64 + z * self.stddev_ms as f64
+            }
+            Distribution::Exponential => {
+                let u: f64 = 1.0 - rng.gen::<f64>();
+                -(self.delay_ms as f64) * u.ln()
+            }
+        };
+        Duration::from_millis(ms.max(0.0) as u64)
+    }
+}
+
+impl FaultPolicy {
+    // 中止的状态码必须是合法的 HTTP 状态码，否则响应发不出去
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.abort {
+            Some(abort) if !(100..=599).contains(&abort.status) => Err(format!(
+                "fault abort status {} must be between 100 and 599",
+                abort.status
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn matches(&self, info: &RequestInfo) -> bool {
+        match &self.match_header {
+            None => true,
+            Some(m) => match (info.header(&m.name), &m.value) {
+                (Some(actual), Some(expected)) => actual == expected,
+                (Some(_), None) => true,
+                (None, _) => false,
+            },
+        }
+    }
+
+    // 顺序：先延迟，再决定中止或截断响应
+    pub fn decide<R: Rng + ?Sized>(&self, info: &RequestInfo, rng: &mut R) -> Injection {
+        if !self.enabled || !self.matches(info) {
+            return Injection::default();
+        }
+
+        let delay = self
+            .delay
+            .as_ref()
+            .filter(|d| hit(d.percentage, rng))
+            .map(|d| d.sample(rng));
+
+        let action = if self.abort.as_ref().is_some_and(|a| hit(a.percentage, rng)) {
+            self.abort.as_ref().map(|a| FaultAction::Abort(a.status))
+        } else if self
+            .truncate
+            .as_ref()
+            .is_some_and(|t| hit(t.percentage, rng))
+        {
+            Some(FaultAction::Truncate)
+        } else {
+            None
+        };
+
+        Injection { delay, action }
+    }
+}
+
+// 路由上的故障策略，可以通过管理接口在运行时替换
+#[derive(Debug, Default)]
+pub struct Faults(RwLock<Option<Arc<FaultPolicy>>>);
+
+impl<'de> Deserialize<'de> for Faults {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let policy = Option::<FaultPolicy>::deserialize(deserializer)?;
+        Ok(Faults(RwLock::new(policy.map(Arc::new))))
+    }
+}
+
+impl Faults {
+    pub fn current(&self) -> Option<Arc<FaultPolicy>> {
+        self.0.read().unwrap().clone()
+    }
+
+    fn set(&self, policy: Option<FaultPolicy>) {
+        *self.0.write().unwrap() = policy.map(Arc::new);
+    }
+}
+
+#[derive(Serialize)]
+pub struct RouteFaults {
+    route: String,
+    policy: Option<FaultPolicy>,
+}
+
+#[get("/admin/faults")]
+pub fn list_faults(_admin: Admin, config: &State<Arc<AppConfig>>) -> Json<Vec<RouteFaults>> {
+    Json(
+        config
+            .routes
+            .iter()
+            .map(|route| RouteFaults {
+                route: route.path.clone(),
+                policy: route.fault.current().map(|p| (*p).clone()),
+            })
+            .collect(),
+    )
+}
+
+// 替换路由的故障策略，例如 PUT /admin/faults?route=/api/v1/*
+#[put("/admin/faults?<route>", data = "<policy>")]
+pub fn set_fault(
+    _admin: Admin,
+    route: &str,
+    policy: Json<FaultPolicy>,
+    config: &State<Arc<AppConfig>>,
+) -> Result<Json<RouteFaults>, Status> {
+    let target = config
+        .routes
+        .iter()
+        .find(|r| r.path == route)
+        .ok_or(Status::NotFound)?;
+    let policy = policy.into_inner();
+    if let Err(e) = policy.validate() {
+        log::warn!("Rejected fault policy for {}: {}", route, e);
+        return Err(Status::UnprocessableEntity);
+    }
+    log::warn!("Fault injection updated for {}: {:?}", route, policy);
+    target.fault.set(Some(policy.clone()));
+    Ok(Json(RouteFaults {
+        route: target.path.clone(),
+        policy: Some(policy),
+    }))
+}
+
+#[delete("/admin/faults?<route>")]
+pub fn clear_fault(_admin: Admin, route: &str, config: &State<Arc<AppConfig>>) -> Status {
+    match config.routes.iter().find(|r| r.path == route) {
+        Some(target) => {
+            log::warn!("Fault injection cleared for {}", route);
+            target.fault.set(None);
+            Status::NoContent
+        }
+        None => Status::NotFound,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn policy(json: &str) -> FaultPolicy {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn request(headers: &[(&str, &str)]) -> RequestInfo {
+        RequestInfo {
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_abort_and_truncate_percentages() {
+        let policy = policy(
+            r#"{"abort": {"percentage": 20, "status": 500}, "truncate": {"percentage": 50}}"#,
+        );
+        let mut rng = StdRng::seed_from_u64(1);
+        let (mut aborts, mut truncates) = (0, 0);
+        for _ in 0..10_000 {
+            match policy.decide(&request(&[]), &mut rng).action {
+                Some(FaultAction::Abort(500)) => aborts += 1,
+                Some(FaultAction::Truncate) => truncates += 1,
+                Some(other) => panic!("unexpected {:?}", other),
+                None => {}
+            }
+        }
+        // 截断只在没有中止的请求里抽样：0.8 * 50% = 40%
+        assert!((1800..2200).contains(&aborts), "aborts = {}", aborts);
+        assert!(
+            (3700..4300).contains(&truncates),
+            "truncates = {}",
+            truncates
+        );
+    }
+
+    #[test]
+    fn test_abort_status_range() {
+        assert!(policy(r#"{"abort": {"percentage": 1, "status": 599}}"#)
+            .validate()
+            .is_ok());
+        for status in [0, 99, 600, 1000] {
+            let json = format!(r#"{{"abort": {{"percentage": 1, "status": {}}}}}"#, status);
+            assert!(policy(&json).validate().is_err(), "status {}", status);
+        }
+    }
+
+    #[test]
+    fn test_delay_distributions() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let uniform = policy(
+            r#"{"delay": {"percentage": 100, "distribution": "uniform", "delay_ms": 10, "max_ms": 20}}"#,
+        );
+        for _ in 0..100 {
+            let delay = uniform.decide(&request(&[]), &mut rng).delay.unwrap();
+            assert!((10..20).contains(&delay.as_millis()));
+        }
+
+        let exponential = policy(
+            r#"{"delay": {"percentage": 100, "distribution": "exponential", "delay_ms": 100}}"#,
+        );
+        let total: u128 = (0..10_000)
+            .map(|_| {
+                exponential
+                    .decide(&request(&[]), &mut rng)
+                    .delay
+                    .unwrap()
+                    .as_millis()
+            })
+            .sum();
+        let mean = total / 10_000;
+        assert!((90..110).contains(&mean), "mean = {}", mean);
+    }
+
+    #[test]
+    fn test_header_match_and_disabled() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let policy = policy(
+            r#"{"abort": {"percentage": 100}, "match_header": {"name": "X-Chaos", "value": "on"}}"#,
+        );
+        assert_eq!(
+            policy
+                .decide(&request(&[("x-chaos", "on")]), &mut rng)
+                .action,
+            Some(FaultAction::Abort(503))
+        );
+        assert_eq!(
+            policy.decide(&request(&[("X-Chaos", "off")]), &mut rng),
+            Injection::default()
+        );
+        assert_eq!(policy.decide(&request(&[]), &mut rng), Injection::default());
+
+        let disabled = FaultPolicy {
+            enabled: false,
+            match_header: None,
+            ..policy
+        };
+        assert_eq!(
+            disabled.decide(&request(&[]), &mut rng),
+            Injection::default()
+        );
+    }
+}
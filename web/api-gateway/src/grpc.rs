@@ -0,0 +1,592 @@
+use crate::access;
+use crate::error::GatewayError;
+use crate::load_balancer::select_upstream;
+use crate::metrics::{metric_key, Metrics};
+use crate::proxy::{admit_tenant, check_access, identity_headers, AUTH_HEADERS};
+use crate::request::RequestInfo;
+use crate::{path_matches, AppConfig, RouteConfig};
+use bytes::{BufMut, Bytes, BytesMut};
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Empty, Full};
+use hyper::body::{Body, Incoming};
+use hyper::header::{HeaderMap, HeaderValue, CONTENT_TYPE, HOST};
+use hyper::server::conn::http2;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode, Uri};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use rocket::fairing::AdHoc;
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+
+// gRPC 监听配置：HTTP/2 明文（h2c），与 Rocket 的 HTTP 端口分开
+#[derive(Debug, Clone, Deserialize)]
+pub struct GrpcConfig {
+    #[serde(default)]
+    pub host: Option<String>, // 不配置时与 server.host 相同
+    pub port: u16,
+}
+
+// 路由协议：grpc 路由只在 gRPC 端口上匹配，路径形如 /package.Service/Method
+#[derive(Debug, Default, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Protocol {
+    #[default]
+    Http,
+    Grpc,
+}
+
+// 网关自己产生的 gRPC 状态码
+const DEADLINE_EXCEEDED: u32 = 4;
+const PERMISSION_DENIED: u32 = 7;
+const RESOURCE_EXHAUSTED: u32 = 8;
+const UNIMPLEMENTED: u32 = 12;
+const UNAVAILABLE: u32 = 14;
+const UNAUTHENTICATED: u32 = 16;
+
+// 健康检查响应里的 ServingStatus::Serving
+const SERVING: u64 = 1;
+
+type GrpcBody = BoxBody<Bytes, hyper::Error>;
+
+// 上游使用 prior knowledge 的 h2c，不做协议协商
+pub fn h2c_client<B>() -> Client<HttpConnector, B>
+where
+    B: Body + Send + 'static + Unpin,
+    B::Data: Send,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    Client::builder(TokioExecutor::new())
+        .http2_only(true)
+        .build_http()
+}
+
+// 拆出 /package.Service/Method 中的服务名和方法名
+pub fn parse_path(path: &str) -> Option<(&str, &str)> {
+    let (service, method) = path.strip_prefix('/')?.split_once('/')?;
+    if service.is_empty() || method.is_empty() || method.contains('/') {
+        return None;
+    }
+    Some((service, method))
+}
+
+// Trailers-Only 响应：状态放在响应头里，没有响应体
+fn status_response(code: u32, message: &str) -> Response<GrpcBody> {
+    let mut response = Response::new(Empty::new().map_err(|never| match never {}).boxed());
+    let headers = response.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+    headers.insert("grpc-status", HeaderValue::from(code));
+    if let Ok(message) = HeaderValue::from_str(message) {
+        headers.insert("grpc-message", message);
+    }
+    response
+}
+
+fn grpc_status(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("grpc-status")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+fn record(metrics: &Metrics, route: &str, path: &str, status: &str) {
+    let (service, method) = parse_path(path).unwrap_or(("", ""));
+    metrics.incr(metric_key(
+        "grpc_requests_total",
+        &[
+            ("route", route),
+            ("service", service),
+            ("method", method),
+            ("status", status),
+        ],
+    ));
+}
+
+fn find_route<'a>(routes: &'a [RouteConfig], path: &str) -> Option<&'a RouteConfig> {
+    routes
+        .iter()
+        .find(|route| route.protocol == Protocol::Grpc && path_matches(route, path))
+}
+
+// 客户端 IP 和转发头与 HTTP 路由一样，按 server.trusted_proxies 和 forwarded_header 计算
+fn request_info(req: &Request<Incoming>, peer: SocketAddr, app: &AppConfig) -> RequestInfo {
+    let headers: Vec<(String, String)> = req
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    let mut header_map = rocket::http::HeaderMap::new();
+    for (name, value) in &headers {
+        header_map.add_raw(name.clone(), value.clone());
+    }
+    // HTTP/2 的 :authority 不在头里，X-Forwarded-Host 从它取
+    if let Some(authority) = req
+        .uri()
+        .authority()
+        .filter(|_| !header_map.contains("Host"))
+    {
+        header_map.add_raw("Host", authority.to_string());
+    }
+    let (trusted_proxies, forwarded_header) = (
+        app.server.trusted_proxies.as_slice(),
+        app.server.forwarded_header,
+    );
+    let client_ip = access::resolve_client_ip(
+        Some(peer.ip()),
+        &header_map,
+        trusted_proxies,
+        forwarded_header,
+    );
+    let forwarding_headers = access::forwarding_headers(
+        client_ip,
+        Some(peer.ip()),
+        &header_map,
+        trusted_proxies,
+        forwarded_header,
+    );
+    let request_id = headers
+        .iter()
+        .find(|(name, _)| name == "x-request-id")
+        .map(|(_, value)| value.clone())
+        .unwrap_or_else(|| format!("{:032x}", rand::random::<u128>()));
+    RequestInfo {
+        method: req.method().as_str().to_string(),
+        path: req.uri().path().to_string(),
+        client_ip,
+        request_id,
+        headers,
+        forwarding_headers,
+        ..Default::default()
+    }
+}
+
+fn status_code(error: &GatewayError) -> u32 {
+    match error {
+        GatewayError::Unauthorized => UNAUTHENTICATED,
+        GatewayError::Forbidden => PERMISSION_DENIED,
+        GatewayError::RateLimited | GatewayError::QuotaExceeded => RESOURCE_EXHAUSTED,
+        _ => UNAVAILABLE,
+    }
+}
+
+// 与 HTTP 代理相同的准入步骤：鉴权 -> 访问控制 -> 多租户，返回要告知上游的身份头
+async fn admit(
+    app: &AppConfig,
+    route: &RouteConfig,
+    http: &reqwest::Client,
+    info: &RequestInfo,
+    metrics: &Metrics,
+) -> Result<Vec<(String, String)>, GatewayError> {
+    let token = match &route.auth {
+        Some(auth) => Some(auth.authenticate(http, info).await.map_err(|e| {
+            metrics.incr(metric_key(
+                "auth_rejected_total",
+                &[("route", &route.path), ("code", e.code())],
+            ));
+            e
+        })?),
+        None => None,
+    };
+    app.rbac
+        .authorize(route, info, token.as_ref())
+        .map_err(|e| {
+            metrics.incr(metric_key(
+                "authz_denied_total",
+                &[("route", &route.path), ("code", e.code())],
+            ));
+            e
+        })?;
+    let tenant = match &app.tenancy {
+        Some(tenancy) => admit_tenant(tenancy, info, token.as_ref(), &route.path, metrics)?,
+        None => None,
+    };
+    Ok(identity_headers(tenant, token.as_ref()))
+}
+
+// 请求体和响应体都以流的形式透传，上游的 trailers（grpc-status 等）原样返回给客户端
+async fn handle(
+    app: Arc<AppConfig>,
+    metrics: Metrics,
+    client: Client<HttpConnector, Incoming>,
+    http: reqwest::Client,
+    peer: SocketAddr,
+    req: Request<Incoming>,
+) -> Result<Response<GrpcBody>, Infallible> {
+    let is_grpc = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/grpc"));
+    if !is_grpc {
+        let mut response = Response::new(Empty::new().map_err(|never| match never {}).boxed());
+        *response.status_mut() = StatusCode::UNSUPPORTED_MEDIA_TYPE;
+        return Ok(response);
+    }
+
+    let path = req.uri().path().to_string();
+    if parse_path(&path).is_none() {
+        return Ok(status_response(UNIMPLEMENTED, "Malformed gRPC method path"));
+    }
+    let Some(route) = find_route(&app.routes, &path) else {
+        record(&metrics, "", &path, &UNIMPLEMENTED.to_string());
+        return Ok(status_response(
+            UNIMPLEMENTED,
+            "No route matches this method",
+        ));
+    };
+
+    let info = request_info(&req, peer, &app);
+    let permitted = check_access(app.access.as_ref(), &info, "global", "", &metrics)
+        .and_then(|_| check_access(route.access.as_ref(), &info, "route", &route.path, &metrics));
+    if permitted.is_err() {
+        record(&metrics, &route.path, &path, &PERMISSION_DENIED.to_string());
+        return Ok(status_response(PERMISSION_DENIED, "Access denied"));
+    }
+
+    let identity = match admit(&app, route, &http, &info, &metrics).await {
+        Ok(identity) => identity,
+        Err(e) => {
+            let code = status_code(&e);
+            record(&metrics, &route.path, &path, &code.to_string());
+            return Ok(status_response(code, &e.message()));
+        }
+    };
+
+    let Some(upstream) = select_upstream(route, &info) else {
+        record(&metrics, &route.path, &path, &UNAVAILABLE.to_string());
+        return Ok(status_response(UNAVAILABLE, "No upstream available"));
+    };
+
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or("/");
+    let uri: Uri = match format!("{}{}", upstream.url.trim_end_matches('/'), path_and_query).parse()
+    {
+        Ok(uri) => uri,
+        Err(_) => {
+            log::error!("Invalid gRPC upstream URL: {}", upstream.url);
+            return Ok(status_response(UNAVAILABLE, "Invalid upstream"));
+        }
+    };
+
+    let (mut parts, body) = req.into_parts();
+    parts.uri = uri;
+    parts.headers.remove(HOST);
+    if let Ok(id) = HeaderValue::from_str(&info.request_id) {
+        parts.headers.insert("x-request-id", id);
+    }
+    // 转发头由网关重新生成，客户端自带的不透传
+    let forwarded: Vec<_> = parts
+        .headers
+        .keys()
+        .filter(|name| access::is_forwarding_header(name.as_str()))
+        .cloned()
+        .collect();
+    for name in forwarded {
+        parts.headers.remove(name);
+    }
+    for (name, value) in &info.forwarding_headers {
+        if let Ok(value) = HeaderValue::from_str(value) {
+            parts.headers.insert(
+                hyper::header::HeaderName::from_bytes(name.as_bytes()).expect("valid header name"),
+                value,
+            );
+        }
+    }
+    // 身份头由网关设置，不信任客户端自带的值
+    if app.tenancy.is_some() {
+        parts.headers.remove("x-tenant-id");
+    }
+    if route.auth.is_some() {
+        for name in AUTH_HEADERS {
+            parts.headers.remove(name);
+        }
+    }
+    for (name, value) in identity {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            parts.headers.insert(
+                hyper::header::HeaderName::from_bytes(name.as_bytes()).expect("valid header name"),
+                value,
+            );
+        }
+    }
+
+    let _in_flight = upstream.stats.start();
+    let start = Instant::now();
+    let result = tokio::time::timeout(
+        Duration::from_secs(route.timeout),
+        client.request(Request::from_parts(parts, body)),
+    )
+    .await;
+    metrics.observe(
+        metric_key("grpc_latency", &[("route", &route.path)]),
+        start.elapsed(),
+    );
+    upstream
+        .stats
+        .observe(start.elapsed(), Duration::from_secs(route.ewma_decay));
+
+    let response = match result {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => {
+            log::debug!("gRPC upstream {} failed: {}", upstream.url, e);
+            record(&metrics, &route.path, &path, &UNAVAILABLE.to_string());
+            return Ok(status_response(UNAVAILABLE, "Upstream unavailable"));
+        }
+        Err(_) => {
+            record(&metrics, &route.path, &path, &DEADLINE_EXCEEDED.to_string());
+            return Ok(status_response(DEADLINE_EXCEEDED, "Upstream timed out"));
+        }
+    };
+
+    // Trailers-Only 响应的状态在响应头里，否则等响应体结束时从 trailers 里取
+    if let Some(status) = grpc_status(response.headers()) {
+        record(&metrics, &route.path, &path, &status);
+        return Ok(response.map(|body| body.boxed()));
+    }
+    let route_path = route.path.clone();
+    Ok(response.map(|body| {
+        body.map_frame(move |frame| {
+            if let Some(status) = frame.trailers_ref().and_then(grpc_status) {
+                record(&metrics, &route_path, &path, &status);
+            }
+            frame
+        })
+        .boxed()
+    }))
+}
+
+async fn serve(
+    listener: TcpListener,
+    app: Arc<AppConfig>,
+    metrics: Metrics,
+    shutdown: rocket::Shutdown,
+) {
+    let client = h2c_client::<Incoming>();
+    let http = reqwest::Client::new(); // 鉴权（token 内省）用
+    tokio::pin!(shutdown);
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    log::warn!("gRPC accept failed: {}", e);
+                    continue;
+                }
+            },
+            _ = &mut shutdown => return,
+        };
+
+        let (app, metrics, client, http) =
+            (app.clone(), metrics.clone(), client.clone(), http.clone());
+        tokio::spawn(async move {
+            let service = service_fn(move |req| {
+                handle(
+                    app.clone(),
+                    metrics.clone(),
+                    client.clone(),
+                    http.clone(),
+                    peer,
+                    req,
+                )
+            });
+            if let Err(e) = http2::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                log::debug!("gRPC connection from {} closed: {}", peer, e);
+            }
+        });
+    }
+}
+
+// Rocket 启动后打开 gRPC 端口，随 Rocket 一起停止接受新连接
+pub fn fairing(config: Option<GrpcConfig>) -> AdHoc {
+    AdHoc::on_liftoff("gRPC Listener", move |rocket| {
+        Box::pin(async move {
+            let Some(config) = config else {
+                return;
+            };
+            let (Some(app), Some(metrics)) = (
+                rocket.state::<Arc<AppConfig>>().cloned(),
+                rocket.state::<Metrics>().cloned(),
+            ) else {
+                return;
+            };
+
+            let host = config.host.unwrap_or_else(|| app.server.host.clone());
+            let listener = match TcpListener::bind((host.as_str(), config.port)).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    log::error!(
+                        "Failed to bind gRPC listener on {}:{}: {}",
+                        host,
+                        config.port,
+                        e
+                    );
+                    return;
+                }
+            };
+            log::info!("gRPC listening on {}:{}", host, config.port);
+            tokio::spawn(serve(listener, app, metrics, rocket.shutdown()));
+        })
+    })
+}
+
+fn encode_varint(buf: &mut BytesMut, mut value: u64) {
+    while value >= 0x80 {
+        buf.put_u8((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.put_u8(value as u8);
+}
+
+fn decode_varint(bytes: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, byte) in bytes.iter().enumerate().take(10) {
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+// grpc.health.v1.HealthCheckRequest { string service = 1; }，加上 5 字节的 gRPC 消息头
+fn health_request(service: &str) -> Bytes {
+    let mut message = BytesMut::new();
+    if !service.is_empty() {
+        message.put_u8(0x0a);
+        encode_varint(&mut message, service.len() as u64);
+        message.put_slice(service.as_bytes());
+    }
+    let mut frame = BytesMut::with_capacity(5 + message.len());
+    frame.put_u8(0);
+    frame.put_u32(message.len() as u32);
+    frame.put_slice(&message);
+    frame.freeze()
+}
+
+// 从 HealthCheckResponse { ServingStatus status = 1; } 中取出状态，缺省为 0（UNKNOWN）
+fn health_status(frame: &[u8]) -> Option<u64> {
+    let len = u32::from_be_bytes(frame.get(1..5)?.try_into().ok()?) as usize;
+    let message = frame.get(5..5usize.checked_add(len)?)?;
+    let mut pos = 0;
+    let mut status = 0;
+    while pos < message.len() {
+        let (tag, n) = decode_varint(&message[pos..])?;
+        pos += n;
+        match tag & 0x7 {
+            0 => {
+                let (value, n) = decode_varint(&message[pos..])?;
+                pos += n;
+                if tag >> 3 == 1 {
+                    status = value;
+                }
+            }
+            2 => {
+                // 长度来自上游，先做溢出和越界检查再跳过
+                let (len, n) = decode_varint(&message[pos..])?;
+                let len = usize::try_from(len).ok()?;
+                pos = pos.checked_add(n)?.checked_add(len)?;
+                if pos > message.len() {
+                    return None;
+                }
+            }
+            _ => return None,
+        }
+    }
+    Some(status)
+}
+
+// 按 grpc.health.v1 协议探测上游，SERVING 视为健康
+pub async fn health_check(
+    client: &Client<HttpConnector, Full<Bytes>>,
+    upstream: &str,
+    service: &str,
+    timeout: Duration,
+) -> Result<(), String> {
+    let uri: Uri = format!(
+        "{}/grpc.health.v1.Health/Check",
+        upstream.trim_end_matches('/')
+    )
+    .parse()
+    .map_err(|_| format!("invalid upstream URL {}", upstream))?;
+    let request = Request::post(uri)
+        .header(CONTENT_TYPE, "application/grpc")
+        .header("te", "trailers")
+        .body(Full::new(health_request(service)))
+        .map_err(|e| e.to_string())?;
+
+    let call = async {
+        let response = client.request(request).await.map_err(|e| e.to_string())?;
+        let headers_status = grpc_status(response.headers());
+        let collected = response
+            .into_body()
+            .collect()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = headers_status.or_else(|| collected.trailers().and_then(grpc_status));
+        Ok::<_, String>((status, collected.to_bytes()))
+    };
+    let (status, body) = tokio::time::timeout(timeout, call)
+        .await
+        .map_err(|_| "timeout".to_string())??;
+
+    match status.as_deref() {
+        Some("0") => {}
+        Some(code) => return Err(format!("grpc-status {}", code)),
+        None => return Err("missing grpc-status".to_string()),
+    }
+    match health_status(&body) {
+        Some(SERVING) => Ok(()),
+        Some(status) => Err(format!("serving status {}", status)),
+        None => Err("invalid health check response".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(
+            parse_path("/helloworld.Greeter/SayHello"),
+            Some(("helloworld.Greeter", "SayHello"))
+        );
+        assert_eq!(parse_path("/helloworld.Greeter"), None);
+        assert_eq!(parse_path("/a/b/c"), None);
+        assert_eq!(parse_path("//SayHello"), None);
+    }
+
+    #[test]
+    fn test_health_messages() {
+        assert_eq!(&health_request("")[..], &[0, 0, 0, 0, 0]);
+        assert_eq!(
+            &health_request("svc")[..],
+            &[0, 0, 0, 0, 5, 0x0a, 3, b's', b'v', b'c']
+        );
+
+        assert_eq!(health_status(&[0, 0, 0, 0, 2, 0x08, 1]), Some(SERVING));
+        assert_eq!(health_status(&[0, 0, 0, 0, 2, 0x08, 2]), Some(2));
+        // 默认值不编码
+        assert_eq!(health_status(&[0, 0, 0, 0, 0]), Some(0));
+        assert_eq!(health_status(&[0, 0]), None);
+        // 长度前缀超出实际数据
+        assert_eq!(health_status(&[0, 0, 0, 0, 9, 0x08, 1]), None);
+        // 长度字段越界或溢出
+        assert_eq!(health_status(&[0, 0, 0, 0, 3, 0x12, 5, 0]), None);
+        let mut huge = vec![0, 0, 0, 0, 12, 0x12];
+        huge.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+        huge.push(0);
+        assert_eq!(health_status(&huge), None);
+    }
+}
@@ -0,0 +1,589 @@
+use crate::concurrency::Usage;
+use crate::grpc;
+use crate::load_balancer::{UpstreamSet, Upstreams};
+use crate::shutdown::DrainState;
+use crate::{AppConfig, RouteConfig, START_TIME};
+use bytes::Bytes;
+use chrono::Utc;
+use http_body_util::Full;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use rocket::fairing::AdHoc;
+use rocket::http::Status;
+use rocket::{get, serde::json::Json, State};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+
+// 健康检查相关的全局配置
+#[derive(Debug, Clone, Deserialize)]
+pub struct HealthConfig {
+    #[serde(default = "default_sample_interval")]
+    pub sample_interval: u64, // 后台采集系统信息的间隔（秒）
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            sample_interval: default_sample_interval(),
+        }
+    }
+}
+
+fn default_sample_interval() -> u64 {
+    5
+}
+
+// 路由级主动健康检查：定期请求上游的 path，2xx 视为成功
+#[derive(Debug, Clone, Deserialize)]
+pub struct HealthCheckConfig {
+    #[serde(default = "default_check_path")]
+    pub path: String,
+    #[serde(default = "default_check_interval")]
+    pub interval: u64, // 秒
+    #[serde(default = "default_check_timeout")]
+    pub timeout: u64, // 秒
+    #[serde(default = "default_unhealthy_threshold")]
+    pub unhealthy_threshold: u32, // 连续失败多少次标记为不健康
+    #[serde(default = "default_healthy_threshold")]
+    pub healthy_threshold: u32, // 连续成功多少次恢复为健康
+    #[serde(default)]
+    pub grpc: bool, // 使用 grpc.health.v1 协议探测，忽略 path
+    #[serde(default)]
+    pub service: String, // gRPC 健康检查的服务名，空串表示整个服务器
+}
+
+fn default_check_path() -> String {
+    "/health".to_string()
+}
+
+fn default_check_interval() -> u64 {
+    10
+}
+
+fn default_check_timeout() -> u64 {
+    2
+}
+
+fn default_unhealthy_threshold() -> u32 {
+    3
+}
+
+fn default_healthy_threshold() -> u32 {
+    2
+}
+
+// 上游健康状态，探测任务和各个接口共享同一份
+#[derive(Debug, Clone, Default)]
+pub struct UpstreamHealth(Arc<Mutex<HealthState>>);
+
+#[derive(Debug, Default)]
+struct HealthState {
+    down: bool, // 默认健康，没有配置主动检查的上游始终视为健康
+    failures: u32,
+    successes: u32,
+    last_check: Option<String>,
+    last_error: Option<String>,
+}
+
+impl UpstreamHealth {
+    pub fn is_healthy(&self) -> bool {
+        !self.0.lock().unwrap().down
+    }
+
+    #[cfg(test)]
+    pub(crate) fn mark_down(&self) {
+        self.0.lock().unwrap().down = true;
+    }
+
+    // 连续失败/成功达到阈值才切换状态，避免抖动
+    fn record(&self, result: Result<(), String>, config: &HealthCheckConfig) {
+        let mut state = self.0.lock().unwrap();
+        state.last_check = Some(Utc::now().to_rfc3339());
+        match result {
+            Ok(()) => {
+                state.failures = 0;
+                state.successes += 1;
+                state.last_error = None;
+                if state.down && state.successes >= config.healthy_threshold {
+                    state.down = false;
+                }
+            }
+            Err(error) => {
+                state.successes = 0;
+                state.failures += 1;
+                state.last_error = Some(error);
+                if !state.down && state.failures >= config.unhealthy_threshold {
+                    state.down = true;
+                }
+            }
+        }
+    }
+}
+
+// 健康检查地址：上游的 scheme + host + 检查路径
+fn check_url(upstream: &str, path: &str) -> Option<reqwest::Url> {
+    reqwest::Url::parse(upstream).ok()?.join(path).ok()
+}
+
+async fn probe(
+    client: &reqwest::Client,
+    url: reqwest::Url,
+    timeout: Duration,
+) -> Result<(), String> {
+    match client.get(url).timeout(timeout).send().await {
+        Ok(response) if response.status().is_success() => Ok(()),
+        Ok(response) => Err(format!("status {}", response.status().as_u16())),
+        Err(e) if e.is_timeout() => Err("timeout".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// 探测用的客户端：普通 HTTP 检查和 h2c 的 gRPC 检查
+#[derive(Clone)]
+struct Clients {
+    http: reqwest::Client,
+    grpc: Client<HttpConnector, Full<Bytes>>,
+}
+
+async fn check(
+    clients: &Clients,
+    upstream: &str,
+    config: &HealthCheckConfig,
+    health: &UpstreamHealth,
+) {
+    let timeout = Duration::from_secs(config.timeout);
+    let result = if config.grpc {
+        grpc::health_check(&clients.grpc, upstream, &config.service, timeout).await
+    } else {
+        let Some(url) = check_url(upstream, &config.path) else {
+            log::warn!("Invalid upstream URL for health check: {}", upstream);
+            return;
+        };
+        probe(&clients.http, url, timeout).await
+    };
+    let was_healthy = health.is_healthy();
+    health.record(result, config);
+    match (was_healthy, health.is_healthy()) {
+        (true, false) => log::warn!("Upstream {} marked unhealthy", upstream),
+        (false, true) => log::info!("Upstream {} recovered", upstream),
+        _ => {}
+    }
+}
+
+// 每轮检查路由当前的上游集合，服务发现增减的上游也会被覆盖
+fn spawn_checker(upstreams: Upstreams, config: HealthCheckConfig) {
+    tokio::spawn(async move {
+        let clients = Clients {
+            http: reqwest::Client::new(),
+            grpc: grpc::h2c_client(),
+        };
+        let mut interval = tokio::time::interval(Duration::from_secs(config.interval.max(1)));
+        loop {
+            interval.tick().await;
+            let probes: Vec<_> = upstreams
+                .snapshot()
+                .servers
+                .iter()
+                .map(|upstream| {
+                    let (clients, config) = (clients.clone(), config.clone());
+                    let (url, health) = (upstream.url.clone(), upstream.health.clone());
+                    tokio::spawn(async move { check(&clients, &url, &config, &health).await })
+                })
+                .collect();
+            for probe in probes {
+                let _ = probe.await;
+            }
+        }
+    });
+}
+
+#[derive(Serialize, Clone, Default)]
+struct MemoryInfo {
+    used_mb: f64,
+    total_mb: f64,
+    usage_percentage: f64,
+}
+
+#[derive(Serialize, Clone, Default)]
+struct CpuInfo {
+    usage_percentage: f64,
+}
+
+#[derive(Serialize, Clone, Default)]
+struct ProcessInfo {
+    pid: u32,
+    memory_mb: f64,
+    virtual_memory_mb: f64,
+    cpu_usage_percentage: f64,
+}
+
+// 后台采样的系统信息快照，接口直接读取，不在请求路径上刷新 sysinfo
+#[derive(Serialize, Clone, Default)]
+struct SystemSnapshot {
+    sampled_at: Option<String>,
+    memory: MemoryInfo,
+    cpu: CpuInfo,
+    process: ProcessInfo,
+}
+
+#[derive(Clone, Default)]
+pub struct Sampler(Arc<RwLock<SystemSnapshot>>);
+
+impl Sampler {
+    fn snapshot(&self) -> SystemSnapshot {
+        self.0.read().unwrap().clone()
+    }
+}
+
+// 保留2位小数
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+fn to_mb(bytes: u64) -> f64 {
+    round2(bytes as f64 / 1024.0 / 1024.0)
+}
+
+// CPU 使用率是两次刷新之间的差值，所以 System 要在采样任务里长期保留
+fn sample(system: &mut System, pid: Option<Pid>) -> SystemSnapshot {
+    system.refresh_memory();
+    system.refresh_cpu_usage();
+
+    let total = system.total_memory();
+    let used = system.used_memory();
+    let memory = MemoryInfo {
+        used_mb: to_mb(used),
+        total_mb: to_mb(total),
+        usage_percentage: round2(used as f64 / total.max(1) as f64 * 100.0),
+    };
+    let cpu = CpuInfo {
+        usage_percentage: round2(system.global_cpu_usage() as f64),
+    };
+
+    let mut process = ProcessInfo::default();
+    if let Some(pid) = pid {
+        system.refresh_processes_specifics(
+            ProcessesToUpdate::Some(&[pid]),
+            true,
+            ProcessRefreshKind::nothing().with_cpu().with_memory(),
+        );
+        if let Some(p) = system.process(pid) {
+            process = ProcessInfo {
+                pid: pid.as_u32(),
+                memory_mb: to_mb(p.memory()),
+                virtual_memory_mb: to_mb(p.virtual_memory()),
+                cpu_usage_percentage: round2(p.cpu_usage() as f64),
+            };
+        }
+    }
+
+    SystemSnapshot {
+        sampled_at: Some(Utc::now().to_rfc3339()),
+        memory,
+        cpu,
+        process,
+    }
+}
+
+// 启动后台任务：定期采集系统信息，并为配置了 health_check 的路由探测上游
+pub fn fairing(config: HealthConfig, sampler: Sampler) -> AdHoc {
+    AdHoc::on_liftoff("Health Sampler", move |rocket| {
+        Box::pin(async move {
+            tokio::spawn(async move {
+                let mut system = System::new();
+                let pid = sysinfo::get_current_pid().ok();
+                let mut interval =
+                    tokio::time::interval(Duration::from_secs(config.sample_interval.max(1)));
+                loop {
+                    interval.tick().await;
+                    let snapshot = sample(&mut system, pid);
+                    *sampler.0.write().unwrap() = snapshot;
+                }
+            });
+
+            let Some(app) = rocket.state::<Arc<AppConfig>>() else {
+                return;
+            };
+            for route in &app.routes {
+                if let Some(check) = &route.health_check {
+                    spawn_checker(route.upstreams.clone(), check.clone());
+                    // API 版本各自的上游也按路由的配置检查
+                    for version in route.versioning.iter().flat_map(|v| &v.versions) {
+                        spawn_checker(version.upstreams.clone(), check.clone());
+                    }
+                }
+            }
+        })
+    })
+}
+
+fn uptime() -> String {
+    let uptime_seconds = START_TIME.get().map_or(0, |t| t.elapsed().as_secs());
+    format!(
+        "{:02}:{:02}:{:02}",
+        uptime_seconds / 3600,
+        (uptime_seconds % 3600) / 60,
+        uptime_seconds % 60
+    )
+}
+
+// 健康检查响应结构体
+#[derive(Serialize)] //  自动生成JSON序列化代码
+pub struct HealthResponse {
+    status: String,
+    timestamp: String,
+    uptime: String,
+    memory: MemoryInfo,
+    cpu: CpuInfo,
+}
+
+// 兼容旧接口，数据来自后台采样
+#[get("/health")]
+pub fn health(
+    drain: &State<DrainState>,
+    sampler: &State<Sampler>,
+) -> (Status, Json<HealthResponse>) {
+    let snapshot = sampler.snapshot();
+
+    // 停机摘流期间返回 503，让负载均衡不再转发新流量
+    let (code, status) = if drain.is_draining() {
+        (Status::ServiceUnavailable, "draining")
+    } else {
+        (Status::Ok, "healthy")
+    };
+
+    let response = HealthResponse {
+        status: status.to_string(),
+        timestamp: Utc::now().to_rfc3339(),
+        uptime: uptime(),
+        memory: snapshot.memory,
+        cpu: snapshot.cpu,
+    };
+
+    (code, Json(response))
+}
+
+// 存活探针：进程能处理请求即可，不做任何检查
+#[get("/livez")]
+pub fn livez() -> &'static str {
+    "ok"
+}
+
+#[derive(Serialize)]
+pub struct RouteReadiness {
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>, // API 版本自己的上游单独统计
+    healthy_upstreams: usize,
+    total_upstreams: usize,
+}
+
+#[derive(Serialize)]
+pub struct ReadinessResponse {
+    status: String,
+    routes: Vec<RouteReadiness>,
+}
+
+// 路由实际会用到的上游组：路由自己的 upstreams，以及配置了上游的 API 版本。
+// 版本都有自己的上游时，路由的 upstreams 可以为空，这时不算进来
+fn upstream_sets(route: &RouteConfig) -> Vec<(Option<&str>, Arc<UpstreamSet>)> {
+    let versions: Vec<(Option<&str>, Arc<UpstreamSet>)> = route
+        .versioning
+        .iter()
+        .flat_map(|v| &v.versions)
+        .map(|version| (Some(version.name.as_str()), version.upstreams.snapshot()))
+        .filter(|(_, set)| !set.servers.is_empty())
+        .collect();
+    let own = route.upstreams.snapshot();
+    let mut sets = Vec::new();
+    if !own.servers.is_empty() || versions.is_empty() {
+        sets.push((None, own));
+    }
+    sets.extend(versions);
+    sets
+}
+
+// 就绪探针：配置已加载、没有在摘流，且每条路由至少有一个健康的上游
+#[get("/readyz")]
+pub fn readyz(
+    config: &State<Arc<AppConfig>>,
+    drain: &State<DrainState>,
+) -> (Status, Json<ReadinessResponse>) {
+    // 模拟路由和聚合路由没有 upstreams，不参与上游健康检查
+    let routes: Vec<RouteReadiness> = config
+        .routes
+        .iter()
+        .filter(|route| route.mock.is_none() && route.aggregate.is_none())
+        .flat_map(|route| {
+            upstream_sets(route)
+                .into_iter()
+                .map(|(version, upstreams)| RouteReadiness {
+                    path: route.path.clone(),
+                    version: version.map(str::to_string),
+                    healthy_upstreams: upstreams
+                        .servers
+                        .iter()
+                        .filter(|u| u.health.is_healthy())
+                        .count(),
+                    total_upstreams: upstreams.servers.len(),
+                })
+        })
+        .collect();
+
+    let status = if drain.is_draining() {
+        "draining"
+    } else if routes.iter().any(|r| r.healthy_upstreams == 0) {
+        "unavailable"
+    } else {
+        "ready"
+    };
+    let code = if status == "ready" {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+
+    (
+        code,
+        Json(ReadinessResponse {
+            status: status.to_string(),
+            routes,
+        }),
+    )
+}
+
+#[derive(Serialize)]
+pub struct UpstreamReport {
+    route: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    url: String,
+    healthy: bool,
+    last_check: Option<String>,
+    last_error: Option<String>,
+    in_flight: usize,
+    latency_ms: f64,
+}
+
+#[derive(Serialize)]
+pub struct ConcurrencyReport {
+    route: String,
+    #[serde(flatten)]
+    usage: Usage,
+}
+
+#[derive(Serialize)]
+pub struct HealthDetails {
+    status: String,
+    timestamp: String,
+    uptime: String,
+    system: SystemSnapshot,
+    upstreams: Vec<UpstreamReport>,
+    concurrency: Vec<ConcurrencyReport>,
+}
+
+#[get("/health/details")]
+pub fn details(
+    config: &State<Arc<AppConfig>>,
+    drain: &State<DrainState>,
+    sampler: &State<Sampler>,
+) -> Json<HealthDetails> {
+    let mut upstreams = Vec::new();
+    let mut concurrency = Vec::new();
+    for route in &config.routes {
+        if let Some(limiter) = &route.concurrency {
+            concurrency.push(ConcurrencyReport {
+                route: route.path.clone(),
+                usage: limiter.usage(),
+            });
+        }
+        for (version, set) in upstream_sets(route) {
+            for upstream in &set.servers {
+                let state = upstream.health.0.lock().unwrap();
+                upstreams.push(UpstreamReport {
+                    route: route.path.clone(),
+                    version: version.map(str::to_string),
+                    url: upstream.url.clone(),
+                    healthy: !state.down,
+                    last_check: state.last_check.clone(),
+                    last_error: state.last_error.clone(),
+                    in_flight: upstream.stats.in_flight(),
+                    latency_ms: round2(upstream.stats.latency_ms()),
+                });
+            }
+        }
+    }
+
+    let status = if drain.is_draining() {
+        "draining"
+    } else {
+        "healthy"
+    };
+
+    Json(HealthDetails {
+        status: status.to_string(),
+        timestamp: Utc::now().to_rfc3339(),
+        uptime: uptime(),
+        system: sampler.snapshot(),
+        upstreams,
+        concurrency,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_config() -> HealthCheckConfig {
+        HealthCheckConfig {
+            path: default_check_path(),
+            interval: default_check_interval(),
+            timeout: default_check_timeout(),
+            unhealthy_threshold: 3,
+            healthy_threshold: 2,
+            grpc: false,
+            service: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_health_thresholds() {
+        let config = check_config();
+        let health = UpstreamHealth::default();
+        assert!(health.is_healthy());
+
+        health.record(Err("timeout".to_string()), &config);
+        health.record(Err("timeout".to_string()), &config);
+        assert!(health.is_healthy());
+        health.record(Ok(()), &config);
+        health.record(Err("status 500".to_string()), &config);
+        health.record(Err("status 500".to_string()), &config);
+        assert!(health.is_healthy());
+        health.record(Err("status 500".to_string()), &config);
+        assert!(!health.is_healthy());
+
+        health.record(Ok(()), &config);
+        assert!(!health.is_healthy());
+        health.record(Ok(()), &config);
+        assert!(health.is_healthy());
+    }
+
+    #[test]
+    fn test_check_url() {
+        assert_eq!(
+            check_url("http://10.0.0.1:8080/api/v1/users", "/health")
+                .unwrap()
+                .as_str(),
+            "http://10.0.0.1:8080/health"
+        );
+        assert_eq!(
+            check_url("https://backend", "/status/ready")
+                .unwrap()
+                .as_str(),
+            "https://backend/status/ready"
+        );
+        assert!(check_url("not a url", "/health").is_none());
+    }
+}
@@ -0,0 +1,224 @@
+use crate::oauth::TokenInfo;
+use crate::secret;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Sha256, Sha384, Sha512};
+
+// 本地校验的 JWT：HMAC 签名 + exp / nbf + 可选的 iss
+#[derive(Debug, Deserialize)]
+pub struct JwtConfig {
+    pub secret: String, // HMAC 密钥；"env:NAME" 表示从环境变量读取
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    #[serde(default)]
+    pub issuer: Option<String>, // 配置后 iss 必须一致
+    #[serde(default = "default_leeway")]
+    pub leeway: u64, // 校验 exp / nbf 时允许的时钟偏差（秒）
+}
+
+fn default_leeway() -> u64 {
+    30
+}
+
+// 只支持 HMAC；header 里的 alg 必须与配置一致，不接受 "none"
+#[derive(Debug, Default, Clone, Copy, Deserialize, PartialEq)]
+pub enum Algorithm {
+    #[default]
+    #[serde(rename = "HS256")]
+    Hs256,
+    #[serde(rename = "HS384")]
+    Hs384,
+    #[serde(rename = "HS512")]
+    Hs512,
+}
+
+impl Algorithm {
+    fn name(self) -> &'static str {
+        match self {
+            Algorithm::Hs256 => "HS256",
+            Algorithm::Hs384 => "HS384",
+            Algorithm::Hs512 => "HS512",
+        }
+    }
+
+    // Mac::verify_slice 做常量时间比较
+    fn verify(self, key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+        fn check<M: Mac + hmac::digest::KeyInit>(
+            key: &[u8],
+            message: &[u8],
+            signature: &[u8],
+        ) -> bool {
+            let Ok(mut mac) = <M as Mac>::new_from_slice(key) else {
+                return false;
+            };
+            mac.update(message);
+            mac.verify_slice(signature).is_ok()
+        }
+        match self {
+            Algorithm::Hs256 => check::<Hmac<Sha256>>(key, message, signature),
+            Algorithm::Hs384 => check::<Hmac<Sha384>>(key, message, signature),
+            Algorithm::Hs512 => check::<Hmac<Sha512>>(key, message, signature),
+        }
+    }
+}
+
+// 角色、作用域既可能是数组，也可能是空格分隔的字符串
+pub fn claim_list(value: &Value) -> Vec<String> {
+    match value {
+        Value::Array(items) => items
+            .iter()
+            .filter_map(|v| v.as_str().map(str::to_string))
+            .collect(),
+        Value::String(s) => s.split_whitespace().map(str::to_string).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn decode_part(part: &str) -> Option<Vec<u8>> {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(part.trim_end_matches('='))
+        .ok()
+}
+
+impl JwtConfig {
+    pub fn resolve_secrets(&mut self) -> Result<(), String> {
+        secret::resolve(&mut self.secret)
+    }
+
+    // 校验顺序：结构 -> alg -> 签名 -> exp / nbf -> iss；任何一步失败都返回 None
+    pub fn verify(&self, token: &str, now: u64) -> Option<TokenInfo> {
+        let mut parts = token.split('.');
+        let (header, payload, signature) = (parts.next()?, parts.next()?, parts.next()?);
+        if parts.next().is_some() {
+            return None;
+        }
+
+        let header: Value = serde_json::from_slice(&decode_part(header)?).ok()?;
+        if header["alg"].as_str() != Some(self.algorithm.name()) {
+            return None;
+        }
+        // 签名覆盖的部分："header.payload"
+        let signed = &token[..token.rfind('.')?];
+        if !self.algorithm.verify(
+            self.secret.as_bytes(),
+            signed.as_bytes(),
+            &decode_part(signature)?,
+        ) {
+            return None;
+        }
+
+        let claims: Value = serde_json::from_slice(&decode_part(payload)?).ok()?;
+        // exp 必须存在，不接受永不过期的 token
+        let exp = claims["exp"].as_u64()?;
+        if exp.saturating_add(self.leeway) <= now {
+            return None;
+        }
+        if claims["nbf"]
+            .as_u64()
+            .is_some_and(|nbf| nbf > now.saturating_add(self.leeway))
+        {
+            return None;
+        }
+        if let Some(issuer) = &self.issuer {
+            if claims["iss"].as_str() != Some(issuer.as_str()) {
+                return None;
+            }
+        }
+
+        let scope = if claims["scope"].is_null() {
+            &claims["scp"]
+        } else {
+            &claims["scope"]
+        };
+        Some(TokenInfo {
+            subject: claims["sub"].as_str().map(str::to_string),
+            client_id: claims["client_id"]
+                .as_str()
+                .or_else(|| claims["azp"].as_str())
+                .map(str::to_string),
+            scopes: claim_list(scope),
+            exp: Some(exp),
+            claims,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn config(issuer: Option<&str>) -> JwtConfig {
+        JwtConfig {
+            secret: "jwt-secret".to_string(),
+            algorithm: Algorithm::Hs256,
+            issuer: issuer.map(str::to_string),
+            leeway: 0,
+        }
+    }
+
+    fn encode(value: &Value) -> String {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(value.to_string())
+    }
+
+    fn token(alg: &str, claims: Value, secret: &str) -> String {
+        let signed = format!(
+            "{}.{}",
+            encode(&json!({ "alg": alg, "typ": "JWT" })),
+            encode(&claims)
+        );
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(signed.as_bytes());
+        let signature =
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+        format!("{}.{}", signed, signature)
+    }
+
+    #[test]
+    fn test_verifies_signature_and_claims() {
+        let claims =
+            json!({ "sub": "bob", "exp": NOW + 60, "scope": "orders write", "roles": ["admin"] });
+        let info = config(None)
+            .verify(&token("HS256", claims.clone(), "jwt-secret"), NOW)
+            .unwrap();
+        assert_eq!(info.subject.as_deref(), Some("bob"));
+        assert_eq!(info.scopes, vec!["orders", "write"]);
+        assert_eq!(info.claims["roles"], json!(["admin"]));
+
+        // 签名密钥不对、被篡改、alg 不一致都拒绝
+        assert!(config(None)
+            .verify(&token("HS256", claims.clone(), "other"), NOW)
+            .is_none());
+        let valid = token("HS256", claims.clone(), "jwt-secret");
+        let mut parts: Vec<&str> = valid.split('.').collect();
+        let forged = encode(&json!({ "sub": "root", "exp": NOW + 60 }));
+        parts[1] = &forged;
+        assert!(config(None).verify(&parts.join("."), NOW).is_none());
+        assert!(config(None)
+            .verify(&token("none", claims, "jwt-secret"), NOW)
+            .is_none());
+        assert!(config(None).verify("not-a-jwt", NOW).is_none());
+    }
+
+    #[test]
+    fn test_rejects_expired_missing_exp_and_wrong_issuer() {
+        let verify = |config: &JwtConfig, claims: Value| {
+            config.verify(&token("HS256", claims, "jwt-secret"), NOW)
+        };
+        assert!(verify(&config(None), json!({ "sub": "a", "exp": NOW })).is_none());
+        assert!(verify(&config(None), json!({ "sub": "a" })).is_none());
+        assert!(verify(&config(None), json!({ "exp": NOW + 60, "nbf": NOW + 30 })).is_none());
+
+        let issuer = config(Some("https://idp.example.com"));
+        assert!(verify(&issuer, json!({ "exp": NOW + 60, "iss": "https://evil" })).is_none());
+        assert!(verify(
+            &issuer,
+            json!({ "exp": NOW + 60, "iss": "https://idp.example.com" })
+        )
+        .is_some());
+    }
+}
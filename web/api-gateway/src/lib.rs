@@ -0,0 +1,426 @@
+use rocket::{catchers, get, routes, Build, Rocket};
+// 添加一个静态变量记录启动时间
+// 改为使用 OnceLock
+use log::LevelFilter;
+use serde::Deserialize;
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+mod access;
+mod admin;
+mod aggregate;
+mod compression;
+mod concurrency;
+mod convert;
+mod cors;
+mod discovery;
+mod error;
+mod fault;
+mod grpc;
+mod health;
+mod jwt;
+mod load_balancer;
+mod metrics;
+mod mirror;
+mod mock;
+mod oauth;
+mod openapi;
+mod proxy;
+mod rbac;
+mod request;
+mod secret;
+mod shutdown;
+mod signature;
+mod tenant;
+#[cfg(test)]
+mod test_util;
+mod transform;
+mod upload;
+mod versioning;
+
+use health::Sampler;
+use metrics::Metrics;
+use shutdown::DrainState;
+
+static START_TIME: OnceLock<Instant> = OnceLock::new();
+
+#[derive(Debug, Deserialize)]
+pub struct AppConfig {
+    server: ServerConfig,
+    logging: LoggingConfig,
+    #[serde(default)]
+    routes: Vec<RouteConfig>, // 直接使用Vec<RouteConfig>，提供默认值
+    #[serde(default)]
+    cors: Option<cors::CorsConfig>, // 全局 CORS 策略
+    #[serde(default)]
+    compression: Option<compression::CompressionConfig>, // 响应压缩，不配置则关闭
+    #[serde(default)]
+    access: Option<access::AccessConfig>, // 全局 IP 黑白名单
+    #[serde(default)]
+    health: health::HealthConfig, // 后台系统信息采样
+    #[serde(default)]
+    openapi: Option<openapi::OpenApiConfig>, // /openapi.json 的文档信息
+    #[serde(default)]
+    admin: admin::AdminConfig, // 管理接口鉴权
+    #[serde(default)]
+    discovery: Option<discovery::DiscoveryConfig>, // 服务发现，路由通过 service 引用
+    #[serde(default)]
+    grpc: Option<grpc::GrpcConfig>, // gRPC（h2c）监听端口，不配置则不开启
+    #[serde(default)]
+    tenancy: Option<tenant::TenancyConfig>, // 多租户：租户识别、路由权限、限流和每日配额
+    #[serde(default)]
+    rbac: rbac::RbacConfig, // 角色来源、默认拒绝和审计日志
+    #[serde(default)]
+    uploads: upload::UploadsConfig, // 可续传上传的暂存目录和过期时间
+}
+
+#[derive(Debug, Deserialize)]
+struct ServerConfig {
+    host: String,
+    port: u16,
+    workers: usize,
+    #[serde(default)]
+    trusted_proxies: Vec<access::Cidr>, // 受信代理，只信任它们传来的转发头
+    #[serde(default)]
+    forwarded_header: access::ForwardedHeader, // 受信代理使用的转发头："x-forwarded-for"（默认）或 "forwarded"
+    #[serde(default)]
+    shutdown: shutdown::ShutdownConfig, // 优雅停机
+}
+
+#[derive(Debug, Deserialize)]
+struct LoggingConfig {
+    level: String,
+    #[allow(dead_code)]
+    format: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RouteConfig {
+    path: String,
+    method: String,
+    #[serde(default)]
+    upstreams: load_balancer::Upstreams, // 支持多个上游服务器，模拟路由和聚合路由可以不配置
+    #[serde(default)]
+    service: Option<String>, // 服务名，上游列表由服务发现提供并定期刷新
+    timeout: u64,
+    #[serde(default)]
+    protocol: grpc::Protocol, // "grpc" 的路由只在 gRPC 端口上按 /package.Service/Method 匹配
+    #[serde(default)]
+    concurrency: Option<concurrency::ConcurrencyConfig>, // 并发上限和排队（舱壁隔离）
+    #[serde(default)]
+    load_balance: String, // 负载均衡算法："round_robin", "weighted", "least_conn", "consistent_hash", "ip_hash", "p2c", "ewma"
+    #[serde(default = "load_balancer::default_ewma_decay")]
+    ewma_decay: u64, // EWMA 延迟的衰减时间常数（秒）
+    #[serde(default)]
+    mirror: Option<mirror::MirrorConfig>, // 流量镜像（可选）
+    #[serde(default)]
+    hash: Option<load_balancer::HashConfig>, // consistent_hash 的哈希键配置
+    #[serde(default)]
+    sticky: Option<load_balancer::StickyConfig>, // 基于 Cookie 的会话保持
+    #[serde(default)]
+    cors: Option<cors::CorsConfig>, // 路由级 CORS 策略，覆盖全局配置
+    #[serde(default)]
+    transform: Option<transform::TransformConfig>, // 请求/响应转换规则
+    #[serde(default)]
+    convert: Option<convert::ConvertConfig>, // 上游使用 XML 或表单时的请求/响应体转换
+    #[serde(default)]
+    decompress_request: bool, // 是否解压 gzip 请求体
+    #[serde(default)]
+    access: Option<access::AccessConfig>, // 路由级 IP 黑白名单，与全局规则同时生效
+    #[serde(default)]
+    signature: Option<signature::SignatureConfig>, // HMAC 请求签名校验
+    #[serde(default)]
+    auth: Option<oauth::RouteAuth>, // 客户端鉴权，例如 OAuth2 token 内省
+    #[serde(default)]
+    upstream_auth: Option<oauth::ClientCredentials>, // 调用受保护上游时由网关获取 token
+    #[serde(flatten)]
+    authorization: rbac::Requirement, // required_roles / required_scopes
+    #[serde(default)]
+    method_policies: rbac::MethodPolicies, // 按方法覆盖 required_roles / required_scopes
+    #[serde(default)]
+    fault: fault::Faults, // 故障注入策略，可通过管理接口在运行时修改
+    #[serde(default)]
+    mock: Option<mock::MockConfig>, // 模拟上游：直接返回配置的响应，不访问后端
+    #[serde(default)]
+    aggregate: Option<aggregate::AggregateConfig>, // 聚合路由：并发调用多个上游并合并 JSON
+    #[serde(default)]
+    openapi: Option<openapi::RouteOpenApi>, // 接口文档和请求体校验
+    #[serde(default)]
+    health_check: Option<health::HealthCheckConfig>, // 上游主动健康检查
+    #[serde(default)]
+    versioning: Option<versioning::VersioningConfig>, // API 版本，各版本可以指向不同的上游
+    #[serde(default)]
+    upload: Option<upload::UploadConfig>, // 可续传的分块上传，完成后把整个文件转发给上游
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct UpstreamServer {
+    url: String,
+    weight: u32, // 用于加权轮询
+    #[serde(skip)]
+    stats: std::sync::Arc<load_balancer::UpstreamStats>, // 运行时统计：EWMA 延迟、在途请求数
+    #[serde(skip)]
+    health: health::UpstreamHealth, // 主动健康检查的结果
+}
+
+// 移除RoutesConfig，直接使用Vec<RouteConfig>
+
+#[get("/")]
+fn index() -> &'static str {
+    "Welcome to API Gateway!"
+}
+
+// 加载配置
+pub fn load_config() -> Result<AppConfig, config::ConfigError> {
+    let settings = config::Config::builder()
+        .add_source(config::File::with_name("config/default.toml"))
+        .build()?;
+
+    parse_config(settings)
+}
+
+// 反序列化并检查配置；不安全或不会生效的组合在启动时报错，而不是运行时静默忽略
+pub fn parse_config(settings: config::Config) -> Result<AppConfig, config::ConfigError> {
+    let mut config = settings.try_deserialize::<AppConfig>()?;
+    config.prepare().map_err(config::ConfigError::Message)?;
+    Ok(config)
+}
+
+impl AppConfig {
+    fn prepare(&mut self) -> Result<(), String> {
+        if let Some(cors) = &self.cors {
+            cors.validate().map_err(|e| format!("cors: {}", e))?;
+        }
+        self.admin
+            .resolve_secrets()
+            .map_err(|e| format!("admin: {}", e))?;
+        self.rbac
+            .resolve_secrets()
+            .map_err(|e| format!("rbac: {}", e))?;
+        // 角色和租户声明只从网关校验过的 token 里取，没有任何路由校验 token 时这些配置不会生效
+        let verifies_tokens = self.routes.iter().any(|r| r.auth.is_some());
+        if self.rbac.jwt_roles_claim.is_some() && !verifies_tokens {
+            return Err("rbac.jwt_roles_claim requires at least one route with auth".to_string());
+        }
+        if self.tenancy.as_ref().is_some_and(|t| t.jwt_claim.is_some()) && !verifies_tokens {
+            return Err("tenancy.jwt_claim requires at least one route with auth".to_string());
+        }
+        for route in &self.routes {
+            if let Some(service) = &route.service {
+                discovery::check_service(self.discovery.as_ref(), service)
+                    .map_err(|e| format!("route {}: {}", route.path, e))?;
+            }
+        }
+        for route in &mut self.routes {
+            let path = route.path.clone();
+            route
+                .prepare()
+                .map_err(|e| format!("route {}: {}", path, e))?;
+        }
+        Ok(())
+    }
+}
+
+impl RouteConfig {
+    fn prepare(&mut self) -> Result<(), String> {
+        if let Some(cors) = &self.cors {
+            cors.validate()?;
+        }
+        if let Some(openapi) = &self.openapi {
+            openapi.prepare()?;
+        }
+        if let Some(fault) = self.fault.current() {
+            fault.validate()?;
+        }
+        if let Some(mock) = &self.mock {
+            mock.validate()?;
+        }
+        if let Some(transform) = &self.transform {
+            transform.validate()?;
+        }
+        if let Some(versioning) = &self.versioning {
+            versioning.validate()?;
+        }
+        if let Some(signature) = &mut self.signature {
+            signature.resolve_secrets()?;
+        }
+        if let Some(auth) = &mut self.auth {
+            auth.resolve_secrets()?;
+        }
+        if let Some(upstream_auth) = &mut self.upstream_auth {
+            secret::resolve(&mut upstream_auth.client_secret)?;
+        }
+        // gRPC 代理只做访问控制、鉴权、角色/作用域和多租户检查，再按负载均衡转发，
+        // 下面这些配置不会生效，直接拒绝
+        if self.protocol == grpc::Protocol::Grpc {
+            let unsupported = [
+                ("signature", self.signature.is_some()),
+                ("concurrency", self.concurrency.is_some()),
+                ("fault", self.fault.current().is_some()),
+                ("method_policies", !self.method_policies.is_empty()),
+                ("upstream_auth", self.upstream_auth.is_some()),
+                ("mirror", self.mirror.is_some()),
+                ("transform", self.transform.is_some()),
+                ("convert", self.convert.is_some()),
+                ("mock", self.mock.is_some()),
+                ("aggregate", self.aggregate.is_some()),
+                ("versioning", self.versioning.is_some()),
+                ("upload", self.upload.is_some()),
+                ("decompress_request", self.decompress_request),
+                ("openapi", self.openapi.is_some()),
+                ("cors", self.cors.is_some()),
+                ("sticky", self.sticky.is_some()),
+            ];
+            reject_unsupported(&unsupported, "grpc routes")?;
+        }
+        // 上传路由的 POST 只创建上传，文件到齐后直接转发，下面这些配置不会生效
+        if let Some(upload) = &self.upload {
+            upload.validate()?;
+            if self.method != "*" && !self.method.split('|').any(|m| m.trim() == "POST") {
+                return Err("upload requires POST in method".to_string());
+            }
+            let unsupported = [
+                ("decompress_request", self.decompress_request),
+                ("openapi", self.openapi.is_some()),
+                ("transform", self.transform.is_some()),
+                ("convert", self.convert.is_some()),
+                ("concurrency", self.concurrency.is_some()),
+                ("mirror", self.mirror.is_some()),
+                ("mock", self.mock.is_some()),
+                ("aggregate", self.aggregate.is_some()),
+            ];
+            reject_unsupported(&unsupported, "upload routes")?;
+        }
+        Ok(())
+    }
+}
+
+fn reject_unsupported(settings: &[(&str, bool)], scope: &str) -> Result<(), String> {
+    match settings.iter().find(|(_, set)| *set) {
+        Some((name, _)) => Err(format!("{} is not supported on {}", name, scope)),
+        None => Ok(()),
+    }
+}
+
+// 初始化日志
+pub fn init_logging(config: &AppConfig) {
+    let config = &config.logging;
+    let level = match config.level.as_str() {
+        "error" => LevelFilter::Error,
+        "warn" => LevelFilter::Warn,
+        "info" => LevelFilter::Info,
+        "debug" => LevelFilter::Debug,
+        "trace" => LevelFilter::Trace,
+        _ => LevelFilter::Info,
+    };
+
+    env_logger::Builder::new()
+        .filter_level(level)
+        .format_timestamp_secs()
+        .init();
+}
+
+// 查找路由
+fn find_route<'a>(routes: &'a [RouteConfig], path: &str, method: &str) -> Option<&'a RouteConfig> {
+    routes.iter().find(|route| {
+        if route.protocol != grpc::Protocol::Http || !path_matches(route, path) {
+            return false;
+        }
+
+        // 方法匹配
+        if route.method == "*" {
+            return true;
+        }
+
+        route.method.split('|').any(|m| m.trim() == method)
+    })
+}
+
+// 只按路径查找路由（例如 CORS 预检时还不知道真正的请求方法）
+fn find_route_by_path<'a>(routes: &'a [RouteConfig], path: &str) -> Option<&'a RouteConfig> {
+    routes
+        .iter()
+        .find(|route| route.protocol == grpc::Protocol::Http && path_matches(route, path))
+}
+
+// 路径匹配（支持通配符）
+fn path_matches(route: &RouteConfig, path: &str) -> bool {
+    if route.path.ends_with("/*") {
+        let prefix = route.path.trim_end_matches("/*");
+        path.starts_with(prefix)
+    } else {
+        route.path == path
+    }
+}
+
+// 根据配置构建 Rocket 实例；二进制入口和集成测试共用
+pub fn rocket(config: AppConfig) -> Rocket<Build> {
+    // 初始化启动时间，同一进程内多次构建时保留第一次的时间
+    START_TIME.get_or_init(Instant::now);
+    log::info!(
+        "API Gateway starting on {}:{}",
+        config.server.host,
+        config.server.port
+    );
+
+    let drain = DrainState::default();
+    let shutdown_config = config.server.shutdown.clone();
+    let sampler = Sampler::default();
+    let health_config = config.health.clone();
+    let discovery_config = config.discovery.clone();
+    let grpc_config = config.grpc.clone();
+
+    rocket::build()
+        .configure(rocket::Config {
+            address: config.server.host.parse().unwrap(),
+            port: config.server.port,
+            workers: config.server.workers,
+            shutdown: shutdown_config.rocket_shutdown(),
+            ..Default::default()
+        })
+        .manage(Arc::new(config)) // 添加状态管理；gRPC 监听器等后台任务共享同一份配置
+        .manage(Metrics::default())
+        .manage(drain.clone())
+        .manage(sampler.clone())
+        .manage(openapi::SpecCache::default())
+        .attach(shutdown::fairing(shutdown_config, drain))
+        .attach(health::fairing(health_config, sampler))
+        .attach(discovery::fairing(discovery_config))
+        .attach(grpc::fairing(grpc_config))
+        .attach(tenant::UsagePersistence)
+        .attach(upload::fairing())
+        .attach(cors::Cors)
+        .mount(
+            "/",
+            routes![
+                index,
+                health::health,
+                health::livez,
+                health::readyz,
+                health::details,
+                metrics::metrics,
+                openapi::openapi,
+                fault::list_faults,
+                fault::set_fault,
+                fault::clear_fault,
+                tenant::usage,
+                cors::preflight,
+                proxy::proxy_get,
+                proxy::proxy_post,
+                proxy::proxy_put,
+                proxy::proxy_patch,
+                proxy::proxy_delete,
+                upload::progress,
+                upload::append,
+                upload::cancel,
+            ],
+        )
+        .register(
+            "/",
+            catchers![
+                error::not_found,
+                error::internal_error,
+                error::default_catcher
+            ],
+        )
+}
@@ -0,0 +1,877 @@
+use crate::request::RequestInfo;
+use crate::{RouteConfig, UpstreamServer};
+use rand::Rng;
+use rocket::http::Cookie;
+use serde::Deserialize;
+// AtomicUsize: 线程安全的计数器，用于轮询算法
+// Ordering: 内存顺序保证
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+// 一致性哈希配置
+#[derive(Debug, Deserialize)]
+pub struct HashConfig {
+    #[serde(default = "default_hash_source")]
+    pub source: HashSource,
+    #[serde(default)]
+    pub name: Option<String>, // source 为 header / cookie 时的名字
+    #[serde(default = "default_virtual_nodes")]
+    pub virtual_nodes: u32, // 每个上游的虚拟节点数
+}
+
+// 哈希键的来源
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HashSource {
+    Header,
+    Cookie,
+    Ip,
+    Path,
+}
+
+// 基于 Cookie 的会话保持配置
+#[derive(Debug, Deserialize)]
+pub struct StickyConfig {
+    #[serde(default = "default_sticky_cookie")]
+    pub cookie: String,
+    #[serde(default)]
+    pub ttl: Option<u64>, // Cookie 有效期（秒），不设置则为会话 Cookie
+}
+
+fn default_hash_source() -> HashSource {
+    HashSource::Ip
+}
+
+fn default_virtual_nodes() -> u32 {
+    160
+}
+
+fn default_sticky_cookie() -> String {
+    "gw_sticky".to_string()
+}
+
+pub fn default_ewma_decay() -> u64 {
+    10
+}
+
+// 上游运行时统计：在途请求数 + 响应延迟的指数加权移动平均（EWMA）
+#[derive(Debug, Default)]
+pub struct UpstreamStats {
+    in_flight: AtomicUsize,
+    ewma: Mutex<Ewma>,
+}
+
+#[derive(Debug, Default)]
+struct Ewma {
+    latency_ms: f64,
+    last_update: Option<Instant>,
+}
+
+// 在途请求的 guard，drop 时计数减一
+pub struct InFlight<'a>(&'a UpstreamStats);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl UpstreamStats {
+    pub fn start(&self) -> InFlight<'_> {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(self)
+    }
+
+    pub fn observe(&self, rtt: Duration, decay: Duration) {
+        self.observe_at(rtt, decay, Instant::now());
+    }
+
+    // 按距离上次更新的时间衰减旧值：间隔越久，新样本权重越大
+    fn observe_at(&self, rtt: Duration, decay: Duration, now: Instant) {
+        let rtt_ms = rtt.as_secs_f64() * 1000.0;
+        let mut ewma = self.ewma.lock().unwrap();
+
+        ewma.latency_ms = match ewma.last_update {
+            Some(last) if decay > Duration::ZERO => {
+                let elapsed = now.saturating_duration_since(last).as_secs_f64();
+                let w = (-elapsed / decay.as_secs_f64()).exp();
+                ewma.latency_ms * w + rtt_ms * (1.0 - w)
+            }
+            _ => rtt_ms,
+        };
+        ewma.last_update = Some(now);
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    pub fn latency_ms(&self) -> f64 {
+        self.ewma.lock().unwrap().latency_ms
+    }
+
+    // 还没有任何响应样本时返回 None
+    fn observed_latency_ms(&self) -> Option<f64> {
+        let ewma = self.ewma.lock().unwrap();
+        ewma.last_update.map(|_| ewma.latency_ms)
+    }
+}
+
+// 某一时刻的上游集合；一致性哈希环跟随集合，集合替换后重新构建
+#[derive(Debug, Default)]
+pub struct UpstreamSet {
+    pub servers: Vec<UpstreamServer>,
+    ring: OnceLock<HashRing>,
+}
+
+// 路由的上游列表：静态配置，或由服务发现在运行时整体替换
+#[derive(Debug, Clone, Default)]
+pub struct Upstreams(Arc<RwLock<Arc<UpstreamSet>>>);
+
+impl<'de> Deserialize<'de> for Upstreams {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Upstreams::from(Vec::<UpstreamServer>::deserialize(
+            deserializer,
+        )?))
+    }
+}
+
+impl From<Vec<UpstreamServer>> for Upstreams {
+    fn from(servers: Vec<UpstreamServer>) -> Self {
+        let set = UpstreamSet {
+            servers,
+            ring: OnceLock::new(),
+        };
+        Upstreams(Arc::new(RwLock::new(Arc::new(set))))
+    }
+}
+
+impl Upstreams {
+    // 请求处理期间使用同一份快照，不受并发刷新影响
+    pub fn snapshot(&self) -> Arc<UpstreamSet> {
+        self.0.read().unwrap().clone()
+    }
+
+    // 替换上游集合；URL 不变的上游保留运行时统计和健康状态。返回集合是否有变化
+    pub fn replace(&self, servers: Vec<UpstreamServer>) -> bool {
+        let current = self.snapshot();
+        let unchanged = current.servers.len() == servers.len()
+            && current
+                .servers
+                .iter()
+                .zip(&servers)
+                .all(|(a, b)| a.url == b.url && a.weight == b.weight);
+        if unchanged {
+            return false;
+        }
+
+        let servers = servers
+            .into_iter()
+            .map(
+                |server| match current.servers.iter().find(|s| s.url == server.url) {
+                    Some(existing) => UpstreamServer {
+                        weight: server.weight,
+                        ..existing.clone()
+                    },
+                    None => server,
+                },
+            )
+            .collect();
+        *self.0.write().unwrap() = Arc::new(UpstreamSet {
+            servers,
+            ring: OnceLock::new(),
+        });
+        true
+    }
+}
+
+// 这是入口函数，根据算法选择不同的负载均衡策略
+pub fn select_upstream(route: &RouteConfig, req: &RequestInfo) -> Option<UpstreamServer> {
+    select_upstream_in(route, &route.upstreams, req)
+}
+
+// 在指定的上游组中选择（例如 API 版本自己的上游），算法等配置仍取自路由
+pub fn select_upstream_in(
+    route: &RouteConfig,
+    upstreams: &Upstreams,
+    req: &RequestInfo,
+) -> Option<UpstreamServer> {
+    select_from(route, upstreams, req, &mut rand::thread_rng())
+}
+
+// 随机数源可注入，测试时使用固定种子
+#[cfg(test)]
+fn select_upstream_with<R: Rng + ?Sized>(
+    route: &RouteConfig,
+    req: &RequestInfo,
+    rng: &mut R,
+) -> Option<UpstreamServer> {
+    select_from(route, &route.upstreams, req, rng)
+}
+
+fn select_from<R: Rng + ?Sized>(
+    route: &RouteConfig,
+    upstreams: &Upstreams,
+    req: &RequestInfo,
+    rng: &mut R,
+) -> Option<UpstreamServer> {
+    let set = upstreams.snapshot();
+    if set.servers.is_empty() {
+        return None;
+    }
+
+    // 健康检查标记为不健康的上游不参与选择；全部不健康时退回全量，总比直接 502 好
+    let all_down = !set.servers.iter().any(|s| s.health.is_healthy());
+    let usable = |s: &UpstreamServer| all_down || s.health.is_healthy();
+
+    // 会话保持优先：Cookie 指向的上游仍然存在且健康就继续使用
+    if let Some(upstream) = select_sticky(route, &set, req).filter(|s| usable(s)) {
+        return Some(upstream.clone());
+    }
+
+    let healthy: Vec<UpstreamServer>;
+    let upstreams = if set.servers.iter().all(usable) {
+        &set.servers[..]
+    } else {
+        healthy = set.servers.iter().filter(|s| usable(s)).cloned().collect();
+        &healthy[..]
+    };
+    let selected = match route.load_balance.as_str() {
+        "round_robin" => select_round_robin(upstreams),
+        "weighted" => select_weighted(upstreams),
+        "least_conn" => select_least_conn(upstreams),
+        "consistent_hash" => select_consistent_hash(route, &set, upstreams, req, usable),
+        "ip_hash" => select_ip_hash(upstreams, req),
+        "p2c" => select_two_choices(upstreams, rng, |s| s.in_flight() as f64),
+        "ewma" => {
+            let seed = mean_latency_ms(upstreams);
+            select_two_choices(upstreams, rng, |s| {
+                s.observed_latency_ms().unwrap_or(seed) * (s.in_flight() + 1) as f64
+            })
+        }
+        _ => select_round_robin(upstreams), // 默认使用轮询
+    };
+    selected.cloned()
+}
+
+// 实现轮询算法
+fn select_round_robin(upstreams: &[UpstreamServer]) -> Option<&UpstreamServer> {
+    // 使用静态原子计数器来跟踪轮询位置
+    static ROUND_ROBIN_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+    let current_index = ROUND_ROBIN_INDEX.fetch_add(1, Ordering::SeqCst);
+    let index = current_index % upstreams.len();
+
+    upstreams.get(index)
+}
+
+// 实现加权轮询算法
+fn select_weighted(upstreams: &[UpstreamServer]) -> Option<&UpstreamServer> {
+    // 简化实现：根据权重随机选择
+    // 你可以实现更复杂的算法
+    let total_weight: u32 = upstreams.iter().map(|s| s.weight).sum();
+
+    if total_weight == 0 {
+        return select_round_robin(upstreams);
+    }
+
+    let mut random_weight = (std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos()
+        % total_weight as u128) as u32;
+
+    for server in upstreams {
+        if random_weight < server.weight {
+            return Some(server);
+        }
+        random_weight -= server.weight;
+    }
+
+    // 兜底返回第一个
+    upstreams.first()
+}
+
+// 最少连接：选在途请求最少的上游；起点轮转，数量相同时请求轮流分配
+fn select_least_conn(upstreams: &[UpstreamServer]) -> Option<&UpstreamServer> {
+    static START: AtomicUsize = AtomicUsize::new(0);
+
+    let start = START.fetch_add(1, Ordering::Relaxed);
+    (0..upstreams.len())
+        .map(|i| &upstreams[(start + i) % upstreams.len()])
+        .min_by_key(|s| s.stats.in_flight())
+}
+
+// 已有样本的上游的平均延迟；新上游按它计算代价，既不会一直被选中也不会被饿死
+fn mean_latency_ms(upstreams: &[UpstreamServer]) -> f64 {
+    let observed: Vec<f64> = upstreams
+        .iter()
+        .filter_map(|s| s.stats.observed_latency_ms())
+        .collect();
+    if observed.is_empty() {
+        return 0.0;
+    }
+    observed.iter().sum::<f64>() / observed.len() as f64
+}
+
+// Power of two choices：随机挑两个候选，取代价更低的一个
+fn select_two_choices<'a, R, F>(
+    upstreams: &'a [UpstreamServer],
+    rng: &mut R,
+    cost: F,
+) -> Option<&'a UpstreamServer>
+where
+    R: Rng + ?Sized,
+    F: Fn(&UpstreamStats) -> f64,
+{
+    let n = upstreams.len();
+    if n == 1 {
+        return upstreams.first();
+    }
+
+    // 保证两个候选不同
+    let a = rng.gen_range(0..n);
+    let mut b = rng.gen_range(0..n - 1);
+    if b >= a {
+        b += 1;
+    }
+
+    let (first, second) = (&upstreams[a], &upstreams[b]);
+    if cost(&second.stats) < cost(&first.stats) {
+        Some(second)
+    } else {
+        Some(first)
+    }
+}
+
+// 一致性哈希：取不到哈希键时在可用上游中轮询
+// 哈希环始终按全量上游构建，上游不健康时顺时针跳到下一个可用节点，恢复后键回到原处
+fn select_consistent_hash<'a>(
+    route: &RouteConfig,
+    set: &'a UpstreamSet,
+    candidates: &'a [UpstreamServer],
+    req: &RequestInfo,
+    usable: impl Fn(&UpstreamServer) -> bool,
+) -> Option<&'a UpstreamServer> {
+    let (source, name, virtual_nodes) = match &route.hash {
+        Some(hash) => (hash.source, hash.name.as_deref(), hash.virtual_nodes),
+        None => (default_hash_source(), None, default_virtual_nodes()),
+    };
+
+    let key = match hash_key(source, name, req) {
+        Some(key) => key,
+        None => return select_round_robin(candidates),
+    };
+
+    let ring = set
+        .ring
+        .get_or_init(|| HashRing::new(&set.servers, virtual_nodes));
+    ring.get_where(&key, |index| usable(&set.servers[index]))
+        .and_then(|index| set.servers.get(index))
+}
+
+// IP 哈希：同一客户端 IP 固定落到同一个上游（上游数量变化时会大量重映射）
+// 和哈希环一样用 md5，映射不随 Rust 版本变化
+fn select_ip_hash<'a>(
+    upstreams: &'a [UpstreamServer],
+    req: &RequestInfo,
+) -> Option<&'a UpstreamServer> {
+    let ip = match req.client_ip {
+        Some(ip) => ip,
+        None => return select_round_robin(upstreams),
+    };
+
+    let hash = ketama_point(&md5::compute(ip.to_string()).0, 0);
+    upstreams.get(hash as usize % upstreams.len())
+}
+
+// 从请求中取出哈希键
+fn hash_key(source: HashSource, name: Option<&str>, req: &RequestInfo) -> Option<String> {
+    match source {
+        HashSource::Header => name.and_then(|n| req.header(n)).map(str::to_string),
+        HashSource::Cookie => name.and_then(|n| req.cookie(n)).map(str::to_string),
+        HashSource::Ip => req.client_ip.map(|ip| ip.to_string()),
+        HashSource::Path => Some(req.path.clone()),
+    }
+}
+
+// Ketama 哈希环：每个上游按权重分配若干虚拟节点
+#[derive(Debug)]
+pub struct HashRing {
+    points: Vec<(u32, usize)>, // (哈希点, 上游下标)，按哈希点排序
+}
+
+impl HashRing {
+    pub fn new(upstreams: &[UpstreamServer], virtual_nodes: u32) -> Self {
+        let total_weight: u32 = upstreams.iter().map(|s| s.weight).sum();
+        let mut points = Vec::new();
+
+        for (index, server) in upstreams.iter().enumerate() {
+            // 权重越大分到的虚拟节点越多；权重都为 0 时平均分配
+            let nodes = if total_weight == 0 {
+                virtual_nodes as u64
+            } else {
+                virtual_nodes as u64 * server.weight as u64 * upstreams.len() as u64
+                    / total_weight as u64
+            };
+
+            // 每个 md5 摘要产生 4 个哈希点
+            for i in 0..(nodes + 3) / 4 {
+                let digest = md5::compute(format!("{}-{}", server.url, i));
+                for h in 0..4 {
+                    points.push((ketama_point(&digest.0, h), index));
+                }
+            }
+        }
+
+        points.sort_unstable();
+        HashRing { points }
+    }
+
+    #[cfg(test)]
+    pub fn get(&self, key: &str) -> Option<usize> {
+        self.get_where(key, |_| true)
+    }
+
+    // 顺时针找到第一个不小于键哈希值、且上游满足条件的节点
+    pub fn get_where(&self, key: &str, accept: impl Fn(usize) -> bool) -> Option<usize> {
+        if self.points.is_empty() {
+            return None;
+        }
+
+        let hash = ketama_point(&md5::compute(key).0, 0);
+        let pos = self.points.partition_point(|(point, _)| *point < hash);
+        (0..self.points.len())
+            .map(|i| self.points[(pos + i) % self.points.len()].1)
+            .find(|index| accept(*index))
+    }
+}
+
+fn ketama_point(digest: &[u8; 16], h: usize) -> u32 {
+    u32::from_le_bytes([
+        digest[h * 4],
+        digest[h * 4 + 1],
+        digest[h * 4 + 2],
+        digest[h * 4 + 3],
+    ])
+}
+
+// 上游在会话 Cookie 中的标识：URL 的摘要，避免暴露内部地址
+fn sticky_id(upstream: &UpstreamServer) -> String {
+    let digest = md5::compute(&upstream.url);
+    digest.0[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn select_sticky<'a>(
+    route: &RouteConfig,
+    set: &'a UpstreamSet,
+    req: &RequestInfo,
+) -> Option<&'a UpstreamServer> {
+    let sticky = route.sticky.as_ref()?;
+    let id = req.cookie(&sticky.cookie)?;
+    set.servers.iter().find(|s| sticky_id(s) == id)
+}
+
+// 需要写回客户端的会话保持 Cookie；已经指向该上游时返回 None
+pub fn sticky_cookie(
+    route: &RouteConfig,
+    upstream: &UpstreamServer,
+    req: &RequestInfo,
+) -> Option<Cookie<'static>> {
+    let sticky = route.sticky.as_ref()?;
+    let id = sticky_id(upstream);
+    if req.cookie(&sticky.cookie) == Some(id.as_str()) {
+        return None;
+    }
+
+    let mut cookie = Cookie::build((sticky.cookie.clone(), id))
+        .path("/")
+        .http_only(true);
+    if let Some(ttl) = sticky.ttl {
+        cookie = cookie.max_age(rocket::time::Duration::seconds(ttl as i64));
+    }
+    Some(cookie.build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashSet;
+
+    fn upstreams(n: usize) -> Vec<UpstreamServer> {
+        (0..n)
+            .map(|i| UpstreamServer {
+                url: format!("http://10.0.0.{}:8080", i + 1),
+                weight: 1,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn route(load_balance: &str, upstreams: Vec<UpstreamServer>) -> RouteConfig {
+        RouteConfig {
+            path: "/api/*".to_string(),
+            method: "*".to_string(),
+            upstreams: upstreams.into(),
+            timeout: 30,
+            load_balance: load_balance.to_string(),
+            ewma_decay: default_ewma_decay(),
+            ..Default::default()
+        }
+    }
+
+    fn request_from(ip: &str) -> RequestInfo {
+        RequestInfo {
+            client_ip: Some(ip.parse().unwrap()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_hash_ring_is_deterministic() {
+        let servers = upstreams(5);
+        let ring = HashRing::new(&servers, 160);
+        for i in 0..100 {
+            let key = format!("user-{}", i);
+            assert_eq!(ring.get(&key), ring.get(&key));
+        }
+    }
+
+    #[test]
+    fn test_hash_ring_spreads_keys() {
+        let servers = upstreams(4);
+        let ring = HashRing::new(&servers, 160);
+        let mut counts = [0usize; 4];
+        for i in 0..10_000 {
+            counts[ring.get(&format!("user-{}", i)).unwrap()] += 1;
+        }
+        // 每个上游应该分到大约 1/4 的键
+        for count in counts {
+            assert!(count > 1_500 && count < 3_500, "unbalanced: {:?}", counts);
+        }
+    }
+
+    #[test]
+    fn test_hash_ring_remap_fraction_on_add() {
+        let keys = 10_000;
+        let before = HashRing::new(&upstreams(10), 160);
+        let after = HashRing::new(&upstreams(11), 160);
+
+        let moved = (0..keys)
+            .filter(|i| {
+                let key = format!("session-{}", i);
+                before.get(&key) != after.get(&key)
+            })
+            .count();
+        let fraction = moved as f64 / keys as f64;
+
+        // 理想值为 1/11 ≈ 0.09，取模哈希会接近 0.9
+        assert!(
+            fraction > 0.04 && fraction < 0.15,
+            "remap fraction {}",
+            fraction
+        );
+    }
+
+    #[test]
+    fn test_ip_hash_remap_fraction_on_add() {
+        let keys = 10_000;
+        let before = route("ip_hash", upstreams(10));
+        let after = route("ip_hash", upstreams(11));
+
+        let moved = (0..keys)
+            .filter(|i| {
+                let req = request_from(&format!("10.{}.{}.1", i / 256, i % 256));
+                select_upstream(&before, &req).unwrap().url
+                    != select_upstream(&after, &req).unwrap().url
+            })
+            .count();
+
+        // 取模哈希没有一致性，大部分键都会迁移
+        assert!(moved as f64 / keys as f64 > 0.5);
+    }
+
+    #[test]
+    fn test_ip_hash_is_stable() {
+        let r = route("ip_hash", upstreams(4));
+        let pick = |ip: &str| select_upstream(&r, &request_from(ip)).unwrap().url.clone();
+        // 固定的期望值：工具链升级后客户端仍然落到同一个上游
+        assert_eq!(pick("192.168.1.10"), "http://10.0.0.4:8080");
+        assert_eq!(pick("2001:db8::1"), "http://10.0.0.2:8080");
+    }
+
+    #[test]
+    fn test_consistent_hash_by_header() {
+        let mut r = route("consistent_hash", upstreams(5));
+        r.hash = Some(HashConfig {
+            source: HashSource::Header,
+            name: Some("X-User-Id".to_string()),
+            virtual_nodes: 160,
+        });
+
+        let req = RequestInfo {
+            headers: vec![("x-user-id".to_string(), "42".to_string())],
+            ..Default::default()
+        };
+        let first = select_upstream(&r, &req).unwrap().url.clone();
+        for _ in 0..10 {
+            assert_eq!(select_upstream(&r, &req).unwrap().url, first);
+        }
+    }
+
+    #[test]
+    fn test_sticky_cookie_survives_upstream_addition() {
+        let mut before = route("round_robin", upstreams(3));
+        before.sticky = Some(StickyConfig {
+            cookie: "gw_sticky".to_string(),
+            ttl: None,
+        });
+        let mut after = route("round_robin", upstreams(4));
+        after.sticky = Some(StickyConfig {
+            cookie: "gw_sticky".to_string(),
+            ttl: None,
+        });
+
+        let mut req = RequestInfo::default();
+        let chosen = select_upstream(&before, &req).unwrap();
+        let cookie = sticky_cookie(&before, &chosen, &req).unwrap();
+        req.cookies
+            .insert(cookie.name().to_string(), cookie.value().to_string());
+
+        // 新增上游后，带 Cookie 的会话仍然落到原来的上游
+        for _ in 0..10 {
+            assert_eq!(select_upstream(&after, &req).unwrap().url, chosen.url);
+        }
+        assert!(sticky_cookie(&after, &chosen, &req).is_none());
+    }
+
+    #[test]
+    fn test_p2c_prefers_fewer_in_flight() {
+        let r = route("p2c", upstreams(2));
+        let servers = r.upstreams.snapshot();
+        let _busy: Vec<_> = (0..5).map(|_| servers.servers[0].stats.start()).collect();
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let req = RequestInfo::default();
+        for _ in 0..100 {
+            let chosen = select_upstream_with(&r, &req, &mut rng).unwrap();
+            assert_eq!(chosen.url, servers.servers[1].url);
+        }
+    }
+
+    #[test]
+    fn test_least_conn_prefers_fewer_in_flight() {
+        let r = route("least_conn", upstreams(3));
+        let servers = r.upstreams.snapshot();
+        let _busy: Vec<_> = [0, 0, 2]
+            .iter()
+            .map(|&i| servers.servers[i].stats.start())
+            .collect();
+
+        let req = RequestInfo::default();
+        for _ in 0..10 {
+            assert_eq!(
+                select_upstream(&r, &req).unwrap().url,
+                servers.servers[1].url
+            );
+        }
+        // 数量相同时两边都会被选到
+        let _more = servers.servers[1].stats.start();
+        let picked: Vec<String> = (0..10)
+            .map(|_| select_upstream(&r, &req).unwrap().url.clone())
+            .collect();
+        assert!(picked.iter().all(|url| *url != servers.servers[0].url));
+        assert!(picked.contains(&servers.servers[1].url));
+        assert!(picked.contains(&servers.servers[2].url));
+    }
+
+    #[test]
+    fn test_replace_keeps_stats_of_remaining_upstreams() {
+        let r = route("p2c", upstreams(2));
+        let before = r.upstreams.snapshot();
+        let _busy = before.servers[1].stats.start();
+
+        assert!(!r.upstreams.replace(upstreams(2)));
+        assert!(r.upstreams.replace(upstreams(3)[1..].to_vec()));
+
+        let after = r.upstreams.snapshot();
+        assert_eq!(after.servers.len(), 2);
+        assert_eq!(after.servers[0].url, before.servers[1].url);
+        assert_eq!(after.servers[0].stats.in_flight(), 1);
+        assert_eq!(after.servers[1].stats.in_flight(), 0);
+        // 旧快照不受影响
+        assert_eq!(before.servers.len(), 2);
+    }
+
+    #[test]
+    fn test_unhealthy_upstream_receives_no_requests() {
+        let algorithms = [
+            "round_robin",
+            "weighted",
+            "least_conn",
+            "consistent_hash",
+            "ip_hash",
+            "p2c",
+            "ewma",
+        ];
+        let mut rng = StdRng::seed_from_u64(7);
+        for algorithm in algorithms {
+            let r = route(algorithm, upstreams(3));
+            let servers = r.upstreams.snapshot();
+            servers.servers[1].health.mark_down();
+
+            for i in 0..300 {
+                let req = request_from(&format!("192.168.{}.{}", i / 250, i % 250 + 1));
+                let chosen = select_upstream_with(&r, &req, &mut rng).unwrap();
+                assert_ne!(chosen.url, servers.servers[1].url, "{}", algorithm);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unhealthy_sticky_upstream_is_replaced() {
+        let mut r = route("round_robin", upstreams(2));
+        r.sticky = Some(StickyConfig {
+            cookie: "gw_sticky".to_string(),
+            ttl: None,
+        });
+        let servers = r.upstreams.snapshot();
+        servers.servers[0].health.mark_down();
+
+        let mut req = RequestInfo::default();
+        req.cookies
+            .insert("gw_sticky".to_string(), sticky_id(&servers.servers[0]));
+        for _ in 0..5 {
+            let chosen = select_upstream(&r, &req).unwrap();
+            assert_eq!(chosen.url, servers.servers[1].url);
+        }
+    }
+
+    #[test]
+    fn test_all_unhealthy_falls_back_to_every_upstream() {
+        let r = route("round_robin", upstreams(2));
+        for server in &r.upstreams.snapshot().servers {
+            server.health.mark_down();
+        }
+
+        let req = RequestInfo::default();
+        let picked: HashSet<String> = (0..4)
+            .map(|_| select_upstream(&r, &req).unwrap().url)
+            .collect();
+        assert_eq!(picked.len(), 2);
+    }
+
+    #[test]
+    fn test_in_flight_guard_releases() {
+        let stats = UpstreamStats::default();
+        {
+            let _a = stats.start();
+            let _b = stats.start();
+            assert_eq!(stats.in_flight(), 2);
+        }
+        assert_eq!(stats.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_ewma_avoids_slow_replica() {
+        let r = route("ewma", upstreams(3));
+        let servers = r.upstreams.snapshot();
+        let decay = Duration::from_secs(10);
+        servers.servers[0]
+            .stats
+            .observe(Duration::from_millis(500), decay);
+        servers.servers[1]
+            .stats
+            .observe(Duration::from_millis(10), decay);
+        servers.servers[2]
+            .stats
+            .observe(Duration::from_millis(12), decay);
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let req = RequestInfo::default();
+        let mut counts = [0usize; 3];
+        for _ in 0..1_000 {
+            let chosen = select_upstream_with(&r, &req, &mut rng).unwrap();
+            let index = servers
+                .servers
+                .iter()
+                .position(|s| s.url == chosen.url)
+                .unwrap();
+            counts[index] += 1;
+        }
+
+        // 两个候选总有一个更快，慢副本永远不会被选中
+        assert_eq!(counts[0], 0);
+        assert!(counts[1] > 0 && counts[2] > 0);
+    }
+
+    #[test]
+    fn test_ewma_seeds_unobserved_upstream_with_mean() {
+        let r = route("ewma", upstreams(3));
+        let servers = r.upstreams.snapshot();
+        let decay = Duration::from_secs(10);
+        servers.servers[0]
+            .stats
+            .observe(Duration::from_millis(10), decay);
+        servers.servers[1]
+            .stats
+            .observe(Duration::from_millis(1_000), decay);
+
+        let mut rng = StdRng::seed_from_u64(3);
+        let req = RequestInfo::default();
+        let mut counts = [0usize; 3];
+        for _ in 0..1_000 {
+            let chosen = select_upstream_with(&r, &req, &mut rng).unwrap();
+            let index = servers
+                .servers
+                .iter()
+                .position(|s| s.url == chosen.url)
+                .unwrap();
+            counts[index] += 1;
+        }
+
+        // 新上游按平均延迟（505ms）计算：输给快副本，赢过慢副本
+        assert_eq!(counts[1], 0);
+        assert!(counts[2] > 0 && counts[0] > counts[2]);
+    }
+
+    #[test]
+    fn test_two_choices_deterministic_with_seed() {
+        let r = route("ewma", upstreams(5));
+        let req = RequestInfo::default();
+        let pick = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..20)
+                .map(|_| {
+                    select_upstream_with(&r, &req, &mut rng)
+                        .unwrap()
+                        .url
+                        .clone()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(pick(1), pick(1));
+    }
+
+    #[test]
+    fn test_ewma_decays_towards_recent_latency() {
+        let stats = UpstreamStats::default();
+        let decay = Duration::from_secs(10);
+        let now = Instant::now();
+
+        stats.observe_at(Duration::from_millis(100), decay, now);
+        assert_eq!(stats.latency_ms(), 100.0);
+
+        // 间隔一个衰减常数：旧值权重为 e^-1
+        stats.observe_at(Duration::from_millis(10), decay, now + decay);
+        let expected = 100.0 * (-1.0f64).exp() + 10.0 * (1.0 - (-1.0f64).exp());
+        assert!((stats.latency_ms() - expected).abs() < 1e-6);
+
+        // 间隔很久之后几乎只剩最新样本
+        stats.observe_at(Duration::from_millis(10), decay, now + decay * 20);
+        assert!((stats.latency_ms() - 10.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,76 @@
+use rocket::{get, serde::json::Json, State};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// 网关运行指标：计数器 + 延迟统计
+// 内部用 Arc 共享，可以 clone 进后台任务（例如流量镜像）
+#[derive(Clone, Default)]
+pub struct Metrics {
+    inner: Arc<Mutex<MetricsInner>>,
+}
+
+#[derive(Default)]
+struct MetricsInner {
+    counters: BTreeMap<String, u64>,
+    latencies: BTreeMap<String, LatencyStats>,
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct LatencyStats {
+    count: u64,
+    total_ms: f64,
+    max_ms: f64,
+    avg_ms: f64,
+}
+
+#[derive(Serialize)]
+pub struct MetricsSnapshot {
+    counters: BTreeMap<String, u64>,
+    latencies: BTreeMap<String, LatencyStats>,
+}
+
+// 生成带标签的指标名，例如 mirror_requests_total{route="/api/*",status="200"}
+pub fn metric_key(name: &str, labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return name.to_string();
+    }
+
+    let labels = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, v))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("{}{{{}}}", name, labels)
+}
+
+impl Metrics {
+    pub fn incr(&self, key: String) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner.counters.entry(key).or_insert(0) += 1;
+    }
+
+    pub fn observe(&self, key: String, elapsed: Duration) {
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        let mut inner = self.inner.lock().unwrap();
+        let stats = inner.latencies.entry(key).or_default();
+        stats.count += 1;
+        stats.total_ms += ms;
+        stats.max_ms = stats.max_ms.max(ms);
+        stats.avg_ms = stats.total_ms / stats.count as f64;
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let inner = self.inner.lock().unwrap();
+        MetricsSnapshot {
+            counters: inner.counters.clone(),
+            latencies: inner.latencies.clone(),
+        }
+    }
+}
+
+#[get("/metrics")]
+pub fn metrics(metrics: &State<Metrics>) -> Json<MetricsSnapshot> {
+    Json(metrics.snapshot())
+}
@@ -0,0 +1,71 @@
+use crate::metrics::{metric_key, Metrics};
+use crate::proxy::UpstreamRequest;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+// 流量镜像配置：请求会额外复制一份发到 mirror 上游，响应直接丢弃
+#[derive(Debug, Deserialize)]
+pub struct MirrorConfig {
+    pub url: String,
+    #[serde(default = "default_percentage")]
+    pub percentage: f64, // 采样百分比 0-100
+    #[serde(default = "default_timeout")]
+    pub timeout: u64, // 镜像请求单独的超时（秒），不影响主请求
+}
+
+fn default_percentage() -> f64 {
+    100.0
+}
+
+fn default_timeout() -> u64 {
+    1
+}
+
+// 按采样百分比决定这次请求是否镜像
+fn sampled(percentage: f64) -> bool {
+    if percentage >= 100.0 {
+        return true;
+    }
+    if percentage <= 0.0 {
+        return false;
+    }
+    rand::random::<f64>() * 100.0 < percentage
+}
+
+// 异步发送镜像请求（fire-and-forget），不阻塞客户端响应
+pub fn spawn(
+    client: reqwest::Client,
+    mirror: &MirrorConfig,
+    route: &str,
+    request: UpstreamRequest,
+    metrics: Metrics,
+) {
+    if !sampled(mirror.percentage) {
+        return;
+    }
+
+    let url = mirror.url.clone();
+    let timeout = Duration::from_secs(mirror.timeout);
+    let route = route.to_string();
+
+    tokio::spawn(async move {
+        let start = Instant::now();
+        let status = match request.build(&client, &url, timeout).send().await {
+            Ok(response) => response.status().as_u16().to_string(),
+            Err(e) if e.is_timeout() => "timeout".to_string(),
+            Err(e) => {
+                log::debug!("Mirror request to {} failed: {}", url, e);
+                "error".to_string()
+            }
+        };
+
+        metrics.incr(metric_key(
+            "mirror_requests_total",
+            &[("route", &route), ("status", &status)],
+        ));
+        metrics.observe(
+            metric_key("mirror_latency", &[("route", &route)]),
+            start.elapsed(),
+        );
+    });
+}
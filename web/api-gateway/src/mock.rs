@@ -0,0 +1,230 @@
+use crate::error::GatewayError;
+use crate::transform::TemplateContext;
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// 模拟上游：不访问后端，直接返回配置好的响应，用于本地开发和测试
+#[derive(Debug, Deserialize)]
+pub struct MockConfig {
+    #[serde(default = "default_status")]
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>, // 值支持模板
+    #[serde(default)]
+    pub body: Option<String>, // 文本响应体，支持模板
+    #[serde(default)]
+    pub json: Option<Value>, // JSON 响应体，其中的字符串支持模板
+    #[serde(default)]
+    pub file: Option<PathBuf>, // 从文件读取响应体，每次请求重新读取，便于修改后直接生效
+    #[serde(default)]
+    pub latency_ms: u64, // 固定延迟
+    #[serde(default)]
+    pub jitter_ms: u64, // 在固定延迟上额外增加 0..jitter_ms 的随机延迟
+    #[serde(default)]
+    pub error_rate: f64, // 注入错误的百分比 0-100
+    #[serde(default = "default_error_status")]
+    pub error_status: u16,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+fn default_error_status() -> u16 {
+    500
+}
+
+// JSON 里的字符串逐个渲染，避免替换的值破坏 JSON 结构
+fn render_json(value: &Value, ctx: &TemplateContext) -> Value {
+    match value {
+        Value::String(s) => Value::String(ctx.render(s)),
+        Value::Array(items) => Value::Array(items.iter().map(|v| render_json(v, ctx)).collect()),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), render_json(v, ctx)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+impl MockConfig {
+    // 状态码必须是合法的 HTTP 状态码，否则响应发不出去
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, status) in [("status", self.status), ("error_status", self.error_status)] {
+            if !(100..=599).contains(&status) {
+                return Err(format!(
+                    "mock {} {} must be between 100 and 599",
+                    name, status
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn delay<R: Rng + ?Sized>(&self, rng: &mut R) -> Duration {
+        let jitter = if self.jitter_ms > 0 {
+            rng.gen_range(0..self.jitter_ms)
+        } else {
+            0
+        };
+        Duration::from_millis(self.latency_ms + jitter)
+    }
+
+    fn inject_error<R: Rng + ?Sized>(&self, rng: &mut R) -> bool {
+        self.error_rate > 0.0 && rng.gen::<f64>() * 100.0 < self.error_rate
+    }
+
+    // 优先级：file > json > body
+    async fn body(
+        &self,
+        ctx: &TemplateContext,
+    ) -> Result<(Vec<u8>, Option<&'static str>), GatewayError> {
+        if let Some(file) = &self.file {
+            let bytes = tokio::fs::read(file).await.map_err(|e| {
+                log::error!("Failed to read mock file {}: {}", file.display(), e);
+                GatewayError::Internal
+            })?;
+            // 文本文件同样支持模板，二进制内容原样返回
+            let body = match String::from_utf8(bytes) {
+                Ok(text) => ctx.render(&text).into_bytes(),
+                Err(e) => e.into_bytes(),
+            };
+            return Ok((body, None));
+        }
+        if let Some(json) = &self.json {
+            let body =
+                serde_json::to_vec(&render_json(json, ctx)).map_err(|_| GatewayError::Internal)?;
+            return Ok((body, Some("application/json")));
+        }
+        let body = self
+            .body
+            .as_deref()
+            .map(|b| ctx.render(b))
+            .unwrap_or_default();
+        Ok((body.into_bytes(), None))
+    }
+
+    pub async fn respond(
+        &self,
+        ctx: &TemplateContext,
+    ) -> Result<(u16, Vec<(String, String)>, Vec<u8>), GatewayError> {
+        let (delay, inject_error) = {
+            let mut rng = rand::thread_rng();
+            (self.delay(&mut rng), self.inject_error(&mut rng))
+        };
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+
+        if inject_error {
+            let body = serde_json::json!({ "error": "injected mock error" });
+            let headers = vec![("Content-Type".to_string(), "application/json".to_string())];
+            return Ok((self.error_status, headers, body.to_string().into_bytes()));
+        }
+
+        let (body, content_type) = self.body(ctx).await?;
+        let mut headers: Vec<(String, String)> = self
+            .headers
+            .iter()
+            .map(|(name, value)| (name.clone(), ctx.render(value)))
+            .collect();
+        let has_content_type = headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("Content-Type"));
+        if let (Some(content_type), false) = (content_type, has_content_type) {
+            headers.push(("Content-Type".to_string(), content_type.to_string()));
+        }
+
+        Ok((self.status, headers, body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::RequestInfo;
+    use crate::test_util::parse;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use serde_json::json;
+
+    fn context() -> TemplateContext {
+        TemplateContext::new(
+            &RequestInfo {
+                method: "GET".to_string(),
+                path: "/proxy/users/42".to_string(),
+                request_id: "req-1".to_string(),
+                ..Default::default()
+            },
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_json_body_with_template() {
+        let mock: MockConfig = parse(
+            r#"
+            status = 201
+            headers = { "X-Mock" = "${request_id}" }
+            json = { path = "${path}", tags = ["${method}"], count = 3 }
+            "#,
+        );
+        let (status, headers, body) = mock.respond(&context()).await.unwrap();
+
+        assert_eq!(status, 201);
+        assert!(headers.contains(&("X-Mock".to_string(), "req-1".to_string())));
+        assert!(headers.contains(&("Content-Type".to_string(), "application/json".to_string())));
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            json!({"path": "/proxy/users/42", "tags": ["GET"], "count": 3})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_file_body() {
+        let path = std::env::temp_dir().join(format!("mock-{}.txt", rand::random::<u64>()));
+        std::fs::write(&path, "hello ${method}").unwrap();
+        let mock = MockConfig {
+            file: Some(path.clone()),
+            ..parse("")
+        };
+
+        let (_, _, body) = mock.respond(&context()).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(body, b"hello GET");
+    }
+
+    #[test]
+    fn test_latency_and_error_injection() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mock: MockConfig = parse("latency_ms = 100\njitter_ms = 50\nerror_rate = 30.0");
+
+        let mut errors = 0;
+        for _ in 0..1000 {
+            let delay = mock.delay(&mut rng).as_millis();
+            assert!((100..150).contains(&delay));
+            if mock.inject_error(&mut rng) {
+                errors += 1;
+            }
+        }
+        assert!((250..350).contains(&errors), "errors = {}", errors);
+        assert!(!parse::<MockConfig>("").inject_error(&mut rng));
+    }
+
+    #[test]
+    fn test_status_range() {
+        assert!(parse::<MockConfig>("status = 204\nerror_status = 503")
+            .validate()
+            .is_ok());
+        assert!(parse::<MockConfig>("status = 0").validate().is_err());
+        assert!(parse::<MockConfig>("error_status = 1000")
+            .validate()
+            .is_err());
+    }
+}
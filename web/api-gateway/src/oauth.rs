@@ -0,0 +1,438 @@
+use crate::error::GatewayError;
+use crate::jwt::JwtConfig;
+use crate::request::RequestInfo;
+use crate::secret;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// 路由鉴权方式
+#[derive(Debug, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum RouteAuth {
+    // 不透明 Bearer token，通过 RFC 7662 内省接口校验
+    Introspection(IntrospectionConfig),
+    // 自包含的 JWT，网关用共享密钥本地校验签名和有效期
+    Jwt(JwtConfig),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IntrospectionConfig {
+    pub endpoint: String,
+    pub client_id: String,
+    pub client_secret: String, // "env:NAME" 表示从环境变量读取
+    #[serde(default = "default_cache_ttl")]
+    pub cache_ttl: u64, // 内省结果的缓存时间（秒），不超过 token 自身的 exp
+    #[serde(default = "default_negative_cache_ttl")]
+    pub negative_cache_ttl: u64, // 无效 token 的缓存时间（秒），随机 token 不会长时间占用缓存
+    #[serde(default = "default_cache_size")]
+    pub cache_size: usize, // 最多缓存的 token 数，满了先淘汰最久没用过的
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    #[serde(skip)]
+    cache: Mutex<TokenCache>,
+}
+
+fn default_cache_ttl() -> u64 {
+    60
+}
+
+fn default_negative_cache_ttl() -> u64 {
+    5
+}
+
+fn default_cache_size() -> usize {
+    10_000
+}
+
+// 内省结果缓存：token 的 SHA-256 -> 缓存项
+#[derive(Debug, Default)]
+struct TokenCache(HashMap<String, CachedToken>);
+
+#[derive(Debug)]
+struct CachedToken {
+    info: Option<TokenInfo>,
+    expires: Instant,
+    last_used: Instant,
+}
+
+impl TokenCache {
+    fn get(&mut self, key: &str, now: Instant) -> Option<Option<TokenInfo>> {
+        let entry = self.0.get_mut(key)?;
+        if entry.expires <= now {
+            self.0.remove(key);
+            return None;
+        }
+        entry.last_used = now;
+        Some(entry.info.clone())
+    }
+
+    // 满了先清掉过期项，仍然满就淘汰最久没用过的一项
+    fn insert(
+        &mut self,
+        key: String,
+        info: Option<TokenInfo>,
+        expires: Instant,
+        now: Instant,
+        capacity: usize,
+    ) {
+        if capacity == 0 {
+            return;
+        }
+        if !self.0.contains_key(&key) && self.0.len() >= capacity {
+            self.0.retain(|_, entry| entry.expires > now);
+            if self.0.len() >= capacity {
+                let oldest = self
+                    .0
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.last_used)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    self.0.remove(&oldest);
+                }
+            }
+        }
+        self.0.insert(
+            key,
+            CachedToken {
+                info,
+                expires,
+                last_used: now,
+            },
+        );
+    }
+}
+
+fn default_timeout() -> u64 {
+    5
+}
+
+// 鉴权通过的 token 信息
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TokenInfo {
+    pub subject: Option<String>,
+    pub client_id: Option<String>,
+    pub scopes: Vec<String>,
+    pub exp: Option<u64>,
+    pub claims: Value, // 已校验的声明：JWT 的 payload 或内省响应
+}
+
+#[derive(Deserialize)]
+struct IntrospectionResponse {
+    active: bool,
+    #[serde(default)]
+    sub: Option<String>,
+    #[serde(default)]
+    client_id: Option<String>,
+    #[serde(default)]
+    scope: Option<String>, // 空格分隔
+    #[serde(default)]
+    exp: Option<u64>,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+pub fn bearer_token(info: &RequestInfo) -> Option<&str> {
+    let value = info.header("Authorization")?;
+    let (scheme, token) = value.split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then(|| token.trim())
+        .filter(|t| !t.is_empty())
+}
+
+impl RouteAuth {
+    pub fn resolve_secrets(&mut self) -> Result<(), String> {
+        match self {
+            RouteAuth::Introspection(config) => secret::resolve(&mut config.client_secret),
+            RouteAuth::Jwt(config) => config.resolve_secrets(),
+        }
+    }
+
+    pub async fn authenticate(
+        &self,
+        client: &reqwest::Client,
+        info: &RequestInfo,
+    ) -> Result<TokenInfo, GatewayError> {
+        match self {
+            RouteAuth::Introspection(config) => {
+                let token = bearer_token(info).ok_or(GatewayError::Unauthorized)?;
+                config
+                    .introspect(client, token)
+                    .await?
+                    .ok_or(GatewayError::Unauthorized)
+            }
+            RouteAuth::Jwt(config) => {
+                let token = bearer_token(info).ok_or(GatewayError::Unauthorized)?;
+                config
+                    .verify(token, unix_now())
+                    .ok_or(GatewayError::Unauthorized)
+            }
+        }
+    }
+}
+
+impl IntrospectionConfig {
+    // 返回 None 表示 token 无效；有效结果按 cache_ttl 缓存，无效结果按 negative_cache_ttl 缓存
+    pub async fn introspect(
+        &self,
+        client: &reqwest::Client,
+        token: &str,
+    ) -> Result<Option<TokenInfo>, GatewayError> {
+        // 缓存键用 token 的哈希，内存里不保留原始 token
+        let key = hex::encode(Sha256::digest(token.as_bytes()));
+        let now = Instant::now();
+        if let Some(info) = self.cache.lock().unwrap().get(&key, now) {
+            return Ok(info);
+        }
+
+        let (response, claims) = client
+            .post(&self.endpoint)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&[("token", token), ("token_type_hint", "access_token")])
+            .timeout(Duration::from_secs(self.timeout))
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| {
+                log::warn!("Token introspection at {} failed: {}", self.endpoint, e);
+                GatewayError::UpstreamError("Token introspection failed".to_string())
+            })?
+            .json::<Value>()
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|claims| {
+                let response = serde_json::from_value::<IntrospectionResponse>(claims.clone())
+                    .map_err(|e| e.to_string())?;
+                Ok((response, claims))
+            })
+            .map_err(|e| {
+                log::warn!(
+                    "Invalid introspection response from {}: {}",
+                    self.endpoint,
+                    e
+                );
+                GatewayError::UpstreamError("Token introspection failed".to_string())
+            })?;
+
+        let info = self.token_info(response, claims, unix_now());
+        let ttl = match info.as_ref() {
+            Some(valid) => {
+                let ttl = Duration::from_secs(self.cache_ttl);
+                match valid.exp {
+                    Some(exp) => ttl.min(Duration::from_secs(exp.saturating_sub(unix_now()))),
+                    None => ttl,
+                }
+            }
+            None => Duration::from_secs(self.negative_cache_ttl),
+        };
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(key, info.clone(), now + ttl, now, self.cache_size);
+        Ok(info)
+    }
+
+    // active 为 false 或已过期都视为无效
+    fn token_info(
+        &self,
+        response: IntrospectionResponse,
+        claims: Value,
+        now: u64,
+    ) -> Option<TokenInfo> {
+        if !response.active || response.exp.is_some_and(|exp| exp <= now) {
+            return None;
+        }
+        Some(TokenInfo {
+            subject: response.sub,
+            client_id: response.client_id,
+            scopes: response
+                .scope
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(str::to_string)
+                .collect(),
+            exp: response.exp,
+            claims,
+        })
+    }
+}
+
+// 网关以 client credentials 方式取得 token，替换客户端的 Authorization 调用上游
+#[derive(Debug, Deserialize)]
+pub struct ClientCredentials {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String, // "env:NAME" 表示从环境变量读取
+    #[serde(default)]
+    pub scope: Option<String>,
+    #[serde(default = "default_refresh_skew")]
+    pub refresh_skew: u64, // 提前多少秒刷新，避免 token 在途中过期
+    #[serde(skip)]
+    token: tokio::sync::Mutex<Option<(String, Instant)>>, // (access_token, 过期时间)
+}
+
+fn default_refresh_skew() -> u64 {
+    30
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+impl ClientCredentials {
+    // 持锁获取，过期时只有一个请求去刷新，其余等待结果
+    pub async fn access_token(&self, client: &reqwest::Client) -> Result<String, GatewayError> {
+        let mut token = self.token.lock().await;
+        let skew = Duration::from_secs(self.refresh_skew);
+        if let Some((value, expires)) = token.as_ref() {
+            if Instant::now() + skew < *expires {
+                return Ok(value.clone());
+            }
+        }
+
+        let mut form = vec![("grant_type", "client_credentials")];
+        if let Some(scope) = &self.scope {
+            form.push(("scope", scope));
+        }
+        let response = client
+            .post(&self.token_url)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&form)
+            .timeout(Duration::from_secs(default_timeout()))
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| {
+                log::warn!(
+                    "Client credentials request to {} failed: {}",
+                    self.token_url,
+                    e
+                );
+                GatewayError::UpstreamError("Failed to obtain upstream access token".to_string())
+            })?
+            .json::<TokenResponse>()
+            .await
+            .map_err(|e| {
+                log::warn!("Invalid token response from {}: {}", self.token_url, e);
+                GatewayError::UpstreamError("Failed to obtain upstream access token".to_string())
+            })?;
+
+        // 没有 expires_in 时按一小时处理
+        let expires = Instant::now() + Duration::from_secs(response.expires_in.unwrap_or(3600));
+        *token = Some((response.access_token.clone(), expires));
+        Ok(response.access_token)
+    }
+
+    // 上游返回 401 时丢弃缓存的 token，下次请求重新获取
+    pub async fn invalidate(&self) {
+        *self.token.lock().await = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn introspection() -> IntrospectionConfig {
+        serde_json::from_value(serde_json::json!({
+            "endpoint": "http://127.0.0.1:1/introspect",
+            "client_id": "gateway",
+            "client_secret": "secret",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        let info = |value: &str| RequestInfo {
+            headers: vec![("authorization".to_string(), value.to_string())],
+            ..Default::default()
+        };
+        assert_eq!(bearer_token(&info("Bearer abc")), Some("abc"));
+        assert_eq!(bearer_token(&info("bearer  abc ")), Some("abc"));
+        assert_eq!(bearer_token(&info("Basic abc")), None);
+        assert_eq!(bearer_token(&info("Bearer ")), None);
+    }
+
+    #[test]
+    fn test_token_cache_evicts_least_recently_used() {
+        let mut cache = TokenCache::default();
+        let now = Instant::now();
+        let later = now + Duration::from_secs(60);
+        let info = |sub: &str| {
+            Some(TokenInfo {
+                subject: Some(sub.to_string()),
+                ..Default::default()
+            })
+        };
+
+        cache.insert("a".to_string(), info("a"), later, now, 2);
+        cache.insert(
+            "b".to_string(),
+            info("b"),
+            later,
+            now + Duration::from_secs(1),
+            2,
+        );
+        // 访问 a 之后 b 成为最久没用过的
+        assert_eq!(
+            cache.get("a", now + Duration::from_secs(2)),
+            Some(info("a"))
+        );
+        cache.insert(
+            "c".to_string(),
+            None,
+            later,
+            now + Duration::from_secs(3),
+            2,
+        );
+
+        assert_eq!(cache.0.len(), 2);
+        assert_eq!(cache.get("b", now + Duration::from_secs(4)), None);
+        assert_eq!(cache.get("c", now + Duration::from_secs(4)), Some(None));
+        // 过期项不再返回
+        assert_eq!(cache.get("a", later), None);
+    }
+
+    #[test]
+    fn test_token_info() {
+        let config = introspection();
+        let token_info = |json: Value, now: u64| {
+            config.token_info(serde_json::from_value(json.clone()).unwrap(), json, now)
+        };
+
+        let info = token_info(
+            serde_json::json!({
+                "active": true, "sub": "alice", "scope": "read write", "exp": 200, "roles": ["admin"]
+            }),
+            100,
+        )
+        .unwrap();
+        assert_eq!(info.subject.as_deref(), Some("alice"));
+        assert_eq!(info.scopes, vec!["read", "write"]);
+        assert_eq!(info.claims["roles"], serde_json::json!(["admin"]));
+
+        assert_eq!(
+            token_info(serde_json::json!({ "active": false }), 100),
+            None
+        );
+        // 内省服务说有效但已过期
+        assert_eq!(
+            token_info(serde_json::json!({ "active": true, "exp": 50 }), 100),
+            None
+        );
+    }
+}
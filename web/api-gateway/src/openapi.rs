@@ -0,0 +1,424 @@
+use crate::grpc::Protocol;
+use crate::{AppConfig, RouteConfig};
+use rocket::{get, serde::json::Json, State};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+// 文档的基本信息
+#[derive(Debug, Deserialize)]
+pub struct OpenApiConfig {
+    #[serde(default = "default_title")]
+    pub title: String,
+    #[serde(default = "default_version")]
+    pub version: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default = "default_spec_ttl")]
+    pub spec_ttl: u64, // 上游文档的缓存时间（秒），过期后下一次请求重新拉取
+}
+
+impl Default for OpenApiConfig {
+    fn default() -> Self {
+        OpenApiConfig {
+            title: default_title(),
+            version: default_version(),
+            description: None,
+            spec_ttl: default_spec_ttl(),
+        }
+    }
+}
+
+fn default_spec_ttl() -> u64 {
+    60
+}
+
+fn default_title() -> String {
+    "API Gateway".to_string()
+}
+
+fn default_version() -> String {
+    env!("CARGO_PKG_VERSION").to_string()
+}
+
+// 路由级的文档和请求校验配置
+#[derive(Debug, Default, Deserialize)]
+pub struct RouteOpenApi {
+    #[serde(default)]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub request_schema: Option<Value>, // 请求体的 JSON Schema
+    #[serde(default)]
+    pub validate: bool, // 按 request_schema 校验请求体，不通过直接返回 400
+    #[serde(default)]
+    pub spec_url: Option<String>, // 上游提供的 OpenAPI 文档，合并进网关文档
+    #[serde(default)]
+    pub upstream_prefix: String, // 上游文档中对应路由前缀的部分，合并时替换为网关路径
+    #[serde(skip)]
+    validator: OnceLock<Option<jsonschema::Validator>>, // 加载配置时编译
+}
+
+// 请求体校验失败的原因
+#[derive(Debug, PartialEq)]
+pub enum ValidationError {
+    InvalidJson,
+    Schema(String),
+}
+
+impl RouteOpenApi {
+    // 加载配置时编译 request_schema，写错的 schema 直接拒绝启动，而不是悄悄放行所有请求
+    pub fn prepare(&self) -> Result<(), String> {
+        let Some(schema) = &self.request_schema else {
+            return Ok(());
+        };
+        let validator = jsonschema::validator_for(schema)
+            .map_err(|e| format!("invalid request_schema: {}", e))?;
+        let _ = self.validator.set(Some(validator));
+        Ok(())
+    }
+
+    fn validator(&self) -> Option<&jsonschema::Validator> {
+        self.validator
+            .get_or_init(|| jsonschema::validator_for(self.request_schema.as_ref()?).ok())
+            .as_ref()
+    }
+
+    // 没有开启校验或没有 schema 时直接通过
+    pub fn validate_body(&self, body: &[u8]) -> Result<(), ValidationError> {
+        if !self.validate {
+            return Ok(());
+        }
+        let Some(validator) = self.validator() else {
+            return Ok(());
+        };
+
+        let value: Value =
+            serde_json::from_slice(body).map_err(|_| ValidationError::InvalidJson)?;
+        validator
+            .validate(&value)
+            .map_err(|e| ValidationError::Schema(e.to_string()))
+    }
+}
+
+const ALL_METHODS: &[&str] = &["get", "post", "put", "patch", "delete"];
+
+fn route_methods(route: &RouteConfig) -> Vec<String> {
+    if route.method == "*" {
+        return ALL_METHODS.iter().map(|m| m.to_string()).collect();
+    }
+    route
+        .method
+        .split('|')
+        .map(|m| m.trim().to_lowercase())
+        .filter(|m| ALL_METHODS.contains(&m.as_str()))
+        .collect()
+}
+
+// 路由在网关上的路径：通配路由用 {path} 参数表示剩余部分
+fn gateway_path(route: &RouteConfig) -> (String, bool) {
+    match route.path.strip_suffix("/*") {
+        Some(prefix) => (format!("/proxy{}/{{path}}", prefix), true),
+        None => (format!("/proxy{}", route.path), false),
+    }
+}
+
+fn route_operation(route: &RouteConfig, method: &str, wildcard: bool) -> Value {
+    let doc = route.openapi.as_ref();
+    let mut operation = Map::new();
+
+    if let Some(summary) = doc.and_then(|d| d.summary.as_ref()) {
+        operation.insert("summary".to_string(), json!(summary));
+    }
+    if let Some(description) = doc.and_then(|d| d.description.as_ref()) {
+        operation.insert("description".to_string(), json!(description));
+    }
+    if let Some(doc) = doc.filter(|d| !d.tags.is_empty()) {
+        operation.insert("tags".to_string(), json!(doc.tags));
+    }
+    if wildcard {
+        operation.insert(
+            "parameters".to_string(),
+            json!([{
+                "name": "path",
+                "in": "path",
+                "required": true,
+                "description": "Remaining path forwarded to the upstream",
+                "schema": { "type": "string" },
+            }]),
+        );
+    }
+
+    let has_body = matches!(method, "post" | "put" | "patch");
+    if let Some(schema) = doc
+        .and_then(|d| d.request_schema.as_ref())
+        .filter(|_| has_body)
+    {
+        operation.insert(
+            "requestBody".to_string(),
+            json!({
+                "required": true,
+                "content": { "application/json": { "schema": schema } },
+            }),
+        );
+    }
+
+    let responses = if route.aggregate.is_some() {
+        json!({ "200": { "description": "Aggregated response", "content": { "application/json": {} } } })
+    } else {
+        json!({ "default": { "description": "Upstream response" } })
+    };
+    operation.insert("responses".to_string(), responses);
+    Value::Object(operation)
+}
+
+fn info(config: Option<&OpenApiConfig>) -> Value {
+    let default = OpenApiConfig::default();
+    let config = config.unwrap_or(&default);
+    let mut info = json!({ "title": config.title, "version": config.version });
+    if let Some(description) = &config.description {
+        info["description"] = json!(description);
+    }
+    info
+}
+
+// 根据路由配置生成文档，不包含上游文档
+pub fn build_document(config: Option<&OpenApiConfig>, routes: &[RouteConfig]) -> Value {
+    let mut paths = Map::new();
+
+    // gRPC 路由不在 /proxy 下，不出现在 HTTP 文档中
+    for route in routes.iter().filter(|r| r.protocol == Protocol::Http) {
+        let (path, wildcard) = gateway_path(route);
+        let Value::Object(item) = paths
+            .entry(path)
+            .or_insert_with(|| Value::Object(Map::new()))
+        else {
+            continue;
+        };
+        for method in route_methods(route) {
+            let operation = route_operation(route, &method, wildcard);
+            // 多条路由声明同一路径和方法时，以先匹配到的为准
+            item.entry(method).or_insert(operation);
+        }
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": info(config),
+        "paths": paths,
+    })
+}
+
+// 把上游文档的路径改写成网关路径后合并；组件同名时保留已有的定义
+pub fn merge_upstream(document: &mut Value, route: &RouteConfig, upstream: &Value) {
+    let Some(doc) = &route.openapi else {
+        return;
+    };
+    let gateway_prefix = format!("/proxy{}", route.path.trim_end_matches("/*"));
+
+    if let Some(upstream_paths) = upstream.get("paths").and_then(Value::as_object) {
+        for (path, item) in upstream_paths {
+            // 不在路由前缀下的接口无法通过这条路由访问
+            let Some(rest) = path.strip_prefix(&doc.upstream_prefix) else {
+                continue;
+            };
+            if !rest.is_empty() && !rest.starts_with('/') {
+                continue;
+            }
+            let gateway = format!("{}{}", gateway_prefix, rest);
+            document["paths"][gateway] = item.clone();
+        }
+        // 上游文档已经描述了具体接口，去掉通配路径的占位条目
+        if route.path.ends_with("/*") {
+            let (placeholder, _) = gateway_path(route);
+            if let Some(paths) = document["paths"].as_object_mut() {
+                paths.remove(&placeholder);
+            }
+        }
+    }
+
+    if let Some(schemas) = upstream
+        .pointer("/components/schemas")
+        .and_then(Value::as_object)
+    {
+        if document.pointer("/components/schemas").is_none() {
+            document["components"]["schemas"] = json!({});
+        }
+        if let Some(target) = document["components"]["schemas"].as_object_mut() {
+            for (name, schema) in schemas {
+                target.entry(name.clone()).or_insert_with(|| schema.clone());
+            }
+        }
+    }
+}
+
+async fn fetch_spec(client: &reqwest::Client, url: &str, timeout: Duration) -> Option<Value> {
+    let response = client.get(url).timeout(timeout).send().await.ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    response.json().await.ok()
+}
+
+// 合并后的文档缓存；锁在拉取期间一直持有，缓存过期时并发请求只触发一次拉取
+#[derive(Default)]
+pub struct SpecCache(tokio::sync::Mutex<Option<(Instant, Value)>>);
+
+// 并发拉取所有上游文档，按路由顺序合并
+async fn assemble(config: &AppConfig) -> Value {
+    let mut document = build_document(config.openapi.as_ref(), &config.routes);
+    let client = reqwest::Client::new();
+
+    let handles: Vec<_> = config
+        .routes
+        .iter()
+        .enumerate()
+        .filter_map(|(index, route)| {
+            let url = route.openapi.as_ref()?.spec_url.clone()?;
+            let client = client.clone();
+            let timeout = Duration::from_secs(route.timeout);
+            Some((
+                index,
+                url.clone(),
+                tokio::spawn(async move { fetch_spec(&client, &url, timeout).await }),
+            ))
+        })
+        .collect();
+
+    for (index, url, handle) in handles {
+        // 上游文档拿不到时保留根据路由生成的条目
+        match handle.await.ok().flatten() {
+            Some(spec) => merge_upstream(&mut document, &config.routes[index], &spec),
+            None => log::warn!("Failed to fetch upstream OpenAPI spec from {}", url),
+        }
+    }
+    document
+}
+
+#[get("/openapi.json")]
+pub async fn openapi(config: &State<Arc<AppConfig>>, cache: &State<SpecCache>) -> Json<Value> {
+    let ttl = Duration::from_secs(
+        config
+            .openapi
+            .as_ref()
+            .map_or_else(default_spec_ttl, |c| c.spec_ttl),
+    );
+    let mut cached = cache.0.lock().await;
+    if let Some((fetched_at, document)) = cached.as_ref() {
+        if fetched_at.elapsed() < ttl {
+            return Json(document.clone());
+        }
+    }
+
+    let document = assemble(config).await;
+    *cached = Some((Instant::now(), document.clone()));
+    Json(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(path: &str, method: &str, openapi: Option<RouteOpenApi>) -> RouteConfig {
+        RouteConfig {
+            path: path.to_string(),
+            method: method.to_string(),
+            openapi,
+            ..Default::default()
+        }
+    }
+
+    fn schema_doc() -> RouteOpenApi {
+        RouteOpenApi {
+            request_schema: Some(json!({
+                "type": "object",
+                "required": ["name"],
+                "properties": { "name": { "type": "string" }, "age": { "type": "integer" } },
+            })),
+            validate: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_paths_from_routes() {
+        let routes = vec![
+            route("/users/*", "GET|POST", Some(schema_doc())),
+            route("/health-check", "*", None),
+        ];
+        let document = build_document(None, &routes);
+
+        let users = &document["paths"]["/proxy/users/{path}"];
+        assert!(users["get"]["requestBody"].is_null());
+        assert_eq!(
+            users["post"]["requestBody"]["content"]["application/json"]["schema"]["required"],
+            json!(["name"])
+        );
+        assert_eq!(users["get"]["parameters"][0]["name"], "path");
+
+        let item = document["paths"]["/proxy/health-check"]
+            .as_object()
+            .unwrap();
+        assert_eq!(item.len(), ALL_METHODS.len());
+    }
+
+    #[test]
+    fn test_merge_upstream_rewrites_prefix() {
+        let doc = RouteOpenApi {
+            spec_url: Some("http://users/openapi.json".to_string()),
+            upstream_prefix: "/v1/users".to_string(),
+            ..Default::default()
+        };
+        let route = route("/users/*", "GET", Some(doc));
+        let mut document = build_document(None, &[]);
+        let upstream = json!({
+            "paths": {
+                "/v1/users/{id}": { "get": { "operationId": "getUser" } },
+                "/v1/orders": { "get": {} },
+            },
+            "components": { "schemas": { "User": { "type": "object" } } },
+        });
+
+        merge_upstream(&mut document, &route, &upstream);
+
+        let paths = document["paths"].as_object().unwrap();
+        assert_eq!(paths["/proxy/users/{id}"]["get"]["operationId"], "getUser");
+        assert_eq!(paths.len(), 1);
+        assert!(document["components"]["schemas"]["User"].is_object());
+    }
+
+    #[test]
+    fn test_validate_body() {
+        let doc = schema_doc();
+        assert_eq!(doc.validate_body(br#"{"name":"a","age":3}"#), Ok(()));
+        assert!(matches!(
+            doc.validate_body(br#"{"age":"x"}"#),
+            Err(ValidationError::Schema(_))
+        ));
+        assert_eq!(
+            doc.validate_body(b"not json"),
+            Err(ValidationError::InvalidJson)
+        );
+
+        let disabled = RouteOpenApi {
+            validate: false,
+            ..schema_doc()
+        };
+        assert_eq!(disabled.validate_body(b"not json"), Ok(()));
+    }
+
+    #[test]
+    fn test_invalid_request_schema_rejected() {
+        assert!(schema_doc().prepare().is_ok());
+        let doc = RouteOpenApi {
+            request_schema: Some(json!({ "type": "no-such-type" })),
+            validate: true,
+            ..Default::default()
+        };
+        assert!(doc.prepare().unwrap_err().contains("request_schema"));
+    }
+}
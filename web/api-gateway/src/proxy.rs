@@ -0,0 +1,688 @@
+use crate::access::{self, AccessConfig};
+use crate::compression;
+use crate::error::GatewayError;
+use crate::fault::FaultAction;
+use crate::load_balancer::{self, select_upstream_in, Upstreams};
+use crate::metrics::{metric_key, Metrics};
+use crate::oauth::TokenInfo;
+use crate::openapi::ValidationError;
+use crate::request::RequestInfo;
+use crate::shutdown::Accepting;
+use crate::tenant::{Rejection as TenantRejection, TenancyConfig};
+use crate::transform::TemplateContext;
+use crate::versioning::{self, find_versioned_route};
+use crate::{mirror, AppConfig, RouteConfig};
+use rocket::data::{ByteUnit, Data};
+use rocket::http::{CookieJar, Status};
+use rocket::response::{self, Responder, Response};
+use rocket::{delete, get, patch, post, put, Request, State};
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// 不转发的逐跳头（hop-by-hop），以及由 HTTP 库自己计算的头
+const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "host",
+    "content-length",
+];
+
+// 请求体大小上限
+const MAX_BODY_SIZE: ByteUnit = ByteUnit::Mebibyte(10);
+
+// 发往上游的请求：主请求和镜像请求共用
+#[derive(Debug, Clone)]
+pub struct UpstreamRequest {
+    pub method: reqwest::Method,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl UpstreamRequest {
+    pub fn build(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        timeout: Duration,
+    ) -> reqwest::RequestBuilder {
+        let mut builder = client.request(self.method.clone(), url).timeout(timeout);
+        for (name, value) in &self.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        if !self.body.is_empty() {
+            builder = builder.body(self.body.clone());
+        }
+        builder
+    }
+}
+
+// 返回给客户端的响应：保留上游的状态码、头和原始字节
+pub struct ProxyResponse {
+    pub status: Status,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub truncate: bool, // 故障注入：响应没发完就关闭连接
+}
+
+impl<'r> Responder<'r, 'static> for ProxyResponse {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        // Rocket 拿不到底层 socket，做不到真正的 TCP RST：这里声明一个比实际内容长的
+        // Content-Length，hyper 发现响应体提前结束后直接关闭连接。
+        // 客户端最多收到响应头（取决于响应头是否已经写出），拿不到完整的响应
+        if self.truncate {
+            return Response::build()
+                .status(self.status)
+                .sized_body(Some(1), Cursor::new(Vec::new()))
+                .ok();
+        }
+
+        let mut builder = Response::build();
+        builder.status(self.status);
+        for (name, value) in self.headers {
+            builder.raw_header_adjoin(name, value);
+        }
+        builder.sized_body(self.body.len(), Cursor::new(self.body));
+        builder.ok()
+    }
+}
+
+fn is_hop_by_hop(name: &str) -> bool {
+    HOP_BY_HOP_HEADERS
+        .iter()
+        .any(|h| h.eq_ignore_ascii_case(name))
+}
+
+fn method_has_body(method: &str) -> bool {
+    matches!(method, "POST" | "PUT" | "PATCH")
+}
+
+async fn read_body(data: Data<'_>) -> Result<Vec<u8>, GatewayError> {
+    let body = data
+        .open(MAX_BODY_SIZE)
+        .into_bytes()
+        .await
+        .map_err(|_| GatewayError::InvalidRequest("Failed to read request body".to_string()))?;
+    if !body.is_complete() {
+        return Err(GatewayError::PayloadTooLarge);
+    }
+    Ok(body.into_inner())
+}
+
+#[get("/proxy/<path..>")]
+pub async fn proxy_get(
+    path: PathBuf,
+    config: &State<Arc<AppConfig>>,
+    metrics: &State<Metrics>,
+    info: RequestInfo,
+    cookies: &CookieJar<'_>,
+    _accepting: Accepting,
+) -> Result<ProxyResponse, GatewayError> {
+    forward(path, Vec::new(), config, metrics, info, cookies).await
+}
+
+#[post("/proxy/<path..>", data = "<body>")]
+pub async fn proxy_post(
+    path: PathBuf,
+    body: Data<'_>,
+    config: &State<Arc<AppConfig>>,
+    metrics: &State<Metrics>,
+    info: RequestInfo,
+    cookies: &CookieJar<'_>,
+    _accepting: Accepting,
+) -> Result<ProxyResponse, GatewayError> {
+    let body = read_body(body).await?;
+    forward(path, body, config, metrics, info, cookies).await
+}
+
+#[put("/proxy/<path..>", data = "<body>")]
+pub async fn proxy_put(
+    path: PathBuf,
+    body: Data<'_>,
+    config: &State<Arc<AppConfig>>,
+    metrics: &State<Metrics>,
+    info: RequestInfo,
+    cookies: &CookieJar<'_>,
+    _accepting: Accepting,
+) -> Result<ProxyResponse, GatewayError> {
+    let body = read_body(body).await?;
+    forward(path, body, config, metrics, info, cookies).await
+}
+
+#[patch("/proxy/<path..>", data = "<body>")]
+pub async fn proxy_patch(
+    path: PathBuf,
+    body: Data<'_>,
+    config: &State<Arc<AppConfig>>,
+    metrics: &State<Metrics>,
+    info: RequestInfo,
+    cookies: &CookieJar<'_>,
+    _accepting: Accepting,
+) -> Result<ProxyResponse, GatewayError> {
+    let body = read_body(body).await?;
+    forward(path, body, config, metrics, info, cookies).await
+}
+
+#[delete("/proxy/<path..>")]
+pub async fn proxy_delete(
+    path: PathBuf,
+    config: &State<Arc<AppConfig>>,
+    metrics: &State<Metrics>,
+    info: RequestInfo,
+    cookies: &CookieJar<'_>,
+    _accepting: Accepting,
+) -> Result<ProxyResponse, GatewayError> {
+    forward(path, Vec::new(), config, metrics, info, cookies).await
+}
+
+// IP 访问控制，拒绝时返回 403 并计数
+pub fn check_access(
+    access: Option<&AccessConfig>,
+    info: &RequestInfo,
+    scope: &str,
+    route: &str,
+    metrics: &Metrics,
+) -> Result<(), GatewayError> {
+    match access {
+        Some(access) if !access.permits(info.client_ip) => {
+            log::debug!("Access denied for {:?} ({} rules)", info.client_ip, scope);
+            metrics.incr(metric_key(
+                "access_denied_total",
+                &[("scope", scope), ("route", route)],
+            ));
+            Err(GatewayError::Forbidden)
+        }
+        _ => Ok(()),
+    }
+}
+
+// 普通路由：负载均衡选出一个上游并转发，返回上游的状态码、头和响应体
+pub async fn send_upstream(
+    route: &RouteConfig,
+    upstreams: &Upstreams,
+    info: &RequestInfo,
+    cookies: &CookieJar<'_>,
+    client: &reqwest::Client,
+    outgoing: UpstreamRequest,
+    metrics: &Metrics,
+) -> Result<(u16, Vec<(String, String)>, Vec<u8>), GatewayError> {
+    let upstream = select_upstream_in(route, upstreams, info).ok_or(GatewayError::NoUpstream)?;
+    // 会话保持：把选中的上游写回 Cookie
+    if let Some(cookie) = load_balancer::sticky_cookie(route, &upstream, info) {
+        cookies.add(cookie);
+    }
+
+    // 流量镜像：后台复制一份请求，不影响主请求
+    if let Some(mirror) = &route.mirror {
+        mirror::spawn(
+            client.clone(),
+            mirror,
+            &route.path,
+            outgoing.clone(),
+            metrics.clone(),
+        );
+    }
+
+    // 在途请求计数，请求结束（guard 被 drop）时自动减一
+    let _in_flight = upstream.stats.start();
+    let start = Instant::now();
+    let result = outgoing
+        .build(client, &upstream.url, Duration::from_secs(route.timeout)) // 使用负载均衡选择的上游
+        .send()
+        .await;
+
+    // 记录主上游的状态和延迟，便于与镜像上游对比
+    let status = match &result {
+        Ok(response) => response.status().as_u16().to_string(),
+        Err(e) if e.is_timeout() => "timeout".to_string(),
+        Err(_) => "error".to_string(),
+    };
+    metrics.incr(metric_key(
+        "upstream_requests_total",
+        &[("route", &route.path), ("status", &status)],
+    ));
+    metrics.observe(
+        metric_key("upstream_latency", &[("route", &route.path)]),
+        start.elapsed(),
+    );
+    upstream
+        .stats
+        .observe(start.elapsed(), Duration::from_secs(route.ewma_decay));
+
+    let response = result.map_err(|e| {
+        log::debug!("Upstream {} failed: {}", upstream.url, e);
+        GatewayError::from_upstream(&e)
+    })?;
+    let status = response.status().as_u16();
+    let headers: Vec<(String, String)> = response
+        .headers()
+        .iter()
+        .filter(|(name, _)| !is_hop_by_hop(name.as_str()))
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| GatewayError::from_upstream(&e))?
+        .to_vec();
+
+    Ok((status, headers, body))
+}
+
+// 网关根据鉴权结果填写的头
+pub const AUTH_HEADERS: [&str; 3] = ["X-Auth-Subject", "X-Auth-Client-Id", "X-Auth-Scope"];
+
+// 网关识别出的身份：租户和鉴权结果
+fn is_identity_header(name: &str) -> bool {
+    name.eq_ignore_ascii_case("X-Tenant-Id")
+        || AUTH_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(name))
+}
+
+pub fn admit_tenant<'a>(
+    tenancy: &'a TenancyConfig,
+    info: &RequestInfo,
+    token: Option<&TokenInfo>,
+    route: &str,
+    metrics: &Metrics,
+) -> Result<Option<&'a str>, GatewayError> {
+    match tenancy.admit(info, token, route) {
+        Ok(tenant) => {
+            if let Some(tenant) = tenant {
+                metrics.incr(metric_key(
+                    "tenant_requests_total",
+                    &[("tenant", tenant), ("route", route)],
+                ));
+            }
+            Ok(tenant)
+        }
+        Err((tenant, rejection)) => {
+            log::debug!("Tenant {:?} rejected on {}: {:?}", tenant, route, rejection);
+            metrics.incr(metric_key(
+                "tenant_rejected_total",
+                &[
+                    ("tenant", tenant.unwrap_or("")),
+                    ("reason", rejection.reason()),
+                ],
+            ));
+            Err(match rejection {
+                TenantRejection::Unidentified => GatewayError::Unauthorized,
+                TenantRejection::RouteNotAllowed => GatewayError::Forbidden,
+                TenantRejection::RateLimited => GatewayError::RateLimited,
+                TenantRejection::QuotaExceeded => GatewayError::QuotaExceeded,
+            })
+        }
+    }
+}
+
+// 网关识别出的租户和鉴权身份，以请求头告知上游
+pub fn identity_headers(tenant: Option<&str>, token: Option<&TokenInfo>) -> Vec<(String, String)> {
+    let mut headers = Vec::new();
+    if let Some(tenant) = tenant {
+        headers.push(("X-Tenant-Id".to_string(), tenant.to_string()));
+    }
+    if let Some(token) = token {
+        let identity = [
+            ("X-Auth-Subject", token.subject.clone()),
+            ("X-Auth-Client-Id", token.client_id.clone()),
+            ("X-Auth-Scope", Some(token.scopes.join(" "))),
+        ];
+        for (name, value) in identity {
+            if let Some(value) = value {
+                headers.push((name.to_string(), value));
+            }
+        }
+    }
+    headers
+}
+
+// 代理流水线：路由匹配 -> 请求转换 -> 调用上游（模拟、聚合或负载均衡）-> 响应转换
+async fn forward(
+    path: PathBuf,
+    body: Vec<u8>,
+    config: &AppConfig,
+    metrics: &Metrics,
+    info: RequestInfo,
+    cookies: &CookieJar<'_>,
+) -> Result<ProxyResponse, GatewayError> {
+    let request_path = format!("/{}", path.display());
+    let method = info.method.as_str();
+
+    // 查找匹配的路由
+    log::debug!(
+        "Looking for route: {} with method: {}",
+        request_path,
+        method
+    );
+    log::debug!("Available routes: {}", config.routes.len());
+
+    check_access(config.access.as_ref(), &info, "global", "", metrics)?;
+
+    let (route, path_version) = find_versioned_route(&config.routes, &request_path, method)
+        .ok_or(GatewayError::RouteNotFound)?;
+    log::debug!("Found matching route: {}", route.path);
+
+    // API 版本协商，选中的版本决定使用哪组上游
+    let version = match &route.versioning {
+        Some(versioning) => versioning.resolve(&info, path_version)?,
+        None => None,
+    };
+    if let Some((version, source)) = version {
+        metrics.incr(metric_key(
+            "api_version_requests_total",
+            &[
+                ("route", &route.path),
+                ("version", &version.name),
+                ("source", source.as_str()),
+            ],
+        ));
+    }
+    let upstreams = versioning::upstreams(route, version.map(|(v, _)| v));
+
+    check_access(route.access.as_ref(), &info, "route", &route.path, metrics)?;
+
+    let client = reqwest::Client::new();
+
+    // 客户端鉴权
+    let token = match &route.auth {
+        Some(auth) => Some(auth.authenticate(&client, &info).await.map_err(|e| {
+            metrics.incr(metric_key(
+                "auth_rejected_total",
+                &[("route", &route.path), ("code", e.code())],
+            ));
+            e
+        })?),
+        None => None,
+    };
+
+    // 访问控制：按路由和方法要求的角色、作用域授权
+    config
+        .rbac
+        .authorize(route, &info, token.as_ref())
+        .map_err(|e| {
+            metrics.incr(metric_key(
+                "authz_denied_total",
+                &[("route", &route.path), ("code", e.code())],
+            ));
+            e
+        })?;
+
+    // 故障注入：模拟网关自身的延迟、错误和断连
+    if let Some(policy) = route.fault.current() {
+        let injection = policy.decide(&info, &mut rand::thread_rng());
+        if let Some(delay) = injection.delay {
+            metrics.incr(metric_key(
+                "faults_injected_total",
+                &[("route", &route.path), ("fault", "delay")],
+            ));
+            tokio::time::sleep(delay).await;
+        }
+        match injection.action {
+            Some(FaultAction::Abort(status)) => {
+                metrics.incr(metric_key(
+                    "faults_injected_total",
+                    &[("route", &route.path), ("fault", "abort")],
+                ));
+                return Err(GatewayError::FaultInjected(status));
+            }
+            Some(FaultAction::Truncate) => {
+                metrics.incr(metric_key(
+                    "faults_injected_total",
+                    &[("route", &route.path), ("fault", "truncate")],
+                ));
+                return Ok(ProxyResponse {
+                    status: Status::Ok,
+                    headers: Vec::new(),
+                    body: Vec::new(),
+                    truncate: true,
+                });
+            }
+            None => {}
+        }
+    }
+
+    // 请求签名校验，使用客户端发来的原始请求体
+    if let Some(signature) = &route.signature {
+        if let Err(e) = signature.verify(&info, &body) {
+            log::debug!("Signature rejected on {}: {:?}", route.path, e);
+            metrics.incr(metric_key(
+                "signature_rejected_total",
+                &[("route", &route.path), ("reason", e.reason())],
+            ));
+            return Err(GatewayError::Unauthorized);
+        }
+    }
+
+    // 多租户：识别租户并检查路由权限、配额和限流；放在鉴权、授权和签名校验之后，
+    // 被拒绝的请求不计入租户的配额和限流
+    let tenant = match &config.tenancy {
+        Some(tenancy) => admit_tenant(tenancy, &info, token.as_ref(), &route.path, metrics)?,
+        None => None,
+    };
+
+    // 请求转换：透传客户端的头和体，再按路由规则修改
+    let ctx = TemplateContext::new(&info, token.as_ref());
+    let mut headers: Vec<(String, String)> = info
+        .headers
+        .iter()
+        .filter(|(name, _)| {
+            !is_hop_by_hop(name)
+                && !access::is_forwarding_header(name)
+                && !name.eq_ignore_ascii_case("X-Request-Id")
+        })
+        .cloned()
+        .collect();
+    headers.push(("X-Request-Id".to_string(), info.request_id.clone()));
+    // 租户由网关识别后告知上游，不信任客户端自带的值
+    if config.tenancy.is_some() {
+        headers.retain(|(name, _)| !name.eq_ignore_ascii_case("X-Tenant-Id"));
+    }
+    // 鉴权通过后把身份告知上游，同样不信任客户端自带的值
+    if route.auth.is_some() {
+        headers.retain(|(name, _)| !AUTH_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(name)));
+    }
+    headers.extend(identity_headers(tenant, token.as_ref()));
+    headers.extend(info.forwarding_headers.iter().cloned());
+
+    // 可续传上传：POST 只创建上传，文件由后续的 PATCH 分块提交，完成后再转发
+    if let Some(upload) = route.upload.as_ref().filter(|_| method == "POST") {
+        let kept = headers
+            .into_iter()
+            .filter(|(name, _)| is_identity_header(name) || access::is_forwarding_header(name))
+            .collect();
+        return upload
+            .create(
+                &config.uploads,
+                &route.path,
+                version.map(|(v, _)| v.name.as_str()),
+                &info,
+                kept,
+                metrics,
+            )
+            .await;
+    }
+    let mut body = body;
+
+    // 路由开启后，解压 gzip 请求体再交给转换和上游
+    let gzipped = info
+        .header("Content-Encoding")
+        .is_some_and(|e| e.eq_ignore_ascii_case("gzip"));
+    if route.decompress_request && gzipped {
+        body = compression::gunzip(&body, MAX_BODY_SIZE.as_u64()).map_err(|e| {
+            log::debug!("Failed to decompress request body: {}", e);
+            GatewayError::InvalidRequest("Invalid gzip request body".to_string())
+        })?;
+        headers.retain(|(name, _)| !name.eq_ignore_ascii_case("Content-Encoding"));
+    }
+
+    // 按路由的 schema 校验请求体，不合法的请求不转发给上游
+    if let Some(doc) = route.openapi.as_ref().filter(|_| method_has_body(method)) {
+        if let Err(e) = doc.validate_body(&body) {
+            log::debug!("Request body rejected on {}: {:?}", route.path, e);
+            metrics.incr(metric_key(
+                "request_validation_failed_total",
+                &[("route", &route.path)],
+            ));
+            let message = match e {
+                ValidationError::InvalidJson => "Request body is not valid JSON".to_string(),
+                ValidationError::Schema(detail) => format!("Request body is invalid: {}", detail),
+            };
+            return Err(GatewayError::InvalidRequest(message));
+        }
+    }
+
+    if let Some(transform) = &route.transform {
+        transform.request.apply_headers(&mut headers, &ctx);
+        transform.request.apply_json(&mut body, &ctx);
+    }
+
+    // 格式转换在 JSON 转换规则之后进行
+    if let Some(convert) = &route.convert {
+        convert
+            .convert_request(&mut headers, &mut body)
+            .map_err(|e| {
+                metrics.incr(metric_key(
+                    "conversion_errors_total",
+                    &[("route", &route.path), ("direction", "request")],
+                ));
+                GatewayError::InvalidRequest(format!(
+                    "Cannot convert request body to {}: {}",
+                    convert.upstream.name(),
+                    e
+                ))
+            })?;
+    }
+
+    // 上游鉴权：用网关自己的 token 替换客户端的 Authorization
+    if let Some(upstream_auth) = &route.upstream_auth {
+        let access_token = upstream_auth.access_token(&client).await?;
+        headers.retain(|(name, _)| !name.eq_ignore_ascii_case("Authorization"));
+        headers.push((
+            "Authorization".to_string(),
+            format!("Bearer {}", access_token),
+        ));
+    }
+
+    let outgoing = UpstreamRequest {
+        method: reqwest::Method::from_bytes(method.as_bytes())
+            .map_err(|_| GatewayError::MethodNotAllowed)?,
+        headers,
+        body,
+    };
+
+    // 并发限制：每个路由单独计数，慢上游只会占满自己的名额
+    let permit = match &route.concurrency {
+        Some(limiter) => match limiter.acquire().await {
+            Ok(permit) => {
+                if !permit.waited.is_zero() {
+                    metrics.observe(
+                        metric_key("concurrency_queue_wait", &[("route", &route.path)]),
+                        permit.waited,
+                    );
+                }
+                Some(permit)
+            }
+            Err(rejection) => {
+                log::debug!(
+                    "Concurrency limit reached on {}: {:?}",
+                    route.path,
+                    rejection
+                );
+                metrics.incr(metric_key(
+                    "concurrency_rejected_total",
+                    &[("route", &route.path), ("reason", rejection.reason())],
+                ));
+                return Err(GatewayError::ConcurrencyLimited);
+            }
+        },
+        None => None,
+    };
+
+    let result = if let Some(mock) = &route.mock {
+        mock.respond(&ctx).await.map(|response| {
+            metrics.incr(metric_key(
+                "mock_responses_total",
+                &[("route", &route.path), ("status", &response.0.to_string())],
+            ));
+            response
+        })
+    } else if let Some(aggregate) = &route.aggregate {
+        aggregate
+            .execute(
+                &client,
+                &route.path,
+                route.timeout,
+                &outgoing,
+                &ctx,
+                metrics,
+            )
+            .await
+            .map(|body| {
+                let headers = vec![("Content-Type".to_string(), "application/json".to_string())];
+                (200, headers, body)
+            })
+    } else {
+        send_upstream(route, upstreams, &info, cookies, &client, outgoing, metrics).await
+    };
+    // 超时、连接失败和 5xx 都算作上游过载的信号
+    if let Some(permit) = permit {
+        permit.finish(matches!(&result, Ok((status, _, _)) if *status < 500));
+    }
+    if let (Some(upstream_auth), Ok((401, _, _))) = (&route.upstream_auth, &result) {
+        upstream_auth.invalidate().await;
+    }
+    let (mut status, mut headers, mut body) = result?;
+
+    // 上游的 XML / 表单响应转回 JSON，再交给响应转换规则
+    if let Some(convert) = &route.convert {
+        convert
+            .convert_response(&mut headers, &mut body)
+            .map_err(|(format, e)| {
+                log::debug!(
+                    "Failed to convert {} response on {}: {}",
+                    format.name(),
+                    route.path,
+                    e
+                );
+                metrics.incr(metric_key(
+                    "conversion_errors_total",
+                    &[("route", &route.path), ("direction", "response")],
+                ));
+                GatewayError::UpstreamError(format!(
+                    "Cannot convert upstream {} response to JSON: {}",
+                    format.name(),
+                    e
+                ))
+            })?;
+    }
+
+    // 响应转换
+    if let Some(transform) = &route.transform {
+        transform.response.apply_headers(&mut headers, &ctx);
+        transform.response.apply_json(&mut body, &ctx);
+        status = transform.map_status(status);
+    }
+
+    // 告知客户端实际使用的版本，弃用版本附带 Deprecation / Sunset
+    if let Some((version, _)) = version {
+        headers.extend(version.response_headers());
+    }
+
+    // 响应压缩
+    if let Some(compression) = &config.compression {
+        compression.compress_response(info.header("Accept-Encoding"), &mut headers, &mut body);
+    }
+
+    Ok(ProxyResponse {
+        status: Status::new(status),
+        headers,
+        body,
+        truncate: false,
+    })
+}
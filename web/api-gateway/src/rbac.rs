@@ -0,0 +1,375 @@
+use crate::error::GatewayError;
+use crate::jwt::claim_list;
+use crate::oauth::TokenInfo;
+use crate::request::RequestInfo;
+use crate::secret;
+use crate::RouteConfig;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+
+// 基于角色的访问控制：角色来自 API Key 映射或已校验 token 的声明，作用域来自 token
+#[derive(Debug, Default, Deserialize)]
+pub struct RbacConfig {
+    #[serde(default)]
+    pub deny_by_default: bool, // 没有配置任何要求的路由也拒绝
+    #[serde(default = "default_api_key_header")]
+    pub api_key_header: String,
+    #[serde(default)]
+    pub api_keys: Vec<ApiKeyRoles>,
+    #[serde(default)]
+    pub jwt_roles_claim: Option<String>, // 从路由 auth 校验过的 token 声明中读取角色
+    #[serde(default)]
+    pub audit_file: Option<String>, // 审计日志（JSON Lines）；不配置时只写到日志的 audit target
+    #[serde(skip)]
+    audit: Mutex<Option<File>>,
+}
+
+fn default_api_key_header() -> String {
+    "X-API-Key".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiKeyRoles {
+    pub key: String,       // "env:NAME" 表示从环境变量读取
+    pub principal: String, // 审计日志里记录的名字，不记录 key 本身
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+// 访问要求：角色满足其一即可，作用域必须全部具备
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Requirement {
+    #[serde(default)]
+    pub required_roles: Vec<String>,
+    #[serde(default)]
+    pub required_scopes: Vec<String>,
+}
+
+// 按方法覆盖的访问要求，键为 HTTP 方法
+pub type MethodPolicies = BTreeMap<String, Requirement>;
+
+impl Requirement {
+    fn is_empty(&self) -> bool {
+        self.required_roles.is_empty() && self.required_scopes.is_empty()
+    }
+}
+
+// 经过认证的调用方
+#[derive(Debug, Default, PartialEq)]
+pub struct Principal {
+    pub id: String,
+    pub roles: Vec<String>,
+    pub scopes: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Decision {
+    Allow,
+    Unauthenticated, // 有访问要求但识别不出调用方
+    MissingRole,
+    MissingScope,
+    NoPolicy, // deny_by_default 下路由没有配置任何要求
+}
+
+impl Decision {
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Decision::Allow => "allowed",
+            Decision::Unauthenticated => "unauthenticated",
+            Decision::MissingRole => "missing_role",
+            Decision::MissingScope => "missing_scope",
+            Decision::NoPolicy => "no_policy",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AuditRecord<'a> {
+    timestamp: String,
+    request_id: &'a str,
+    principal: Option<&'a str>,
+    method: &'a str,
+    route: &'a str,
+    path: &'a str,
+    outcome: &'static str,
+    reason: &'static str,
+}
+
+// 路由的访问要求，方法级配置覆盖路由级；None 表示没有配置
+pub fn requirement<'a>(route: &'a RouteConfig, method: &str) -> Option<&'a Requirement> {
+    let overridden = route
+        .method_policies
+        .iter()
+        .find(|(m, _)| m.eq_ignore_ascii_case(method))
+        .map(|(_, r)| r);
+    overridden.or(Some(&route.authorization).filter(|r| !r.is_empty()))
+}
+
+impl RbacConfig {
+    pub fn resolve_secrets(&mut self) -> Result<(), String> {
+        self.api_keys
+            .iter_mut()
+            .try_for_each(|entry| secret::resolve(&mut entry.key))
+    }
+
+    // 依次从 API Key、网关鉴权得到的 token 中识别调用方；未经校验的 Authorization 头一律不用
+    pub fn principal(&self, info: &RequestInfo, token: Option<&TokenInfo>) -> Option<Principal> {
+        if let Some(key) = info.header(&self.api_key_header) {
+            if let Some(entry) = self.api_keys.iter().find(|entry| entry.key == key) {
+                return Some(Principal {
+                    id: entry.principal.clone(),
+                    roles: entry.roles.clone(),
+                    scopes: entry.scopes.clone(),
+                });
+            }
+        }
+
+        let token = token?;
+        let roles = self
+            .jwt_roles_claim
+            .as_ref()
+            .map(|claim| claim_list(&token.claims[claim.as_str()]))
+            .unwrap_or_default();
+        Some(Principal {
+            id: token
+                .subject
+                .clone()
+                .or_else(|| token.client_id.clone())
+                .unwrap_or_default(),
+            roles,
+            scopes: token.scopes.clone(),
+        })
+    }
+
+    pub fn decide(
+        &self,
+        requirement: Option<&Requirement>,
+        principal: Option<&Principal>,
+    ) -> Decision {
+        let Some(requirement) = requirement else {
+            return if self.deny_by_default {
+                Decision::NoPolicy
+            } else {
+                Decision::Allow
+            };
+        };
+        if requirement.is_empty() {
+            return Decision::Allow;
+        }
+        let Some(principal) = principal else {
+            return Decision::Unauthenticated;
+        };
+        if !requirement.required_roles.is_empty()
+            && !requirement
+                .required_roles
+                .iter()
+                .any(|r| principal.roles.contains(r))
+        {
+            return Decision::MissingRole;
+        }
+        if !requirement
+            .required_scopes
+            .iter()
+            .all(|s| principal.scopes.contains(s))
+        {
+            return Decision::MissingScope;
+        }
+        Decision::Allow
+    }
+
+    // 检查并记录审计日志；没有访问要求且放行的请求不记录
+    pub fn authorize(
+        &self,
+        route: &RouteConfig,
+        info: &RequestInfo,
+        token: Option<&TokenInfo>,
+    ) -> Result<Decision, GatewayError> {
+        let requirement = requirement(route, &info.method);
+        let principal = self.principal(info, token);
+        let decision = self.decide(requirement, principal.as_ref());
+        if requirement.is_some() || decision != Decision::Allow {
+            self.audit(&AuditRecord {
+                timestamp: Utc::now().to_rfc3339(),
+                request_id: &info.request_id,
+                principal: principal.as_ref().map(|p| p.id.as_str()),
+                method: &info.method,
+                route: &route.path,
+                path: &info.path,
+                outcome: if decision == Decision::Allow {
+                    "allow"
+                } else {
+                    "deny"
+                },
+                reason: decision.reason(),
+            });
+        }
+        match decision {
+            Decision::Allow => Ok(decision),
+            Decision::Unauthenticated => Err(GatewayError::Unauthorized),
+            _ => Err(GatewayError::Forbidden),
+        }
+    }
+
+    fn audit(&self, record: &AuditRecord) {
+        let Ok(line) = serde_json::to_string(record) else {
+            return;
+        };
+        log::info!(target: "audit", "{}", line);
+
+        let Some(path) = &self.audit_file else {
+            return;
+        };
+        let mut file = self.audit.lock().unwrap();
+        if file.is_none() {
+            match OpenOptions::new().create(true).append(true).open(path) {
+                Ok(opened) => *file = Some(opened),
+                Err(e) => {
+                    log::error!("Failed to open audit log {}: {}", path, e);
+                    return;
+                }
+            }
+        }
+        if let Some(f) = file.as_mut() {
+            if let Err(e) = writeln!(f, "{}", line) {
+                log::error!("Failed to write audit log {}: {}", path, e);
+                *file = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+    use base64::Engine;
+
+    fn request(method: &str, headers: &[(&str, &str)]) -> RequestInfo {
+        RequestInfo {
+            method: method.to_string(),
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    const POLICY: &str = r#"
+        path = "/orders/*"
+        method = "GET|DELETE"
+        timeout = 5
+        required_roles = ["reader", "admin"]
+        required_scopes = ["orders"]
+        method_policies.DELETE = { required_roles = ["admin"] }
+    "#;
+
+    #[test]
+    fn test_method_override() {
+        let route: RouteConfig = parse(POLICY);
+        assert_eq!(
+            requirement(&route, "GET").unwrap().required_roles,
+            vec!["reader", "admin"]
+        );
+        let delete = requirement(&route, "delete").unwrap();
+        assert_eq!(delete.required_roles, vec!["admin"]);
+        assert!(delete.required_scopes.is_empty());
+
+        let open: RouteConfig = parse("path = \"/\"\nmethod = \"GET\"\ntimeout = 5");
+        assert!(requirement(&open, "GET").is_none());
+    }
+
+    #[test]
+    fn test_decisions() {
+        let rbac: RbacConfig = parse(
+            r#"
+            [[api_keys]]
+            key = "k-reader"
+            principal = "reporting"
+            roles = ["reader"]
+            scopes = ["orders"]
+            "#,
+        );
+        let route: RouteConfig = parse(POLICY);
+        let decide = |method: &str, headers: &[(&str, &str)]| {
+            let info = request(method, headers);
+            let principal = rbac.principal(&info, None);
+            rbac.decide(requirement(&route, method), principal.as_ref())
+        };
+
+        assert_eq!(decide("GET", &[("X-API-Key", "k-reader")]), Decision::Allow);
+        assert_eq!(
+            decide("DELETE", &[("X-API-Key", "k-reader")]),
+            Decision::MissingRole
+        );
+        assert_eq!(decide("GET", &[]), Decision::Unauthenticated);
+        assert_eq!(
+            decide("GET", &[("X-API-Key", "unknown")]),
+            Decision::Unauthenticated
+        );
+
+        // token 的作用域不足
+        let token = TokenInfo {
+            subject: Some("alice".to_string()),
+            scopes: vec!["profile".to_string()],
+            ..Default::default()
+        };
+        let principal = rbac.principal(&request("GET", &[]), Some(&token)).unwrap();
+        assert_eq!(principal.id, "alice");
+        let with_role = Principal {
+            roles: vec!["reader".to_string()],
+            ..principal
+        };
+        assert_eq!(
+            rbac.decide(requirement(&route, "GET"), Some(&with_role)),
+            Decision::MissingScope
+        );
+    }
+
+    #[test]
+    fn test_jwt_roles_and_deny_by_default() {
+        let rbac: RbacConfig = parse("deny_by_default = true\njwt_roles_claim = \"roles\"");
+        let token = TokenInfo {
+            subject: Some("bob".to_string()),
+            scopes: vec!["orders".to_string(), "write".to_string()],
+            claims: serde_json::json!({ "sub": "bob", "roles": ["admin"] }),
+            ..Default::default()
+        };
+        let principal = rbac
+            .principal(&request("DELETE", &[]), Some(&token))
+            .unwrap();
+        assert_eq!(
+            principal,
+            Principal {
+                id: "bob".to_string(),
+                roles: vec!["admin".to_string()],
+                scopes: vec!["orders".to_string(), "write".to_string()],
+            }
+        );
+        assert_eq!(
+            rbac.decide(requirement(&parse(POLICY), "DELETE"), Some(&principal)),
+            Decision::Allow
+        );
+        assert_eq!(rbac.decide(None, Some(&principal)), Decision::NoPolicy);
+        // 显式配置为空的要求表示公开
+        assert_eq!(
+            rbac.decide(Some(&Requirement::default()), None),
+            Decision::Allow
+        );
+
+        // 没有经过网关校验的 JWT 不能用来识别调用方
+        let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .encode(r#"{"sub":"bob","roles":["admin"],"scope":"orders write"}"#);
+        let info = request(
+            "DELETE",
+            &[("Authorization", &format!("Bearer h.{}.s", payload))],
+        );
+        assert_eq!(rbac.principal(&info, None), None);
+    }
+}
@@ -0,0 +1,98 @@
+use crate::{access, AppConfig};
+use rocket::request::{FromRequest, Outcome, Request};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+// 客户端请求的上下文信息：负载均衡等环节需要读取头、Cookie、IP
+#[derive(Debug, Clone, Default)]
+pub struct RequestInfo {
+    pub method: String,
+    pub path: String,
+    pub query: Option<String>,     // 原始查询串
+    pub client_ip: Option<IpAddr>, // 经过受信代理解析后的真实客户端 IP
+    pub request_id: String,
+    pub headers: Vec<(String, String)>,
+    pub cookies: HashMap<String, String>,
+    pub forwarding_headers: Vec<(String, String)>, // 发往上游的 X-Forwarded-* 头
+}
+
+// 缓存在请求上，保证同一请求内各处拿到的 ID 一致
+struct RequestId(String);
+
+// 沿用客户端传入的 X-Request-Id，没有则生成一个
+pub fn request_id<'r>(req: &'r Request<'_>) -> &'r str {
+    &req.local_cache(|| {
+        let id = req
+            .headers()
+            .get_one("X-Request-Id")
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{:032x}", rand::random::<u128>()));
+        RequestId(id)
+    })
+    .0
+}
+
+impl RequestInfo {
+    // 头名大小写不敏感
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.cookies.get(name).map(|v| v.as_str())
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestInfo {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = req
+            .headers()
+            .iter()
+            .map(|h| (h.name().to_string(), h.value().to_string()))
+            .collect();
+        let cookies = req
+            .cookies()
+            .iter()
+            .map(|c| (c.name().to_string(), c.value().to_string()))
+            .collect();
+
+        let (trusted_proxies, forwarded_header) = req
+            .rocket()
+            .state::<Arc<AppConfig>>()
+            .map(|config| {
+                (
+                    config.server.trusted_proxies.as_slice(),
+                    config.server.forwarded_header,
+                )
+            })
+            .unwrap_or_default();
+        let peer_ip = req.remote().map(|addr| addr.ip());
+        let client_ip =
+            access::resolve_client_ip(peer_ip, req.headers(), trusted_proxies, forwarded_header);
+        let forwarding_headers = access::forwarding_headers(
+            client_ip,
+            peer_ip,
+            req.headers(),
+            trusted_proxies,
+            forwarded_header,
+        );
+
+        Outcome::Success(RequestInfo {
+            method: req.method().as_str().to_string(),
+            path: req.uri().path().to_string(),
+            query: req.uri().query().map(|q| q.to_string()),
+            client_ip,
+            request_id: request_id(req).to_string(),
+            headers,
+            cookies,
+            forwarding_headers,
+        })
+    }
+}
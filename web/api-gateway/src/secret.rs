@@ -0,0 +1,31 @@
+// 配置里的密钥："env:NAME" 表示从环境变量读取，其它值原样使用
+// 加载配置时统一解析，环境变量缺失直接拒绝启动，而不是悄悄退化成空密钥或关闭校验
+pub fn resolve(value: &mut String) -> Result<(), String> {
+    if let Some(name) = value.strip_prefix("env:") {
+        *value =
+            std::env::var(name).map_err(|_| format!("environment variable {} is not set", name))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let mut plain = "s3cret".to_string();
+        resolve(&mut plain).unwrap();
+        assert_eq!(plain, "s3cret");
+
+        std::env::set_var("GATEWAY_SECRET_TEST", "from-env");
+        let mut from_env = "env:GATEWAY_SECRET_TEST".to_string();
+        resolve(&mut from_env).unwrap();
+        assert_eq!(from_env, "from-env");
+
+        let mut missing = "env:GATEWAY_SECRET_TEST_MISSING".to_string();
+        assert!(resolve(&mut missing)
+            .unwrap_err()
+            .contains("GATEWAY_SECRET_TEST_MISSING"));
+    }
+}
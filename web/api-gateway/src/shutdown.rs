@@ -0,0 +1,187 @@
+use rocket::fairing::AdHoc;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::Request;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// 优雅停机配置
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShutdownConfig {
+    #[serde(default = "default_drain_delay")]
+    pub drain_delay: u64, // 收到信号后的摘流时间（秒）：健康检查报告 draining，新的代理请求返回 503
+    #[serde(default = "default_grace")]
+    pub grace: u32, // 停止接收新连接后，等待在途请求（包括流式响应）完成的时间（秒）
+    #[serde(default = "default_mercy")]
+    pub mercy: u32, // 宽限期结束后，关闭剩余连接的时间（秒）
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        ShutdownConfig {
+            drain_delay: default_drain_delay(),
+            grace: default_grace(),
+            mercy: default_mercy(),
+        }
+    }
+}
+
+fn default_drain_delay() -> u64 {
+    5
+}
+
+fn default_grace() -> u32 {
+    30
+}
+
+fn default_mercy() -> u32 {
+    5
+}
+
+// 网关是否正在摘流，/health 据此返回 draining
+#[derive(Clone, Default)]
+pub struct DrainState(Arc<AtomicBool>);
+
+impl DrainState {
+    pub fn is_draining(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    fn start(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+// 代理请求的守卫：摘流期间拒绝新请求（503），在途请求不受影响
+pub struct Accepting;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Accepting {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req.rocket().state::<DrainState>() {
+            Some(drain) if drain.is_draining() => Outcome::Error((Status::ServiceUnavailable, ())),
+            _ => Outcome::Success(Accepting),
+        }
+    }
+}
+
+impl ShutdownConfig {
+    // 信号由网关自己处理，Rocket 只负责 grace / mercy 阶段
+    pub fn rocket_shutdown(&self) -> rocket::config::Shutdown {
+        #[cfg_attr(not(unix), allow(unused_mut))]
+        let mut shutdown = rocket::config::Shutdown {
+            ctrlc: false,
+            grace: self.grace,
+            mercy: self.mercy,
+            ..Default::default()
+        };
+        #[cfg(unix)]
+        shutdown.signals.clear();
+        shutdown
+    }
+}
+
+async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut term = signal(SignalKind::terminate()).expect("failed to install SIGTERM handler");
+        tokio::select! {
+            _ = term.recv() => {}
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+// 收到 SIGTERM / Ctrl-C 后：先标记 draining，等待 drain_delay，再通知 Rocket 停机
+pub fn fairing(config: ShutdownConfig, drain: DrainState) -> AdHoc {
+    AdHoc::on_liftoff("Graceful Shutdown", move |rocket| {
+        Box::pin(async move {
+            let shutdown = rocket.shutdown();
+            tokio::spawn(async move {
+                wait_for_signal().await;
+                log::info!(
+                    "Shutdown signal received, draining for {}s",
+                    config.drain_delay
+                );
+                drain.start();
+
+                tokio::time::sleep(Duration::from_secs(config.drain_delay)).await;
+                log::info!(
+                    "Stopped accepting connections, waiting up to {}s for in-flight requests",
+                    config.grace
+                );
+                shutdown.notify();
+            });
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::settings;
+    use rocket::local::asynchronous::Client;
+    use serde_json::Value;
+
+    async fn gateway() -> Client {
+        let settings = settings(
+            r#"
+            [server]
+            host = "127.0.0.1"
+            port = 0
+            workers = 1
+
+            [logging]
+            level = "warn"
+            format = "text"
+
+            [[routes]]
+            path = "/mocked"
+            method = "GET"
+            timeout = 5
+            mock = { status = 200, body = "ok" }
+            "#,
+        );
+        let config = crate::parse_config(settings).unwrap();
+        Client::untracked(crate::rocket(config)).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_draining_rejects_new_requests() {
+        let client = gateway().await;
+        assert_eq!(client.get("/readyz").dispatch().await.status(), Status::Ok);
+        assert_eq!(
+            client.get("/proxy/mocked").dispatch().await.status(),
+            Status::Ok
+        );
+
+        client.rocket().state::<DrainState>().unwrap().start();
+
+        let response = client.get("/readyz").dispatch().await;
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        let body: Value = response.into_json().await.unwrap();
+        assert_eq!(body["status"], "draining");
+
+        let response = client.get("/proxy/mocked").dispatch().await;
+        assert_eq!(response.status(), Status::ServiceUnavailable);
+        let body: Value = response.into_json().await.unwrap();
+        assert_eq!(body["code"], "service_unavailable");
+        // 续传上传的分块同样被拒绝
+        let upload = format!("/uploads/{:032x}", 1);
+        assert_eq!(
+            client.patch(upload).dispatch().await.status(),
+            Status::ServiceUnavailable
+        );
+        // 存活探针不受影响，避免停机期间被重启
+        assert_eq!(client.get("/livez").dispatch().await.status(), Status::Ok);
+    }
+}
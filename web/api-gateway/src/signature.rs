@@ -0,0 +1,306 @@
+use crate::request::RequestInfo;
+use crate::secret;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// 路由级请求签名校验（HMAC-SHA256）
+#[derive(Debug, Deserialize)]
+pub struct SignatureConfig {
+    #[serde(default = "default_key_id_header")]
+    pub key_id_header: String,
+    #[serde(default = "default_signature_header")]
+    pub signature_header: String, // 十六进制的签名
+    #[serde(default = "default_timestamp_header")]
+    pub timestamp_header: String, // Unix 时间戳（秒）
+    #[serde(default = "default_nonce_header")]
+    pub nonce_header: String,
+    #[serde(default)]
+    pub signed_headers: Vec<String>, // 参与签名的请求头，按配置顺序拼接
+    #[serde(default = "default_max_skew")]
+    pub max_skew: u64, // 允许的时间偏差（秒）
+    #[serde(default)]
+    pub keys: BTreeMap<String, String>, // key ID = 密钥；"env:NAME" 表示从环境变量读取
+    #[serde(skip)]
+    nonces: Mutex<HashMap<String, u64>>, // 已使用的 nonce -> 过期时间
+}
+
+fn default_key_id_header() -> String {
+    "X-Key-Id".to_string()
+}
+
+fn default_signature_header() -> String {
+    "X-Signature".to_string()
+}
+
+fn default_timestamp_header() -> String {
+    "X-Timestamp".to_string()
+}
+
+fn default_nonce_header() -> String {
+    "X-Nonce".to_string()
+}
+
+fn default_max_skew() -> u64 {
+    300
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SignatureError {
+    MissingHeader,
+    UnknownKey,
+    Expired,
+    Invalid,
+    Replayed,
+}
+
+impl SignatureError {
+    // 用作指标标签
+    pub fn reason(&self) -> &'static str {
+        match self {
+            SignatureError::MissingHeader => "missing_header",
+            SignatureError::UnknownKey => "unknown_key",
+            SignatureError::Expired => "expired",
+            SignatureError::Invalid => "invalid",
+            SignatureError::Replayed => "replayed",
+        }
+    }
+}
+
+// 待签名字符串，每项一行：
+// METHOD / 路径 / 排序后的查询串 / 选定的头（name:value）/ 时间戳 / nonce / 请求体 SHA-256（十六进制）
+pub fn canonical_string(
+    method: &str,
+    path: &str,
+    query: Option<&str>,
+    headers: &[(String, String)],
+    timestamp: &str,
+    nonce: &str,
+    body: &[u8],
+) -> String {
+    let mut pairs: Vec<(&str, &str)> = query
+        .unwrap_or("")
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| p.split_once('=').unwrap_or((p, "")))
+        .collect();
+    pairs.sort_unstable();
+    let query = pairs
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let mut lines = vec![method.to_uppercase(), path.to_string(), query];
+    for (name, value) in headers {
+        lines.push(format!("{}:{}", name.to_lowercase(), value.trim()));
+    }
+    lines.push(timestamp.to_string());
+    lines.push(nonce.to_string());
+    lines.push(hex::encode(Sha256::digest(body)));
+    lines.join("\n")
+}
+
+fn now_unix() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+impl SignatureConfig {
+    pub fn resolve_secrets(&mut self) -> Result<(), String> {
+        self.keys.values_mut().try_for_each(secret::resolve)
+    }
+
+    pub fn verify(&self, info: &RequestInfo, body: &[u8]) -> Result<(), SignatureError> {
+        self.verify_at(info, body, now_unix())
+    }
+
+    // 校验顺序：必需头 -> key ID -> 时间窗口 -> 签名 -> nonce 重放
+    // 签名通过后才记录 nonce，避免伪造请求占满缓存
+    fn verify_at(&self, info: &RequestInfo, body: &[u8], now: u64) -> Result<(), SignatureError> {
+        let header = |name: &str| info.header(name).ok_or(SignatureError::MissingHeader);
+        let key_id = header(&self.key_id_header)?;
+        let signature = header(&self.signature_header)?;
+        let timestamp = header(&self.timestamp_header)?;
+        let nonce = header(&self.nonce_header)?;
+
+        let secret = self.keys.get(key_id).ok_or(SignatureError::UnknownKey)?;
+
+        let ts: u64 = timestamp.parse().map_err(|_| SignatureError::Expired)?;
+        if ts.abs_diff(now) > self.max_skew {
+            return Err(SignatureError::Expired);
+        }
+
+        let mut signed_headers = Vec::new();
+        for name in &self.signed_headers {
+            let value = info.header(name).ok_or(SignatureError::MissingHeader)?;
+            signed_headers.push((name.clone(), value.to_string()));
+        }
+        let canonical = canonical_string(
+            &info.method,
+            &info.path,
+            info.query.as_deref(),
+            &signed_headers,
+            timestamp,
+            nonce,
+            body,
+        );
+
+        let expected = hex::decode(signature).map_err(|_| SignatureError::Invalid)?;
+        let mut mac =
+            HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+        mac.update(canonical.as_bytes());
+        // verify_slice 是常量时间比较
+        mac.verify_slice(&expected)
+            .map_err(|_| SignatureError::Invalid)?;
+
+        let mut nonces = self.nonces.lock().unwrap();
+        nonces.retain(|_, expires| *expires >= now);
+        let cache_key = format!("{}:{}", key_id, nonce);
+        if nonces.contains_key(&cache_key) {
+            return Err(SignatureError::Replayed);
+        }
+        // 超出时间窗口后时间戳校验会拒绝，nonce 不用再保留
+        nonces.insert(cache_key, ts + self.max_skew);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    // 客户端的签名方式
+    fn sign(secret: &[u8], canonical: &str) -> String {
+        let mut mac = HmacSha256::new_from_slice(secret).unwrap();
+        mac.update(canonical.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn config() -> SignatureConfig {
+        SignatureConfig {
+            key_id_header: default_key_id_header(),
+            signature_header: default_signature_header(),
+            timestamp_header: default_timestamp_header(),
+            nonce_header: default_nonce_header(),
+            signed_headers: vec!["Content-Type".to_string()],
+            max_skew: default_max_skew(),
+            keys: BTreeMap::from([("partner-a".to_string(), "s3cret".to_string())]),
+            nonces: Default::default(),
+        }
+    }
+
+    fn signed_request(secret: &str, timestamp: u64, nonce: &str, body: &[u8]) -> RequestInfo {
+        let canonical = canonical_string(
+            "POST",
+            "/proxy/orders",
+            Some("b=2&a=1"),
+            &[("content-type".to_string(), "application/json".to_string())],
+            &timestamp.to_string(),
+            nonce,
+            body,
+        );
+        RequestInfo {
+            method: "POST".to_string(),
+            path: "/proxy/orders".to_string(),
+            query: Some("b=2&a=1".to_string()),
+            headers: vec![
+                ("Content-Type".to_string(), "application/json".to_string()),
+                ("X-Key-Id".to_string(), "partner-a".to_string()),
+                ("X-Timestamp".to_string(), timestamp.to_string()),
+                ("X-Nonce".to_string(), nonce.to_string()),
+                (
+                    "X-Signature".to_string(),
+                    sign(secret.as_bytes(), &canonical),
+                ),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_canonical_string_sorts_query() {
+        let canonical = canonical_string("get", "/a", Some("z=1&a=2&a=1"), &[], "1", "n", b"");
+        let lines: Vec<&str> = canonical.lines().collect();
+        assert_eq!(lines[0], "GET");
+        assert_eq!(lines[2], "a=1&a=2&z=1");
+        assert_eq!(
+            lines[5],
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn test_valid_signature() {
+        let body = br#"{"id":1}"#;
+        let req = signed_request("s3cret", NOW, "n-1", body);
+        assert_eq!(config().verify_at(&req, body, NOW), Ok(()));
+    }
+
+    #[test]
+    fn test_rejects_tampered_body_and_wrong_secret() {
+        let body = br#"{"id":1}"#;
+        let config = config();
+
+        let req = signed_request("s3cret", NOW, "n-1", body);
+        assert_eq!(
+            config.verify_at(&req, br#"{"id":2}"#, NOW),
+            Err(SignatureError::Invalid)
+        );
+
+        let req = signed_request("wrong", NOW, "n-2", body);
+        assert_eq!(
+            config.verify_at(&req, body, NOW),
+            Err(SignatureError::Invalid)
+        );
+    }
+
+    #[test]
+    fn test_rejects_clock_skew() {
+        let body = b"";
+        let req = signed_request("s3cret", NOW - 301, "n-1", body);
+        assert_eq!(
+            config().verify_at(&req, body, NOW),
+            Err(SignatureError::Expired)
+        );
+    }
+
+    #[test]
+    fn test_rejects_replayed_nonce() {
+        let body = b"";
+        let config = config();
+        let req = signed_request("s3cret", NOW, "n-1", body);
+        assert_eq!(config.verify_at(&req, body, NOW), Ok(()));
+        assert_eq!(
+            config.verify_at(&req, body, NOW + 1),
+            Err(SignatureError::Replayed)
+        );
+    }
+
+    #[test]
+    fn test_rejects_unknown_key_and_missing_headers() {
+        let body = b"";
+        let config = config();
+
+        let mut req = signed_request("s3cret", NOW, "n-1", body);
+        req.headers[1].1 = "partner-b".to_string();
+        assert_eq!(
+            config.verify_at(&req, body, NOW),
+            Err(SignatureError::UnknownKey)
+        );
+
+        let req = RequestInfo::default();
+        assert_eq!(
+            config.verify_at(&req, body, NOW),
+            Err(SignatureError::MissingHeader)
+        );
+    }
+}
@@ -0,0 +1,529 @@
+use crate::admin::Admin;
+use crate::oauth::TokenInfo;
+use crate::request::RequestInfo;
+use crate::AppConfig;
+use chrono::{NaiveDate, Utc};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::serde::json::Json;
+use rocket::{get, Build, Orbit, Rocket, State};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// 多租户：按 API Key、已校验 token 的声明或 Host 识别租户，分别限制可访问的路由、速率和每日配额
+#[derive(Debug, Deserialize)]
+pub struct TenancyConfig {
+    #[serde(default = "default_api_key_header")]
+    pub api_key_header: String,
+    #[serde(default)]
+    pub jwt_claim: Option<String>, // 声明值即租户名，取自路由 auth 校验过的 token
+    #[serde(default)]
+    pub required: bool, // 为 true 时拒绝无法识别租户的请求
+    #[serde(default)]
+    pub usage_file: Option<String>, // 用量持久化文件（JSON），重启后继续累计
+    #[serde(default = "default_flush_interval")]
+    pub flush_interval: u64, // 用量写盘间隔（秒）
+    #[serde(default)]
+    pub tenants: BTreeMap<String, TenantConfig>,
+    #[serde(skip)]
+    usage: Mutex<BTreeMap<String, Usage>>,
+    #[serde(skip)]
+    dirty: AtomicBool,
+}
+
+fn default_api_key_header() -> String {
+    "X-API-Key".to_string()
+}
+
+fn default_flush_interval() -> u64 {
+    10
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TenantConfig {
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    #[serde(default)]
+    pub routes: Vec<String>, // 允许访问的路由（填路由的 path），为空表示全部
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+    #[serde(default)]
+    pub daily_quota: Option<u64>, // 每天（UTC）允许的请求数
+    #[serde(skip)]
+    bucket: Mutex<Option<Bucket>>,
+}
+
+// 令牌桶限流
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimit {
+    pub requests_per_second: f64,
+    #[serde(default)]
+    pub burst: Option<u32>, // 桶容量，默认等于每秒请求数
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimit {
+    fn capacity(&self) -> f64 {
+        self.burst
+            .map(f64::from)
+            .unwrap_or(self.requests_per_second.ceil())
+            .max(1.0)
+    }
+
+    fn take(&self, bucket: &mut Option<Bucket>, now: Instant) -> bool {
+        let capacity = self.capacity();
+        let bucket = bucket.get_or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.requests_per_second).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// 单个租户的用量，持久化到 usage_file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub day: Option<NaiveDate>, // requests_today 所属的日期
+    pub requests_today: u64,
+    pub total_requests: u64,
+    pub rejected: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Rejection {
+    Unidentified,
+    RouteNotAllowed,
+    RateLimited,
+    QuotaExceeded,
+}
+
+impl Rejection {
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Rejection::Unidentified => "unidentified",
+            Rejection::RouteNotAllowed => "route_not_allowed",
+            Rejection::RateLimited => "rate_limited",
+            Rejection::QuotaExceeded => "quota_exceeded",
+        }
+    }
+}
+
+// 放行时为识别出的租户，拒绝时附带租户（如果识别出了）和原因
+pub type Admission<'a> = Result<Option<&'a str>, (Option<&'a str>, Rejection)>;
+
+impl TenancyConfig {
+    // 依次按 API Key、token 声明、Host 识别
+    pub fn identify(&self, info: &RequestInfo, token: Option<&TokenInfo>) -> Option<&str> {
+        if let Some(key) = info.header(&self.api_key_header) {
+            return self
+                .tenants
+                .iter()
+                .find(|(_, tenant)| tenant.api_keys.iter().any(|k| k == key))
+                .map(|(name, _)| name.as_str());
+        }
+
+        if let (Some(claim), Some(token)) = (&self.jwt_claim, token) {
+            let value = token.claims[claim.as_str()].as_str();
+            if let Some((name, _)) = value.and_then(|v| self.tenants.get_key_value(v)) {
+                return Some(name.as_str());
+            }
+        }
+
+        let host = info.header("Host")?;
+        let host = host.rsplit_once(':').map_or(host, |(h, _)| h);
+        self.tenants
+            .iter()
+            .find(|(_, tenant)| tenant.hosts.iter().any(|h| h.eq_ignore_ascii_case(host)))
+            .map(|(name, _)| name.as_str())
+    }
+
+    // 返回识别出的租户；未识别且不要求租户时返回 None 并放行
+    pub fn admit(
+        &self,
+        info: &RequestInfo,
+        token: Option<&TokenInfo>,
+        route: &str,
+    ) -> Admission<'_> {
+        self.admit_at(info, token, route, Utc::now().date_naive(), Instant::now())
+    }
+
+    fn admit_at(
+        &self,
+        info: &RequestInfo,
+        token: Option<&TokenInfo>,
+        route: &str,
+        today: NaiveDate,
+        now: Instant,
+    ) -> Admission<'_> {
+        let Some(name) = self.identify(info, token) else {
+            if self.required {
+                return Err((None, Rejection::Unidentified));
+            }
+            return Ok(None);
+        };
+        let tenant = &self.tenants[name];
+
+        let mut all_usage = self.usage.lock().unwrap();
+        let usage = all_usage.entry(name.to_string()).or_default();
+        if usage.day != Some(today) {
+            usage.day = Some(today);
+            usage.requests_today = 0;
+        }
+        self.dirty.store(true, Ordering::Relaxed);
+
+        let rejection = if !tenant.routes.is_empty() && !tenant.routes.iter().any(|r| r == route) {
+            Some(Rejection::RouteNotAllowed)
+        } else if tenant
+            .daily_quota
+            .is_some_and(|quota| usage.requests_today >= quota)
+        {
+            Some(Rejection::QuotaExceeded)
+        } else if tenant
+            .rate_limit
+            .as_ref()
+            .is_some_and(|limit| !limit.take(&mut tenant.bucket.lock().unwrap(), now))
+        {
+            Some(Rejection::RateLimited)
+        } else {
+            None
+        };
+
+        match rejection {
+            Some(rejection) => {
+                usage.rejected += 1;
+                Err((Some(name), rejection))
+            }
+            None => {
+                usage.requests_today += 1;
+                usage.total_requests += 1;
+                Ok(Some(name))
+            }
+        }
+    }
+
+    fn load(&self, path: &Path) {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+            Err(e) => {
+                log::error!("Failed to read tenant usage {}: {}", path.display(), e);
+                return;
+            }
+        };
+        match serde_json::from_str(&content) {
+            Ok(usage) => *self.usage.lock().unwrap() = usage,
+            Err(e) => log::error!("Invalid tenant usage file {}: {}", path.display(), e),
+        }
+    }
+
+    // 先写临时文件再改名，避免写到一半时进程退出留下损坏的文件
+    fn flush(&self, path: &Path) -> std::io::Result<()> {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let content = serde_json::to_vec_pretty(&*self.usage.lock().unwrap())?;
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, content)?;
+        std::fs::rename(&tmp, path)
+    }
+
+    fn flush_logged(&self, path: &Path) {
+        if let Err(e) = self.flush(path) {
+            self.dirty.store(true, Ordering::Relaxed);
+            log::error!("Failed to write tenant usage {}: {}", path.display(), e);
+        }
+    }
+}
+
+// 启动时加载用量，运行中定期写盘，停机时再写一次
+pub struct UsagePersistence;
+
+fn tenancy_of(config: &AppConfig) -> Option<(&TenancyConfig, &Path)> {
+    let tenancy = config.tenancy.as_ref()?;
+    let path = Path::new(tenancy.usage_file.as_ref()?);
+    Some((tenancy, path))
+}
+
+fn tenancy<P: rocket::Phase>(rocket: &Rocket<P>) -> Option<(&TenancyConfig, &Path)> {
+    tenancy_of(rocket.state::<Arc<AppConfig>>()?)
+}
+
+#[rocket::async_trait]
+impl Fairing for UsagePersistence {
+    fn info(&self) -> Info {
+        Info {
+            name: "Tenant Usage",
+            kind: Kind::Ignite | Kind::Liftoff | Kind::Shutdown,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        if let Some((tenancy, path)) = tenancy(&rocket) {
+            tenancy.load(path);
+        }
+        Ok(rocket)
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let Some(config) = rocket.state::<Arc<AppConfig>>().cloned() else {
+            return;
+        };
+        if tenancy(rocket).is_none() {
+            return;
+        }
+        tokio::spawn(async move {
+            let Some((tenancy, path)) = tenancy_of(&config) else {
+                return;
+            };
+            let mut interval =
+                tokio::time::interval(Duration::from_secs(tenancy.flush_interval.max(1)));
+            loop {
+                interval.tick().await;
+                tenancy.flush_logged(path);
+            }
+        });
+    }
+
+    async fn on_shutdown(&self, rocket: &Rocket<Orbit>) {
+        if let Some((tenancy, path)) = tenancy(rocket) {
+            tenancy.flush_logged(path);
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct TenantUsage {
+    tenant: String,
+    day: NaiveDate,
+    requests_today: u64,
+    daily_quota: Option<u64>,
+    remaining: Option<u64>,
+    total_requests: u64,
+    rejected: u64,
+}
+
+#[get("/admin/tenants")]
+pub fn usage(_admin: Admin, config: &State<Arc<AppConfig>>) -> Json<Vec<TenantUsage>> {
+    let Some(tenancy) = &config.tenancy else {
+        return Json(Vec::new());
+    };
+    let today = Utc::now().date_naive();
+    let usage = tenancy.usage.lock().unwrap();
+    Json(
+        tenancy
+            .tenants
+            .iter()
+            .map(|(name, tenant)| {
+                let usage = usage.get(name).cloned().unwrap_or_default();
+                // 记录的日期不是今天说明今天还没有请求
+                let requests_today = if usage.day == Some(today) {
+                    usage.requests_today
+                } else {
+                    0
+                };
+                TenantUsage {
+                    tenant: name.clone(),
+                    day: today,
+                    requests_today,
+                    daily_quota: tenant.daily_quota,
+                    remaining: tenant
+                        .daily_quota
+                        .map(|quota| quota.saturating_sub(requests_today)),
+                    total_requests: usage.total_requests,
+                    rejected: usage.rejected,
+                }
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+    use base64::Engine;
+
+    fn request(headers: &[(&str, &str)]) -> RequestInfo {
+        RequestInfo {
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, d).unwrap()
+    }
+
+    const TENANTS: &str = r#"
+        jwt_claim = "tenant"
+
+        [tenants.acme]
+        api_keys = ["key-acme"]
+        hosts = ["acme.example.com"]
+        routes = ["/orders"]
+        daily_quota = 2
+
+        [tenants.globex]
+        api_keys = ["key-globex"]
+        rate_limit = { requests_per_second = 1, burst = 2 }
+    "#;
+
+    #[test]
+    fn test_identify() {
+        let tenancy: TenancyConfig = parse(TENANTS);
+        let token = TokenInfo {
+            claims: serde_json::json!({ "tenant": "globex" }),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            tenancy.identify(&request(&[("x-api-key", "key-acme")]), None),
+            Some("acme")
+        );
+        assert_eq!(
+            tenancy.identify(&request(&[("X-API-Key", "nope")]), None),
+            None
+        );
+        assert_eq!(
+            tenancy.identify(&request(&[]), Some(&token)),
+            Some("globex")
+        );
+        assert_eq!(
+            tenancy.identify(&request(&[("Host", "ACME.example.com:8000")]), None),
+            Some("acme")
+        );
+        assert_eq!(
+            tenancy.identify(&request(&[("Host", "other.com")]), None),
+            None
+        );
+
+        // 未经网关校验的 JWT 不用来识别租户
+        let payload =
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(r#"{"tenant":"globex"}"#);
+        let bearer = format!("Bearer h.{}.s", payload);
+        assert_eq!(
+            tenancy.identify(&request(&[("Authorization", &bearer)]), None),
+            None
+        );
+    }
+
+    #[test]
+    fn test_routes_and_daily_quota() {
+        let tenancy: TenancyConfig = parse(TENANTS);
+        let now = Instant::now();
+        let acme = request(&[("X-API-Key", "key-acme")]);
+
+        assert_eq!(
+            tenancy.admit_at(&acme, None, "/users", day(1), now),
+            Err((Some("acme"), Rejection::RouteNotAllowed))
+        );
+        assert!(tenancy
+            .admit_at(&acme, None, "/orders", day(1), now)
+            .is_ok());
+        assert!(tenancy
+            .admit_at(&acme, None, "/orders", day(1), now)
+            .is_ok());
+        assert_eq!(
+            tenancy.admit_at(&acme, None, "/orders", day(1), now),
+            Err((Some("acme"), Rejection::QuotaExceeded))
+        );
+        // 第二天配额重置
+        assert!(tenancy
+            .admit_at(&acme, None, "/orders", day(2), now)
+            .is_ok());
+
+        let usage = tenancy.usage.lock().unwrap()["acme"].clone();
+        assert_eq!(usage.requests_today, 1);
+        assert_eq!(usage.total_requests, 3);
+        assert_eq!(usage.rejected, 2);
+
+        // 未识别的请求默认放行，required 时拒绝
+        assert_eq!(
+            tenancy.admit_at(&request(&[]), None, "/users", day(2), now),
+            Ok(None)
+        );
+        let required: TenancyConfig = parse("required = true");
+        assert_eq!(
+            required.admit_at(&request(&[]), None, "/users", day(2), now),
+            Err((None, Rejection::Unidentified))
+        );
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let tenancy: TenancyConfig = parse(TENANTS);
+        let globex = request(&[("X-API-Key", "key-globex")]);
+        let start = Instant::now();
+
+        assert!(tenancy
+            .admit_at(&globex, None, "/any", day(1), start)
+            .is_ok());
+        assert!(tenancy
+            .admit_at(&globex, None, "/any", day(1), start)
+            .is_ok());
+        assert_eq!(
+            tenancy.admit_at(&globex, None, "/any", day(1), start),
+            Err((Some("globex"), Rejection::RateLimited))
+        );
+        // 每秒补充一个令牌
+        let later = start + Duration::from_millis(1100);
+        assert!(tenancy
+            .admit_at(&globex, None, "/any", day(1), later)
+            .is_ok());
+        assert!(tenancy
+            .admit_at(&globex, None, "/any", day(1), later)
+            .is_err());
+    }
+
+    #[test]
+    fn test_usage_persistence() {
+        let path = std::env::temp_dir()
+            .join(format!("tenants-{}", rand::random::<u64>()))
+            .join("usage.json");
+        let tenancy: TenancyConfig = parse(TENANTS);
+        let acme = request(&[("X-API-Key", "key-acme")]);
+        tenancy
+            .admit_at(&acme, None, "/orders", day(1), Instant::now())
+            .unwrap();
+        tenancy.flush(&path).unwrap();
+
+        let restored: TenancyConfig = parse(TENANTS);
+        restored.load(&path);
+        assert_eq!(
+            restored.usage.lock().unwrap()["acme"],
+            Usage {
+                day: Some(day(1)),
+                requests_today: 1,
+                total_requests: 1,
+                rejected: 0,
+            }
+        );
+        // 没有新请求时不重写文件
+        assert!(!tenancy.dirty.load(Ordering::Relaxed));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,14 @@
+use serde::de::DeserializeOwned;
+
+// 单元测试共用：从 TOML 字符串构建配置源
+pub fn settings(toml: &str) -> config::Config {
+    config::Config::builder()
+        .add_source(config::File::from_str(toml, config::FileFormat::Toml))
+        .build()
+        .unwrap()
+}
+
+// 把 TOML 片段反序列化成任意配置结构
+pub fn parse<T: DeserializeOwned>(toml: &str) -> T {
+    settings(toml).try_deserialize().unwrap()
+}
@@ -0,0 +1,430 @@
+use crate::oauth::TokenInfo;
+use crate::request::RequestInfo;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+// 路由级的请求/响应转换规则
+#[derive(Debug, Default, Deserialize)]
+pub struct TransformConfig {
+    #[serde(default)]
+    pub request: MessageTransform, // 发往上游之前
+    #[serde(default)]
+    pub response: MessageTransform, // 返回客户端之前
+    #[serde(default)]
+    pub status_map: BTreeMap<String, u16>, // 响应状态码映射，例如 "404" = 200
+}
+
+// 对一条消息（请求或响应）的头和 JSON 体的修改
+#[derive(Debug, Default, Deserialize)]
+pub struct MessageTransform {
+    #[serde(default)]
+    pub add_headers: BTreeMap<String, String>, // 值支持模板，例如 "${client_ip}"
+    #[serde(default)]
+    pub remove_headers: Vec<String>,
+    #[serde(default)]
+    pub rename_headers: BTreeMap<String, String>, // 旧名 = 新名
+    #[serde(default)]
+    pub inject_json: BTreeMap<String, Value>, // 字段路径（用 . 分隔）= 值，字符串值支持模板
+    #[serde(default)]
+    pub remove_json: Vec<String>, // 要删除的字段路径
+}
+
+// 模板变量的取值来源
+// 支持 ${client_ip}、${request_id}、${method}、${path}、${header.<name>}、${query.<name>}、${jwt.<claim>}
+pub struct TemplateContext {
+    client_ip: String,
+    request_id: String,
+    method: String,
+    path: String,
+    query: Option<String>,
+    headers: Vec<(String, String)>,
+    claims: Option<Value>,
+}
+
+impl TemplateContext {
+    // ${jwt.*} 只取路由 auth 校验过的 token 声明，没有时为空
+    pub fn new(info: &RequestInfo, token: Option<&TokenInfo>) -> Self {
+        TemplateContext {
+            client_ip: info.client_ip.map(|ip| ip.to_string()).unwrap_or_default(),
+            request_id: info.request_id.clone(),
+            method: info.method.clone(),
+            path: info.path.clone(),
+            query: info.query.clone(),
+            headers: info.headers.clone(),
+            claims: token.map(|t| t.claims.clone()),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> String {
+        match name {
+            "client_ip" => self.client_ip.clone(),
+            "request_id" => self.request_id.clone(),
+            "method" => self.method.clone(),
+            "path" => self.path.clone(),
+            _ => {
+                if let Some(header) = name.strip_prefix("header.") {
+                    return self
+                        .headers
+                        .iter()
+                        .find(|(k, _)| k.eq_ignore_ascii_case(header))
+                        .map(|(_, v)| v.clone())
+                        .unwrap_or_default();
+                }
+                if let Some(param) = name.strip_prefix("query.") {
+                    return self
+                        .query
+                        .as_deref()
+                        .unwrap_or("")
+                        .split('&')
+                        .filter_map(|pair| pair.split_once('=').or(Some((pair, ""))))
+                        .find(|(k, _)| *k == param)
+                        .map(|(_, v)| v.to_string())
+                        .unwrap_or_default();
+                }
+                match (name.strip_prefix("jwt."), &self.claims) {
+                    (Some(claim), Some(claims)) => match claims.get(claim) {
+                        Some(Value::String(s)) => s.clone(),
+                        Some(Value::Null) | None => String::new(),
+                        Some(other) => other.to_string(),
+                    },
+                    _ => String::new(),
+                }
+            }
+        }
+    }
+
+    // 替换模板中的 ${...}，未知变量替换为空字符串
+    pub fn render(&self, template: &str) -> String {
+        self.render_with(template, |_, value| value)
+    }
+
+    // 用于 URL 模板：变量值整体百分号编码，不能借 / ? # & 改写路径或查询参数
+    // query.* 的原始值已经是编码过的，先解码再编码，避免二次编码
+    pub fn render_url(&self, template: &str) -> String {
+        self.render_with(template, |name, value| {
+            let value = match name.strip_prefix("query.") {
+                Some(_) => form_urlencoded::parse(format!("v={}", value).as_bytes())
+                    .next()
+                    .map(|(_, v)| v.into_owned())
+                    .unwrap_or_default(),
+                None => value,
+            };
+            percent_encode(&value)
+        })
+    }
+
+    fn render_with(&self, template: &str, escape: impl Fn(&str, String) -> String) -> String {
+        let mut output = String::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find("${") {
+            let Some(end) = rest[start..].find('}') else {
+                break;
+            };
+            output.push_str(&rest[..start]);
+            let name = &rest[start + 2..start + end];
+            output.push_str(&escape(name, self.lookup(name)));
+            rest = &rest[start + end + 1..];
+        }
+
+        output.push_str(rest);
+        output
+    }
+}
+
+// 除 RFC 3986 的非保留字符外全部编码
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+impl MessageTransform {
+    // 顺序：删除 -> 重命名 -> 添加（添加会覆盖同名头）
+    pub fn apply_headers(&self, headers: &mut Vec<(String, String)>, ctx: &TemplateContext) {
+        headers.retain(|(name, _)| {
+            !self
+                .remove_headers
+                .iter()
+                .any(|r| r.eq_ignore_ascii_case(name))
+        });
+
+        for (name, _) in headers.iter_mut() {
+            if let Some((_, new_name)) = self
+                .rename_headers
+                .iter()
+                .find(|(old, _)| old.eq_ignore_ascii_case(name))
+            {
+                *name = new_name.clone();
+            }
+        }
+
+        for (name, template) in &self.add_headers {
+            headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+            headers.push((name.clone(), ctx.render(template)));
+        }
+    }
+
+    // 只处理 JSON 对象体；无法解析时保持原样
+    pub fn apply_json(&self, body: &mut Vec<u8>, ctx: &TemplateContext) {
+        if self.inject_json.is_empty() && self.remove_json.is_empty() {
+            return;
+        }
+
+        let Ok(mut value) = serde_json::from_slice::<Value>(body) else {
+            return;
+        };
+        if !value.is_object() {
+            return;
+        }
+
+        for path in &self.remove_json {
+            remove_path(&mut value, path);
+        }
+        for (path, injected) in &self.inject_json {
+            let injected = match injected {
+                Value::String(s) => Value::String(ctx.render(s)),
+                other => other.clone(),
+            };
+            insert_path(&mut value, path, injected);
+        }
+
+        if let Ok(bytes) = serde_json::to_vec(&value) {
+            *body = bytes;
+        }
+    }
+}
+
+impl TransformConfig {
+    // 映射的两端都必须是合法的 HTTP 状态码，键写成其它形式永远匹配不上
+    pub fn validate(&self) -> Result<(), String> {
+        for (from, to) in &self.status_map {
+            let valid_from = from
+                .parse::<u16>()
+                .is_ok_and(|s| (100..=599).contains(&s) && s.to_string() == *from);
+            if !valid_from {
+                return Err(format!(
+                    "transform status_map key \"{}\" must be a status code between 100 and 599",
+                    from
+                ));
+            }
+            if !(100..=599).contains(to) {
+                return Err(format!(
+                    "transform status_map value {} must be between 100 and 599",
+                    to
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn map_status(&self, status: u16) -> u16 {
+        self.status_map
+            .get(&status.to_string())
+            .copied()
+            .unwrap_or(status)
+    }
+}
+
+fn remove_path(value: &mut Value, path: &str) {
+    let mut parts: Vec<&str> = path.split('.').collect();
+    let Some(last) = parts.pop() else {
+        return;
+    };
+
+    let mut current = value;
+    for part in parts {
+        match current.get_mut(part) {
+            Some(next) => current = next,
+            None => return,
+        }
+    }
+    if let Some(object) = current.as_object_mut() {
+        object.remove(last);
+    }
+}
+
+// 中间缺少的对象会自动创建
+pub fn insert_path(value: &mut Value, path: &str, injected: Value) {
+    let mut parts: Vec<&str> = path.split('.').collect();
+    let Some(last) = parts.pop() else {
+        return;
+    };
+
+    let mut current = value;
+    for part in parts {
+        let Some(object) = current.as_object_mut() else {
+            return;
+        };
+        current = object
+            .entry(part)
+            .or_insert_with(|| Value::Object(Default::default()));
+    }
+    if let Some(object) = current.as_object_mut() {
+        object.insert(last.to_string(), injected);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request() -> RequestInfo {
+        // payload: {"sub":"user-1","tenant":"acme"}
+        RequestInfo {
+            method: "GET".to_string(),
+            path: "/proxy/users".to_string(),
+            query: Some("page=2&q=rust+lang%2Fweb".to_string()),
+            client_ip: Some("10.0.0.7".parse().unwrap()),
+            request_id: "req-123".to_string(),
+            headers: vec![
+                (
+                    "Authorization".to_string(),
+                    "Bearer eyJhbGciOiJIUzI1NiJ9.eyJzdWIiOiJ1c2VyLTEiLCJ0ZW5hbnQiOiJhY21lIn0.sig"
+                        .to_string(),
+                ),
+                ("X-Locale".to_string(), "zh-CN".to_string()),
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn context() -> TemplateContext {
+        let token = TokenInfo {
+            claims: json!({ "sub": "user-1", "tenant": "acme" }),
+            ..Default::default()
+        };
+        TemplateContext::new(&request(), Some(&token))
+    }
+
+    #[test]
+    fn test_render_template() {
+        let ctx = context();
+        assert_eq!(ctx.render("${client_ip}"), "10.0.0.7");
+        assert_eq!(ctx.render("id=${request_id};"), "id=req-123;");
+        assert_eq!(ctx.render("${jwt.sub}/${jwt.tenant}"), "user-1/acme");
+        assert_eq!(ctx.render("${jwt.missing}"), "");
+        assert_eq!(ctx.render("plain"), "plain");
+        assert_eq!(ctx.render("${method} ${path}"), "GET /proxy/users");
+        assert_eq!(ctx.render("${header.x-locale}"), "zh-CN");
+        assert_eq!(ctx.render("${query.page}/${query.none}"), "2/");
+    }
+
+    #[test]
+    fn test_jwt_variables_need_verified_token() {
+        // 请求里带着 JWT，但没有经过路由 auth 校验
+        let ctx = TemplateContext::new(&request(), None);
+        assert_eq!(ctx.render("${jwt.sub}/${jwt.tenant}"), "/");
+    }
+
+    #[test]
+    fn test_render_url_encodes_values() {
+        let mut ctx = context();
+        ctx.headers
+            .push(("X-User".to_string(), "../admin?x=1&y=2#z".to_string()));
+        assert_eq!(
+            ctx.render_url("http://svc/users/${header.x-user}?lang=${header.x-locale}"),
+            "http://svc/users/..%2Fadmin%3Fx%3D1%26y%3D2%23z?lang=zh-CN"
+        );
+        // 查询参数先解码再编码，不会二次编码
+        assert_eq!(
+            ctx.render_url("http://svc/search?q=${query.q}"),
+            "http://svc/search?q=rust%20lang%2Fweb"
+        );
+        assert_eq!(ctx.render_url("http://svc/${jwt.sub}"), "http://svc/user-1");
+    }
+
+    #[test]
+    fn test_apply_headers() {
+        let rules = MessageTransform {
+            add_headers: BTreeMap::from([("X-Client-IP".to_string(), "${client_ip}".to_string())]),
+            remove_headers: vec!["cookie".to_string()],
+            rename_headers: BTreeMap::from([("X-Old".to_string(), "X-New".to_string())]),
+            ..Default::default()
+        };
+        let mut headers = vec![
+            ("Cookie".to_string(), "a=b".to_string()),
+            ("x-old".to_string(), "v".to_string()),
+            ("X-Client-IP".to_string(), "spoofed".to_string()),
+        ];
+        rules.apply_headers(&mut headers, &context());
+
+        assert_eq!(
+            headers,
+            vec![
+                ("X-New".to_string(), "v".to_string()),
+                ("X-Client-IP".to_string(), "10.0.0.7".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply_json() {
+        let rules = MessageTransform {
+            inject_json: BTreeMap::from([
+                ("meta.user".to_string(), json!("${jwt.sub}")),
+                ("source".to_string(), json!("gateway")),
+            ]),
+            remove_json: vec!["password".to_string(), "profile.ssn".to_string()],
+            ..Default::default()
+        };
+        let mut body = br#"{"name":"a","password":"x","profile":{"ssn":"1","age":3}}"#.to_vec();
+        rules.apply_json(&mut body, &context());
+
+        let value: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "name": "a",
+                "profile": {"age": 3},
+                "meta": {"user": "user-1"},
+                "source": "gateway"
+            })
+        );
+    }
+
+    #[test]
+    fn test_apply_json_ignores_non_json() {
+        let rules = MessageTransform {
+            remove_json: vec!["a".to_string()],
+            ..Default::default()
+        };
+        let mut body = b"not json".to_vec();
+        rules.apply_json(&mut body, &context());
+        assert_eq!(body, b"not json");
+    }
+
+    #[test]
+    fn test_map_status() {
+        let config = TransformConfig {
+            status_map: BTreeMap::from([("404".to_string(), 200)]),
+            ..Default::default()
+        };
+        assert_eq!(config.map_status(404), 200);
+        assert_eq!(config.map_status(500), 500);
+        assert!(config.validate().is_ok());
+
+        for (from, to) in [
+            ("404", 0),
+            ("404", 1000),
+            ("0", 200),
+            ("abc", 200),
+            ("0404", 200),
+        ] {
+            let config = TransformConfig {
+                status_map: BTreeMap::from([(from.to_string(), to)]),
+                ..Default::default()
+            };
+            assert!(config.validate().is_err(), "{} = {}", from, to);
+        }
+    }
+}
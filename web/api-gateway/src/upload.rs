@@ -0,0 +1,721 @@
+use crate::error::GatewayError;
+use crate::metrics::{metric_key, Metrics};
+use crate::proxy::{check_access, send_upstream, ProxyResponse, UpstreamRequest};
+use crate::request::RequestInfo;
+use crate::shutdown::Accepting;
+use crate::versioning;
+use crate::AppConfig;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rocket::data::{Data, ToByteUnit};
+use rocket::fairing::AdHoc;
+use rocket::http::{CookieJar, Status};
+use rocket::{delete, head, patch, State};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// 可续传上传（参照 tus 1.0 协议）：
+// POST /proxy/<路由> 带 Upload-Length 创建上传，返回 Location: /uploads/<id>；
+// PATCH /uploads/<id> 按 Upload-Offset 追加分块，HEAD 查询进度，DELETE 取消。
+// 鉴权、租户和路由的访问控制在创建时完成，之后凭随机的上传 ID 续传。
+// 全部分块到齐后按偏移拼接成完整文件，以 POST 转发给路由的上游。
+const TUS_VERSION: &str = "1.0.0";
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+const STATE_FILE: &str = "upload.json";
+
+// 暂存目录和过期时间，所有上传路由共用
+#[derive(Debug, Deserialize)]
+pub struct UploadsConfig {
+    #[serde(default = "default_dir")]
+    pub dir: PathBuf,
+    #[serde(default = "default_expire_after")]
+    pub expire_after: u64, // 创建后多久没有完成就丢弃（秒）
+    #[serde(skip)]
+    locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>, // 同一个上传的分块串行处理
+}
+
+impl Default for UploadsConfig {
+    fn default() -> Self {
+        Self {
+            dir: default_dir(),
+            expire_after: default_expire_after(),
+            locks: Mutex::default(),
+        }
+    }
+}
+
+fn default_dir() -> PathBuf {
+    std::env::temp_dir().join("api-gateway-uploads")
+}
+
+fn default_expire_after() -> u64 {
+    24 * 3600
+}
+
+// 路由开启后，该路由的 POST 不再直接转发，而是创建一个上传
+#[derive(Debug, Deserialize)]
+pub struct UploadConfig {
+    #[serde(default = "default_max_size")]
+    pub max_size: u64, // 整个文件的大小上限（字节）
+    #[serde(default = "default_max_chunk_size")]
+    pub max_chunk_size: u64, // 单个 PATCH 的大小上限（字节）
+}
+
+fn default_max_size() -> u64 {
+    100 * 1024 * 1024
+}
+
+fn default_max_chunk_size() -> u64 {
+    5 * 1024 * 1024
+}
+
+// 每个上传一个目录：upload.json 记录进度，分块存为 <offset>.part
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct UploadState {
+    route: String,
+    #[serde(default)]
+    version: Option<String>, // 创建时协商出的 API 版本，完成时发给该版本的上游
+    length: u64,
+    offset: u64,
+    created: u64, // Unix 时间（秒）
+    #[serde(default)]
+    metadata: BTreeMap<String, String>, // Upload-Metadata 解码后的键值
+    #[serde(default)]
+    headers: Vec<(String, String)>, // 创建请求的身份和转发头，完成后随文件发给上游
+    #[serde(default)]
+    chunks: Vec<Chunk>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Chunk {
+    offset: u64,
+    size: u64,
+    sha256: String, // 写盘前计算，拼接时再校验一次
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+// ID 会拼进文件路径，只接受网关自己生成的格式
+fn is_valid_id(id: &str) -> bool {
+    id.len() == 32 && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn io_error(context: &str, e: std::io::Error) -> GatewayError {
+    log::error!("Upload {}: {}", context, e);
+    GatewayError::Internal
+}
+
+// Upload-Metadata: "filename d29ybGQudHh0,filetype dGV4dC9wbGFpbg=="，值可以省略
+fn parse_metadata(value: &str) -> Result<BTreeMap<String, String>, GatewayError> {
+    let invalid = || GatewayError::InvalidRequest("Invalid Upload-Metadata".to_string());
+    let mut metadata = BTreeMap::new();
+    for pair in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (key, encoded) = pair.split_once(' ').unwrap_or((pair, ""));
+        let decoded = STANDARD.decode(encoded.trim()).map_err(|_| invalid())?;
+        let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+        metadata.insert(key.to_string(), decoded);
+    }
+    Ok(metadata)
+}
+
+// Upload-Checksum: "<算法> <Base64 摘要>"，支持 sha256 和 md5
+fn verify_checksum(value: &str, data: &[u8]) -> Result<(), GatewayError> {
+    let (algorithm, expected) = value
+        .trim()
+        .split_once(' ')
+        .ok_or_else(|| GatewayError::InvalidRequest("Invalid Upload-Checksum".to_string()))?;
+    let actual = match algorithm.to_ascii_lowercase().as_str() {
+        "sha256" => Sha256::digest(data).to_vec(),
+        "md5" => md5::compute(data).0.to_vec(),
+        other => {
+            return Err(GatewayError::InvalidRequest(format!(
+                "Unsupported checksum algorithm: {}",
+                other
+            )))
+        }
+    };
+    if STANDARD.decode(expected.trim()).ok() != Some(actual) {
+        return Err(GatewayError::ChecksumMismatch);
+    }
+    Ok(())
+}
+
+fn tus_headers(state: &UploadState) -> Vec<(String, String)> {
+    vec![
+        ("Tus-Resumable".to_string(), TUS_VERSION.to_string()),
+        ("Upload-Offset".to_string(), state.offset.to_string()),
+        ("Upload-Length".to_string(), state.length.to_string()),
+        ("Cache-Control".to_string(), "no-store".to_string()),
+    ]
+}
+
+fn empty_response(status: Status, headers: Vec<(String, String)>) -> ProxyResponse {
+    ProxyResponse {
+        status,
+        headers,
+        body: Vec::new(),
+        truncate: false,
+    }
+}
+
+impl UploadsConfig {
+    fn upload_dir(&self, id: &str) -> PathBuf {
+        self.dir.join(id)
+    }
+
+    // 只为存在的上传建锁，任意 ID 的请求不会让锁表无限增长
+    async fn lock(&self, id: &str) -> Result<Arc<tokio::sync::Mutex<()>>, GatewayError> {
+        if !is_valid_id(id) {
+            return Err(GatewayError::UploadNotFound);
+        }
+        if tokio::fs::metadata(self.upload_dir(id).join(STATE_FILE))
+            .await
+            .is_err()
+        {
+            return Err(GatewayError::UploadNotFound);
+        }
+        Ok(self
+            .locks
+            .lock()
+            .unwrap()
+            .entry(id.to_string())
+            .or_default()
+            .clone())
+    }
+
+    fn expired(&self, created: u64, now: u64) -> bool {
+        created.saturating_add(self.expire_after) <= now
+    }
+
+    // 不存在、ID 不合法或已过期都按 404 处理；过期的顺手删掉
+    async fn load(&self, id: &str) -> Result<UploadState, GatewayError> {
+        if !is_valid_id(id) {
+            return Err(GatewayError::UploadNotFound);
+        }
+        let path = self.upload_dir(id).join(STATE_FILE);
+        let content = match tokio::fs::read(&path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(GatewayError::UploadNotFound)
+            }
+            Err(e) => return Err(io_error("state read failed", e)),
+        };
+        let state: UploadState = serde_json::from_slice(&content).map_err(|e| {
+            log::error!("Corrupted upload state {}: {}", path.display(), e);
+            GatewayError::Internal
+        })?;
+        if self.expired(state.created, unix_now()) {
+            self.remove(id).await;
+            return Err(GatewayError::UploadNotFound);
+        }
+        Ok(state)
+    }
+
+    // 先写临时文件再改名，崩溃时不会留下写了一半的状态
+    async fn save(&self, id: &str, state: &UploadState) -> Result<(), GatewayError> {
+        let dir = self.upload_dir(id);
+        let tmp = dir.join(format!("{}.tmp", STATE_FILE));
+        let content = serde_json::to_vec(state).map_err(|_| GatewayError::Internal)?;
+        tokio::fs::write(&tmp, content)
+            .await
+            .map_err(|e| io_error("state write failed", e))?;
+        tokio::fs::rename(&tmp, dir.join(STATE_FILE))
+            .await
+            .map_err(|e| io_error("state write failed", e))
+    }
+
+    async fn remove(&self, id: &str) {
+        if let Err(e) = tokio::fs::remove_dir_all(self.upload_dir(id)).await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                log::warn!("Failed to remove upload {}: {}", id, e);
+            }
+        }
+        self.locks.lock().unwrap().remove(id);
+    }
+
+    // 按偏移顺序拼接分块，并逐块核对写盘时的摘要
+    async fn assemble(&self, id: &str, state: &UploadState) -> Result<Vec<u8>, GatewayError> {
+        let dir = self.upload_dir(id);
+        let mut file = Vec::with_capacity(state.length as usize);
+        for chunk in &state.chunks {
+            let data = tokio::fs::read(dir.join(format!("{}.part", chunk.offset)))
+                .await
+                .map_err(|e| io_error("chunk read failed", e))?;
+            if hex::encode(Sha256::digest(&data)) != chunk.sha256 {
+                log::error!("Upload {} chunk at {} is corrupted", id, chunk.offset);
+                return Err(GatewayError::Internal);
+            }
+            file.extend_from_slice(&data);
+        }
+        Ok(file)
+    }
+
+    // 删除过期的上传；没有状态文件的目录（创建中途失败）按目录的修改时间判断
+    pub fn sweep(&self, now: u64) -> usize {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return 0;
+        };
+        let mut removed = 0;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_upload = entry.file_name().to_str().is_some_and(is_valid_id);
+            if !is_upload || !path.is_dir() {
+                continue;
+            }
+            let expired = match std::fs::read(path.join(STATE_FILE))
+                .ok()
+                .and_then(|content| serde_json::from_slice::<UploadState>(&content).ok())
+            {
+                Some(state) => self.expired(state.created, now),
+                None => entry
+                    .metadata()
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .is_some_and(|t| self.expired(t.as_secs(), now)),
+            };
+            if expired && std::fs::remove_dir_all(&path).is_ok() {
+                removed += 1;
+            }
+        }
+        // 上传已经不在、也没有请求持有的锁一并清掉
+        self.locks
+            .lock()
+            .unwrap()
+            .retain(|id, lock| Arc::strong_count(lock) > 1 || self.upload_dir(id).is_dir());
+        removed
+    }
+}
+
+impl UploadConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_chunk_size == 0 || self.max_chunk_size > self.max_size {
+            return Err(format!(
+                "upload max_chunk_size {} must be between 1 and max_size ({})",
+                self.max_chunk_size, self.max_size
+            ));
+        }
+        Ok(())
+    }
+
+    // 由代理流水线在鉴权之后调用；headers 是需要在完成时带给上游的头
+    pub async fn create(
+        &self,
+        uploads: &UploadsConfig,
+        route: &str,
+        version: Option<&str>,
+        info: &RequestInfo,
+        headers: Vec<(String, String)>,
+        metrics: &Metrics,
+    ) -> Result<ProxyResponse, GatewayError> {
+        let length = info
+            .header("Upload-Length")
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .ok_or_else(|| {
+                GatewayError::InvalidRequest("Upload-Length header is required".to_string())
+            })?;
+        if length > self.max_size {
+            return Err(GatewayError::PayloadTooLarge);
+        }
+        let metadata = parse_metadata(info.header("Upload-Metadata").unwrap_or(""))?;
+
+        let id = format!("{:032x}", rand::random::<u128>());
+        let state = UploadState {
+            route: route.to_string(),
+            version: version.map(str::to_string),
+            length,
+            offset: 0,
+            created: unix_now(),
+            metadata,
+            headers,
+            chunks: Vec::new(),
+        };
+        tokio::fs::create_dir_all(uploads.upload_dir(&id))
+            .await
+            .map_err(|e| io_error("staging directory creation failed", e))?;
+        uploads.save(&id, &state).await?;
+        log::debug!("Created upload {} on {} ({} bytes)", id, route, length);
+        metrics.incr(metric_key("uploads_created_total", &[("route", route)]));
+
+        let mut headers = tus_headers(&state);
+        headers.push(("Location".to_string(), format!("/uploads/{}", id)));
+        Ok(empty_response(Status::Created, headers))
+    }
+}
+
+// 完成后把文件发给上游；上游失败时保留暂存文件，客户端在末尾偏移发送空 PATCH 即可重试
+async fn complete(
+    id: &str,
+    state: &UploadState,
+    config: &AppConfig,
+    info: &RequestInfo,
+    cookies: &CookieJar<'_>,
+    metrics: &Metrics,
+) -> Result<ProxyResponse, GatewayError> {
+    let route = config
+        .routes
+        .iter()
+        .find(|r| r.path == state.route && r.upload.is_some())
+        .ok_or(GatewayError::RouteNotFound)?;
+    let version = state.version.as_deref().and_then(|name| {
+        route
+            .versioning
+            .as_ref()
+            .and_then(|versioning| versioning.find(name))
+    });
+    let body = config.uploads.assemble(id, state).await?;
+
+    let mut headers = state.headers.clone();
+    headers.push(("X-Request-Id".to_string(), info.request_id.clone()));
+    headers.push(("X-Upload-Id".to_string(), id.to_string()));
+    let content_type = ["filetype", "content_type"]
+        .iter()
+        .find_map(|key| state.metadata.get(*key))
+        .map_or("application/octet-stream", String::as_str);
+    headers.push(("Content-Type".to_string(), content_type.to_string()));
+    if let Some(filename) = state.metadata.get("filename") {
+        headers.push((
+            "Content-Disposition".to_string(),
+            format!("attachment; filename=\"{}\"", filename.replace('"', "")),
+        ));
+    }
+    headers.push((
+        "Content-Digest".to_string(),
+        format!("sha-256=:{}:", STANDARD.encode(Sha256::digest(&body))),
+    ));
+
+    let client = reqwest::Client::new();
+    if let Some(upstream_auth) = &route.upstream_auth {
+        let access_token = upstream_auth.access_token(&client).await?;
+        headers.push((
+            "Authorization".to_string(),
+            format!("Bearer {}", access_token),
+        ));
+    }
+    let outgoing = UpstreamRequest {
+        method: reqwest::Method::POST,
+        headers,
+        body,
+    };
+    let result = send_upstream(
+        route,
+        versioning::upstreams(route, version),
+        info,
+        cookies,
+        &client,
+        outgoing,
+        metrics,
+    )
+    .await;
+    if let (Some(upstream_auth), Ok((401, _, _))) = (&route.upstream_auth, &result) {
+        upstream_auth.invalidate().await;
+    }
+
+    let outcome = match &result {
+        Ok((status, _, _)) => status.to_string(),
+        Err(e) => e.code().to_string(),
+    };
+    metrics.incr(metric_key(
+        "uploads_forwarded_total",
+        &[("route", &route.path), ("status", &outcome)],
+    ));
+    let (status, mut headers, body) = result?;
+    if status < 500 {
+        config.uploads.remove(id).await;
+    }
+    headers.extend(tus_headers(state));
+    Ok(ProxyResponse {
+        status: Status::new(status),
+        headers,
+        body,
+        truncate: false,
+    })
+}
+
+#[head("/uploads/<id>")]
+pub async fn progress(
+    id: &str,
+    config: &State<Arc<AppConfig>>,
+    metrics: &State<Metrics>,
+    info: RequestInfo,
+    _accepting: Accepting,
+) -> Result<ProxyResponse, GatewayError> {
+    check_access(config.access.as_ref(), &info, "global", "", metrics)?;
+    let state = config.uploads.load(id).await?;
+    Ok(empty_response(Status::Ok, tus_headers(&state)))
+}
+
+#[patch("/uploads/<id>", data = "<data>")]
+pub async fn append(
+    id: &str,
+    data: Data<'_>,
+    config: &State<Arc<AppConfig>>,
+    metrics: &State<Metrics>,
+    info: RequestInfo,
+    cookies: &CookieJar<'_>,
+    _accepting: Accepting,
+) -> Result<ProxyResponse, GatewayError> {
+    // 续传不再重复鉴权，但全局的 IP 访问控制和摘流仍然生效
+    check_access(config.access.as_ref(), &info, "global", "", metrics)?;
+    let content_type = info.header("Content-Type").unwrap_or("");
+    if !content_type.eq_ignore_ascii_case(OFFSET_CONTENT_TYPE) {
+        return Err(GatewayError::Http(Status::UnsupportedMediaType));
+    }
+    let offset = info
+        .header("Upload-Offset")
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .ok_or_else(|| {
+            GatewayError::InvalidRequest("Upload-Offset header is required".to_string())
+        })?;
+
+    let uploads = &config.uploads;
+    let lock = uploads.lock(id).await?;
+    let _guard = lock.lock().await;
+    let mut state = uploads.load(id).await?;
+    let upload = config
+        .routes
+        .iter()
+        .find(|r| r.path == state.route)
+        .and_then(|r| r.upload.as_ref())
+        .ok_or(GatewayError::UploadNotFound)?;
+    if offset != state.offset {
+        return Err(GatewayError::UploadOffsetMismatch(state.offset));
+    }
+
+    // 多读一个字节来判断是否超过分块上限
+    let chunk = data
+        .open((upload.max_chunk_size + 1).bytes())
+        .into_bytes()
+        .await
+        .map_err(|e| {
+            log::debug!("Failed to read upload chunk: {}", e);
+            GatewayError::InvalidRequest("Failed to read request body".to_string())
+        })?
+        .into_inner();
+    let reject = |reason: &str| {
+        metrics.incr(metric_key(
+            "upload_chunks_rejected_total",
+            &[("route", &state.route), ("reason", reason)],
+        ));
+    };
+    if chunk.len() as u64 > upload.max_chunk_size {
+        reject("too_large");
+        return Err(GatewayError::PayloadTooLarge);
+    }
+    if offset + chunk.len() as u64 > state.length {
+        reject("exceeds_length");
+        return Err(GatewayError::InvalidRequest(
+            "Chunk exceeds the declared Upload-Length".to_string(),
+        ));
+    }
+    if let Some(checksum) = info.header("Upload-Checksum") {
+        if let Err(e) = verify_checksum(checksum, &chunk) {
+            reject(e.code());
+            return Err(e);
+        }
+    }
+
+    if !chunk.is_empty() {
+        let part = uploads.upload_dir(id).join(format!("{}.part", offset));
+        tokio::fs::write(&part, &chunk)
+            .await
+            .map_err(|e| io_error("chunk write failed", e))?;
+        state.chunks.push(Chunk {
+            offset,
+            size: chunk.len() as u64,
+            sha256: hex::encode(Sha256::digest(&chunk)),
+        });
+        state.offset += chunk.len() as u64;
+        uploads.save(id, &state).await?;
+        metrics.incr(metric_key(
+            "upload_chunks_total",
+            &[("route", &state.route)],
+        ));
+    }
+
+    if state.offset < state.length {
+        return Ok(empty_response(Status::NoContent, tus_headers(&state)));
+    }
+    complete(id, &state, config, &info, cookies, metrics).await
+}
+
+#[delete("/uploads/<id>")]
+pub async fn cancel(
+    id: &str,
+    config: &State<Arc<AppConfig>>,
+    metrics: &State<Metrics>,
+    info: RequestInfo,
+    _accepting: Accepting,
+) -> Result<ProxyResponse, GatewayError> {
+    check_access(config.access.as_ref(), &info, "global", "", metrics)?;
+    let uploads = &config.uploads;
+    let lock = uploads.lock(id).await?;
+    let _guard = lock.lock().await;
+    uploads.load(id).await?;
+    uploads.remove(id).await;
+    let headers = vec![("Tus-Resumable".to_string(), TUS_VERSION.to_string())];
+    Ok(empty_response(Status::NoContent, headers))
+}
+
+// 定期清理过期的上传，启动时先清理一次上次运行留下的
+pub fn fairing() -> AdHoc {
+    AdHoc::on_liftoff("Upload Cleanup", |rocket| {
+        Box::pin(async move {
+            let Some(app) = rocket.state::<Arc<AppConfig>>().cloned() else {
+                return;
+            };
+            if !app.routes.iter().any(|r| r.upload.is_some()) {
+                return;
+            }
+            tokio::spawn(async move {
+                let period = app.uploads.expire_after.clamp(1, 600);
+                let mut interval = tokio::time::interval(Duration::from_secs(period));
+                loop {
+                    interval.tick().await;
+                    let removed = app.uploads.sweep(unix_now());
+                    if removed > 0 {
+                        log::info!("Removed {} expired uploads", removed);
+                    }
+                }
+            });
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uploads() -> UploadsConfig {
+        UploadsConfig {
+            dir: std::env::temp_dir().join(format!("uploads-{}", rand::random::<u64>())),
+            expire_after: 60,
+            ..Default::default()
+        }
+    }
+
+    fn state(created: u64) -> UploadState {
+        UploadState {
+            route: "/files".to_string(),
+            version: None,
+            length: 6,
+            offset: 0,
+            created,
+            metadata: BTreeMap::new(),
+            headers: Vec::new(),
+            chunks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_metadata() {
+        let metadata = parse_metadata("filename d29ybGQudHh0, is_draft").unwrap();
+        assert_eq!(metadata["filename"], "world.txt");
+        assert_eq!(metadata["is_draft"], "");
+        assert!(parse_metadata("").unwrap().is_empty());
+        assert!(parse_metadata("filename !!!").is_err());
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        let sha = STANDARD.encode(Sha256::digest(b"hello"));
+        assert_eq!(
+            verify_checksum(&format!("sha256 {}", sha), b"hello"),
+            Ok(())
+        );
+        assert_eq!(
+            verify_checksum(&format!("sha256 {}", sha), b"hellO"),
+            Err(GatewayError::ChecksumMismatch)
+        );
+        let md5 = STANDARD.encode(md5::compute(b"hello").0);
+        assert_eq!(verify_checksum(&format!("md5 {}", md5), b"hello"), Ok(()));
+        assert!(matches!(
+            verify_checksum("crc32 AAAA", b"hello"),
+            Err(GatewayError::InvalidRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_state_and_assembly() {
+        let uploads = uploads();
+        let id = format!("{:032x}", rand::random::<u128>());
+        let dir = uploads.upload_dir(&id);
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+
+        let mut state = state(unix_now());
+        for (offset, data) in [(0u64, &b"abc"[..]), (3, &b"def"[..])] {
+            tokio::fs::write(dir.join(format!("{}.part", offset)), data)
+                .await
+                .unwrap();
+            state.chunks.push(Chunk {
+                offset,
+                size: 3,
+                sha256: hex::encode(Sha256::digest(data)),
+            });
+        }
+        state.offset = 6;
+        uploads.save(&id, &state).await.unwrap();
+        assert_eq!(uploads.load(&id).await.unwrap(), state);
+        assert_eq!(uploads.assemble(&id, &state).await.unwrap(), b"abcdef");
+
+        // 暂存的分块被改动后拒绝拼接
+        tokio::fs::write(dir.join("3.part"), b"xyz").await.unwrap();
+        assert_eq!(
+            uploads.assemble(&id, &state).await,
+            Err(GatewayError::Internal)
+        );
+
+        assert_eq!(
+            uploads.load("../../etc").await,
+            Err(GatewayError::UploadNotFound)
+        );
+        tokio::fs::remove_dir_all(&uploads.dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_expiry_and_sweep() {
+        let uploads = uploads();
+        let (fresh, stale) = (
+            format!("{:032x}", rand::random::<u128>()),
+            format!("{:032x}", rand::random::<u128>()),
+        );
+        let now = unix_now();
+        for (id, created) in [(&fresh, now), (&stale, now - 120)] {
+            tokio::fs::create_dir_all(uploads.upload_dir(id))
+                .await
+                .unwrap();
+            uploads.save(id, &state(created)).await.unwrap();
+        }
+        // 无关的目录不动
+        tokio::fs::create_dir_all(uploads.dir.join("keep"))
+            .await
+            .unwrap();
+
+        assert_eq!(uploads.sweep(now), 1);
+        assert!(uploads.load(&fresh).await.is_ok());
+        assert_eq!(
+            uploads.load(&stale).await,
+            Err(GatewayError::UploadNotFound)
+        );
+        assert!(uploads.dir.join("keep").exists());
+
+        // 不合法或不存在的 ID 不建锁；上传被清理后锁也随之清掉
+        assert!(uploads.lock("../../etc").await.is_err());
+        assert!(uploads.lock(&stale).await.is_err());
+        assert!(uploads.lock(&fresh).await.is_ok());
+        assert_eq!(uploads.locks.lock().unwrap().len(), 1);
+        // 到期后同样被清理
+        assert_eq!(uploads.sweep(now + 60), 1);
+        assert!(uploads.locks.lock().unwrap().is_empty());
+        tokio::fs::remove_dir_all(&uploads.dir).await.unwrap();
+    }
+}
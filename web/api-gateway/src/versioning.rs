@@ -0,0 +1,348 @@
+use crate::error::GatewayError;
+use crate::load_balancer::Upstreams;
+use crate::request::RequestInfo;
+use crate::{find_route, find_route_by_path, RouteConfig};
+use chrono::{NaiveDate, Utc};
+use serde::Deserialize;
+
+// API 版本：每个版本对应一组上游，可以标记弃用和下线日期
+#[derive(Debug, Deserialize)]
+pub struct VersioningConfig {
+    #[serde(default)]
+    pub default: Option<String>, // 请求没有指定版本时使用；不配置则使用路由自己的 upstreams
+    #[serde(default = "default_header")]
+    pub header: String,
+    #[serde(default = "default_param")]
+    pub media_type_param: String, // Accept: application/json; version=2
+    #[serde(default = "default_param")]
+    pub query_param: String,
+    pub versions: Vec<ApiVersion>,
+}
+
+fn default_header() -> String {
+    "Accept-Version".to_string()
+}
+
+fn default_param() -> String {
+    "version".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiVersion {
+    pub name: String,
+    #[serde(default)]
+    pub upstreams: Upstreams, // 为空时使用路由的 upstreams
+    #[serde(default)]
+    pub deprecated: bool,
+    #[serde(default)]
+    pub sunset: Option<NaiveDate>, // 下线日期，之后的请求返回 410
+}
+
+// 版本从哪里取得，作为指标标签
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Path,
+    Header,
+    MediaType,
+    Query,
+    Default,
+}
+
+impl Source {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Source::Path => "path",
+            Source::Header => "header",
+            Source::MediaType => "media_type",
+            Source::Query => "query",
+            Source::Default => "default",
+        }
+    }
+}
+
+// "v2"、"V2" 和 "2" 视为同一个版本
+fn same_version(a: &str, b: &str) -> bool {
+    let normalize = |v: &str| v.trim().trim_start_matches(['v', 'V']).to_string();
+    normalize(a) == normalize(b)
+}
+
+fn media_type_version<'a>(accept: &'a str, param: &str) -> Option<&'a str> {
+    accept
+        .split(',')
+        .flat_map(|range| range.split(';').skip(1))
+        .filter_map(|p| p.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case(param))
+        .map(|(_, value)| value.trim().trim_matches('"'))
+}
+
+fn query_version<'a>(query: &'a str, param: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| *name == param)
+        .map(|(_, value)| value)
+}
+
+// 先按完整路径匹配路由；匹配不到时把第一段当作版本号去掉再匹配，例如 /v2/users
+pub fn find_versioned_route<'a>(
+    routes: &'a [RouteConfig],
+    path: &'a str,
+    method: &str,
+) -> Option<(&'a RouteConfig, Option<&'a str>)> {
+    find_versioned(path, |p| find_route(routes, p, method))
+}
+
+// 同上，只按路径查找（例如 CORS 预检时还不知道真正的请求方法）
+pub fn find_versioned_route_by_path<'a>(
+    routes: &'a [RouteConfig],
+    path: &str,
+) -> Option<&'a RouteConfig> {
+    find_versioned(path, |p| find_route_by_path(routes, p)).map(|(route, _)| route)
+}
+
+fn find_versioned<'a, 'p>(
+    path: &'p str,
+    find: impl Fn(&str) -> Option<&'a RouteConfig>,
+) -> Option<(&'a RouteConfig, Option<&'p str>)> {
+    if let Some(route) = find(path) {
+        return Some((route, None));
+    }
+    let end = path.strip_prefix('/')?.find('/')? + 1;
+    let (segment, rest) = (&path[1..end], &path[end..]);
+    let route = find(rest)?;
+    let versioning = route.versioning.as_ref()?;
+    versioning.find(segment).map(|_| (route, Some(segment)))
+}
+
+// 选中版本的上游组；版本没有配置自己的上游时使用路由的 upstreams
+pub fn upstreams<'a>(route: &'a RouteConfig, version: Option<&'a ApiVersion>) -> &'a Upstreams {
+    version
+        .map(|v| &v.upstreams)
+        .filter(|u| !u.snapshot().servers.is_empty())
+        .unwrap_or(&route.upstreams)
+}
+
+impl VersioningConfig {
+    // 版本名按 same_version 比较，"v2" 和 "2" 算重复；默认版本必须是配置过的版本
+    pub fn validate(&self) -> Result<(), String> {
+        if self.versions.is_empty() {
+            return Err("versioning requires at least one version".to_string());
+        }
+        for (i, version) in self.versions.iter().enumerate() {
+            if version.name.trim().is_empty() {
+                return Err("versioning version name must not be empty".to_string());
+            }
+            if self.versions[..i]
+                .iter()
+                .any(|v| same_version(&v.name, &version.name))
+            {
+                return Err(format!("versioning version {} is duplicated", version.name));
+            }
+        }
+        match &self.default {
+            Some(default) if self.find(default).is_none() => Err(format!(
+                "versioning default {} is not one of the versions",
+                default
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn find(&self, name: &str) -> Option<&ApiVersion> {
+        self.versions.iter().find(|v| same_version(&v.name, name))
+    }
+
+    // 优先级：路径 > 请求头 > 媒体类型参数 > 查询参数 > 默认版本
+    pub fn requested<'a>(
+        &'a self,
+        info: &'a RequestInfo,
+        path_version: Option<&'a str>,
+    ) -> Option<(&'a str, Source)> {
+        if let Some(version) = path_version {
+            return Some((version, Source::Path));
+        }
+        if let Some(version) = info.header(&self.header) {
+            return Some((version, Source::Header));
+        }
+        if let Some(version) = info
+            .header("Accept")
+            .and_then(|accept| media_type_version(accept, &self.media_type_param))
+        {
+            return Some((version, Source::MediaType));
+        }
+        if let Some(version) = info
+            .query
+            .as_deref()
+            .and_then(|query| query_version(query, &self.query_param))
+        {
+            return Some((version, Source::Query));
+        }
+        self.default.as_deref().map(|v| (v, Source::Default))
+    }
+
+    pub fn resolve(
+        &self,
+        info: &RequestInfo,
+        path_version: Option<&str>,
+    ) -> Result<Option<(&ApiVersion, Source)>, GatewayError> {
+        self.resolve_on(info, path_version, Utc::now().date_naive())
+    }
+
+    fn resolve_on(
+        &self,
+        info: &RequestInfo,
+        path_version: Option<&str>,
+        today: NaiveDate,
+    ) -> Result<Option<(&ApiVersion, Source)>, GatewayError> {
+        let Some((name, source)) = self.requested(info, path_version) else {
+            return Ok(None);
+        };
+        let version = self.find(name).ok_or_else(|| {
+            GatewayError::InvalidRequest(format!("Unsupported API version: {}", name))
+        })?;
+        if version.sunset.is_some_and(|sunset| today >= sunset) {
+            return Err(GatewayError::VersionRetired(version.name.clone()));
+        }
+        Ok(Some((version, source)))
+    }
+}
+
+impl ApiVersion {
+    // 弃用版本的响应头：Deprecation（RFC 9745）和 Sunset（RFC 8594）
+    pub fn response_headers(&self) -> Vec<(String, String)> {
+        let mut headers = vec![("API-Version".to_string(), self.name.clone())];
+        if self.deprecated {
+            headers.push(("Deprecation".to_string(), "true".to_string()));
+        }
+        if let Some(sunset) = self.sunset {
+            let date = sunset.and_hms_opt(0, 0, 0).unwrap().and_utc();
+            headers.push((
+                "Sunset".to_string(),
+                date.format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+            ));
+        }
+        headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    fn versioning() -> VersioningConfig {
+        parse(
+            r#"
+            default = "v2"
+
+            [[versions]]
+            name = "v1"
+            deprecated = true
+            sunset = "2030-01-01"
+
+            [[versions]]
+            name = "v2"
+            "#,
+        )
+    }
+
+    fn request(headers: &[(&str, &str)], query: Option<&str>) -> RequestInfo {
+        RequestInfo {
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            query: query.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn resolved(info: &RequestInfo, path: Option<&str>) -> Result<(String, Source), GatewayError> {
+        let today = NaiveDate::from_ymd_opt(2029, 6, 1).unwrap();
+        versioning()
+            .resolve_on(info, path, today)
+            .map(|v| v.map(|(v, s)| (v.name.clone(), s)).unwrap())
+    }
+
+    #[test]
+    fn test_negotiation_order() {
+        let all = request(
+            &[
+                ("Accept-Version", "v1"),
+                ("Accept", "application/json; version=2"),
+            ],
+            Some("version=1"),
+        );
+        assert_eq!(
+            resolved(&all, Some("v2")).unwrap(),
+            ("v2".to_string(), Source::Path)
+        );
+        assert_eq!(
+            resolved(&all, None).unwrap(),
+            ("v1".to_string(), Source::Header)
+        );
+        let accept = request(
+            &[("Accept", "text/html, application/json;version=\"1\"")],
+            None,
+        );
+        assert_eq!(
+            resolved(&accept, None).unwrap(),
+            ("v1".to_string(), Source::MediaType)
+        );
+        assert_eq!(
+            resolved(&request(&[], Some("a=b&version=1")), None).unwrap(),
+            ("v1".to_string(), Source::Query)
+        );
+        assert_eq!(
+            resolved(&request(&[], None), None).unwrap(),
+            ("v2".to_string(), Source::Default)
+        );
+        assert_eq!(
+            resolved(&request(&[("Accept-Version", "v9")], None), None),
+            Err(GatewayError::InvalidRequest(
+                "Unsupported API version: v9".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_sunset() {
+        let config = versioning();
+        let v1 = request(&[("Accept-Version", "1")], None);
+        let after = NaiveDate::from_ymd_opt(2030, 1, 1).unwrap();
+        assert_eq!(
+            config.resolve_on(&v1, None, after).err(),
+            Some(GatewayError::VersionRetired("v1".to_string()))
+        );
+
+        let headers = config.find("v1").unwrap().response_headers();
+        assert!(headers.contains(&("Deprecation".to_string(), "true".to_string())));
+        assert!(headers.contains(&(
+            "Sunset".to_string(),
+            "Tue, 01 Jan 2030 00:00:00 GMT".to_string()
+        )));
+        assert_eq!(config.find("v2").unwrap().response_headers().len(), 1);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(versioning().validate().is_ok());
+
+        let mut config = versioning();
+        config.default = Some("v3".to_string());
+        assert!(config.validate().unwrap_err().contains("default"));
+
+        let mut config = versioning();
+        config.versions[1].name = "1".to_string();
+        assert!(config.validate().unwrap_err().contains("duplicated"));
+
+        let mut config = versioning();
+        config.versions[1].name = " ".to_string();
+        assert!(config.validate().unwrap_err().contains("empty"));
+
+        let mut config = versioning();
+        config.default = None;
+        config.versions.clear();
+        assert!(config.validate().is_err());
+    }
+}
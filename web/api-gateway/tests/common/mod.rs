@@ -0,0 +1,280 @@
+// 集成测试的公共工具：本地桩上游 + 进程内网关
+#![allow(dead_code)]
+
+use api_gateway::AppConfig;
+use rocket::local::asynchronous::Client;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// 桩上游：监听随机端口，对每个请求返回固定的状态码和 JSON
+pub struct Stub {
+    pub url: String,
+    hits: Arc<AtomicUsize>,
+}
+
+impl Stub {
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Clone)]
+pub struct StubOptions {
+    pub name: String,
+    pub status: u16,
+    pub delay: Duration,
+}
+
+impl StubOptions {
+    pub fn new(name: &str) -> Self {
+        StubOptions {
+            name: name.to_string(),
+            status: 200,
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub fn status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+pub async fn stub(name: &str) -> Stub {
+    start_stub(StubOptions::new(name)).await
+}
+
+pub async fn start_stub(options: StubOptions) -> Stub {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    let hits = Arc::new(AtomicUsize::new(0));
+
+    let counter = hits.clone();
+    tokio::spawn(async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                return;
+            };
+            counter.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(serve(stream, options.clone()));
+        }
+    });
+
+    Stub { url, hits }
+}
+
+// 桩服务收到的请求
+pub struct RawRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl RawRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    // application/x-www-form-urlencoded 请求体里的字段（测试里的值不含转义字符）
+    pub fn form(&self, name: &str) -> Option<String> {
+        String::from_utf8_lossy(&self.body)
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(k, _)| *k == name)
+            .map(|(_, v)| v.to_string())
+    }
+}
+
+async fn read_request(stream: &mut TcpStream) -> Option<RawRequest> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let content_length = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    while buf.len() < header_end + content_length {
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return None,
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+        }
+    }
+
+    Some(RawRequest {
+        method,
+        path,
+        headers,
+        body: buf[header_end..].to_vec(),
+    })
+}
+
+async fn respond(stream: &mut TcpStream, status: u16, content_type: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {} Stub\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+// 读完一个请求后按配置应答并关闭连接；响应里带上收到的方法、头和请求体
+async fn serve(mut stream: TcpStream, options: StubOptions) {
+    let Some(request) = read_request(&mut stream).await else {
+        return;
+    };
+
+    if !options.delay.is_zero() {
+        tokio::time::sleep(options.delay).await;
+    }
+
+    let headers: serde_json::Map<String, serde_json::Value> = request
+        .headers
+        .iter()
+        .map(|(k, v)| (k.to_ascii_lowercase(), v.clone().into()))
+        .collect();
+    let body = serde_json::json!({
+        "upstream": options.name,
+        "method": request.method,
+        "headers": headers,
+        "body": String::from_utf8_lossy(&request.body),
+    })
+    .to_string();
+    respond(&mut stream, options.status, "application/json", &body).await;
+}
+
+// 自定义处理函数的桩服务，返回 (状态码, JSON 响应体)
+pub async fn start_server<F>(handler: F) -> String
+where
+    F: Fn(&RawRequest) -> (u16, String) + Send + Sync + 'static,
+{
+    start_typed_server(move |request| {
+        let (status, body) = handler(request);
+        (status, "application/json", body)
+    })
+    .await
+}
+
+// 同上，处理函数自己决定 Content-Type
+pub async fn start_typed_server<F>(handler: F) -> String
+where
+    F: Fn(&RawRequest) -> (u16, &'static str, String) + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handler = Arc::new(handler);
+    tokio::spawn(async move {
+        loop {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                return;
+            };
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                if let Some(request) = read_request(&mut stream).await {
+                    let (status, content_type, body) = handler(&request);
+                    respond(&mut stream, status, content_type, &body).await;
+                }
+            });
+        }
+    });
+    url
+}
+
+// 没有服务监听的地址，用来模拟连接失败
+pub async fn closed_url() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    drop(listener);
+    url
+}
+
+// 在公共的 server / logging 配置后拼上测试自己的路由
+pub fn config(routes: &str) -> AppConfig {
+    try_config(routes).unwrap()
+}
+
+// 同上，返回配置检查的错误
+pub fn try_config(routes: &str) -> Result<AppConfig, config::ConfigError> {
+    let toml = format!(
+        r#"
+        [server]
+        host = "127.0.0.1"
+        port = 0
+        workers = 2
+
+        [logging]
+        level = "warn"
+        format = "text"
+
+        {}
+        "#,
+        routes
+    );
+    let settings = config::Config::builder()
+        .add_source(config::File::from_str(&toml, config::FileFormat::Toml))
+        .build()?;
+    api_gateway::parse_config(settings)
+}
+
+// 在真实端口上启动网关，用于需要观察 TCP 连接行为的测试
+pub async fn launch(routes: &str) -> SocketAddr {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let rocket = api_gateway::rocket(config(routes));
+    let figment = rocket.figment().clone().merge(("port", port));
+    tokio::spawn(rocket.configure(figment).launch());
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    for _ in 0..50 {
+        if TcpStream::connect(addr).await.is_ok() {
+            return addr;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("gateway did not start on port {}", port);
+}
+
+pub async fn gateway(routes: &str) -> Client {
+    Client::tracked(api_gateway::rocket(config(routes)))
+        .await
+        .expect("valid rocket instance")
+}
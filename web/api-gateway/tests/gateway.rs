@@ -0,0 +1,1006 @@
+mod common;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use common::{closed_url, gateway, start_stub, stub, StubOptions};
+use rocket::http::{ContentType, Header, Status};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+async fn json(response: rocket::local::asynchronous::LocalResponse<'_>) -> Value {
+    response.into_json().await.expect("JSON body")
+}
+
+#[tokio::test]
+async fn test_routes_by_path_and_method() {
+    let users = stub("users").await;
+    let orders = stub("orders").await;
+    let client = gateway(&format!(
+        r#"
+        [[routes]]
+        path = "/users/*"
+        method = "GET|POST"
+        timeout = 5
+        upstreams = [{{ url = "{}", weight = 1 }}]
+
+        [[routes]]
+        path = "/orders"
+        method = "GET"
+        timeout = 5
+        upstreams = [{{ url = "{}", weight = 1 }}]
+        "#,
+        users.url, orders.url
+    ))
+    .await;
+
+    let response = client.get("/proxy/users/42").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(json(response).await["upstream"], "users");
+
+    let response = client
+        .post("/proxy/users")
+        .header(ContentType::JSON)
+        .body(r#"{"name":"a"}"#)
+        .dispatch()
+        .await;
+    let body = json(response).await;
+    assert_eq!(body["method"], "POST");
+    assert_eq!(body["body"], r#"{"name":"a"}"#);
+
+    let response = client.get("/proxy/orders").dispatch().await;
+    assert_eq!(json(response).await["upstream"], "orders");
+
+    // 方法不匹配和路径不匹配都找不到路由
+    let response = client.delete("/proxy/orders").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(json(response).await["code"], "route_not_found");
+    assert_eq!(users.hits(), 2);
+    assert_eq!(orders.hits(), 1);
+}
+
+#[tokio::test]
+async fn test_round_robin_spreads_requests() {
+    let a = stub("a").await;
+    let b = stub("b").await;
+    let client = gateway(&format!(
+        r#"
+        [[routes]]
+        path = "/rr"
+        method = "GET"
+        timeout = 5
+        load_balance = "round_robin"
+        upstreams = [{{ url = "{}", weight = 1 }}, {{ url = "{}", weight = 1 }}]
+        "#,
+        a.url, b.url
+    ))
+    .await;
+
+    for _ in 0..10 {
+        let response = client.get("/proxy/rr").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+    }
+    // 轮询计数器是全局的，其它并发测试可能插队，只要求两边都收到请求
+    assert_eq!(a.hits() + b.hits(), 10);
+    assert!(a.hits() > 0 && b.hits() > 0);
+}
+
+#[tokio::test]
+async fn test_mirror_copies_requests() {
+    let primary = stub("primary").await;
+    let mirror = start_stub(StubOptions::new("mirror").status(500)).await;
+    let client = gateway(&format!(
+        r#"
+        [[routes]]
+        path = "/mirrored"
+        method = "GET"
+        timeout = 5
+        upstreams = [{{ url = "{}", weight = 1 }}]
+        mirror = {{ url = "{}" }}
+
+        [[routes]]
+        path = "/mirror-down"
+        method = "GET"
+        timeout = 5
+        upstreams = [{{ url = "{}", weight = 1 }}]
+        mirror = {{ url = "{}" }}
+        "#,
+        primary.url,
+        mirror.url,
+        primary.url,
+        closed_url().await
+    ))
+    .await;
+
+    // 镜像上游返回 500 或连不上都不影响主请求
+    for path in ["/proxy/mirrored", "/proxy/mirrored", "/proxy/mirror-down"] {
+        let response = client.get(path).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(json(response).await["upstream"], "primary");
+    }
+    assert_eq!(primary.hits(), 3);
+
+    // 镜像请求在后台发送，等它们完成并记录指标
+    let mut counters = Value::Null;
+    for _ in 0..50 {
+        counters = json(client.get("/metrics").dispatch().await).await;
+        let done =
+            counters["counters"]["mirror_requests_total{route=\"/mirrored\",status=\"500\"}"] == 2
+                && counters["counters"]
+                    ["mirror_requests_total{route=\"/mirror-down\",status=\"error\"}"]
+                    == 1;
+        if done {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(mirror.hits(), 2);
+    assert_eq!(
+        counters["counters"]["mirror_requests_total{route=\"/mirrored\",status=\"500\"}"],
+        2
+    );
+    assert_eq!(
+        counters["counters"]["mirror_requests_total{route=\"/mirror-down\",status=\"error\"}"],
+        1
+    );
+    assert!(counters["latencies"]["mirror_latency{route=\"/mirrored\"}"].is_object());
+}
+
+#[tokio::test]
+async fn test_cors_vary_and_credentials() {
+    let upstream = stub("a").await;
+    let client = gateway(&format!(
+        r#"
+        [cors]
+        allowed_origins = ["https://app.example.com"]
+        allow_credentials = true
+
+        [[routes]]
+        path = "/cors"
+        method = "GET"
+        timeout = 5
+        upstreams = [{{ url = "{}", weight = 1 }}]
+        "#,
+        upstream.url
+    ))
+    .await;
+
+    // 允许、不允许和不带 Origin 的响应都带 Vary，共享缓存不会串用
+    for origin in [
+        Some("https://app.example.com"),
+        Some("https://evil.io"),
+        None,
+    ] {
+        let mut request = client.get("/proxy/cors");
+        if let Some(origin) = origin {
+            request = request.header(Header::new("Origin", origin));
+        }
+        let response = request.dispatch().await;
+        assert_eq!(response.headers().get_one("Vary"), Some("Origin"));
+        let allowed = response.headers().get_one("Access-Control-Allow-Origin");
+        assert_eq!(allowed, origin.filter(|o| o.starts_with("https://app")));
+    }
+
+    let error = common::try_config(
+        r#"
+        [cors]
+        allowed_origins = ["*"]
+        allow_credentials = true
+        "#,
+    )
+    .err()
+    .unwrap();
+    assert!(error.to_string().contains("allow_credentials"));
+}
+
+#[tokio::test]
+async fn test_consistent_hash_pins_key() {
+    let stubs = [stub("a").await, stub("b").await, stub("c").await];
+    let upstreams: Vec<String> = stubs
+        .iter()
+        .map(|s| format!(r#"{{ url = "{}", weight = 1 }}"#, s.url))
+        .collect();
+    let client = gateway(&format!(
+        r#"
+        [[routes]]
+        path = "/hash"
+        method = "GET"
+        timeout = 5
+        load_balance = "consistent_hash"
+        upstreams = [{}]
+        hash = {{ source = "header", name = "X-User-Id" }}
+        "#,
+        upstreams.join(", ")
+    ))
+    .await;
+
+    let mut chosen = Vec::new();
+    for _ in 0..5 {
+        let response = client
+            .get("/proxy/hash")
+            .header(Header::new("X-User-Id", "42"))
+            .dispatch()
+            .await;
+        chosen.push(json(response).await["upstream"].clone());
+    }
+    assert!(chosen.iter().all(|u| *u == chosen[0]));
+    assert_eq!(stubs.iter().filter(|s| s.hits() > 0).count(), 1);
+}
+
+#[tokio::test]
+async fn test_upstream_timeout_returns_504() {
+    let slow = start_stub(StubOptions::new("slow").delay(Duration::from_secs(3))).await;
+    let client = gateway(&format!(
+        r#"
+        [[routes]]
+        path = "/slow"
+        method = "GET"
+        timeout = 1
+        upstreams = [{{ url = "{}", weight = 1 }}]
+        "#,
+        slow.url
+    ))
+    .await;
+
+    let response = client
+        .get("/proxy/slow")
+        .header(Header::new("X-Request-Id", "req-timeout"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::GatewayTimeout);
+    let body = json(response).await;
+    assert_eq!(body["code"], "upstream_timeout");
+    assert_eq!(body["request_id"], "req-timeout");
+}
+
+#[tokio::test]
+async fn test_error_codes() {
+    let failing = start_stub(StubOptions::new("failing").status(500)).await;
+    let client = gateway(&format!(
+        r#"
+        [[routes]]
+        path = "/down"
+        method = "GET"
+        timeout = 1
+        upstreams = [{{ url = "{}", weight = 1 }}]
+
+        [[routes]]
+        path = "/empty"
+        method = "GET"
+        timeout = 1
+
+        [[routes]]
+        path = "/failing"
+        method = "GET"
+        timeout = 1
+        upstreams = [{{ url = "{}", weight = 1 }}]
+        "#,
+        closed_url().await,
+        failing.url
+    ))
+    .await;
+
+    let response = client.get("/proxy/down").dispatch().await;
+    assert_eq!(response.status(), Status::BadGateway);
+    assert_eq!(json(response).await["code"], "upstream_connect_error");
+
+    let response = client.get("/proxy/empty").dispatch().await;
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    assert_eq!(json(response).await["code"], "no_upstream_available");
+
+    // 上游自己的错误原样透传
+    let response = client.get("/proxy/failing").dispatch().await;
+    assert_eq!(response.status(), Status::InternalServerError);
+    assert_eq!(json(response).await["upstream"], "failing");
+
+    // 不在 /proxy 下的路径由 404 catcher 处理
+    let response = client.get("/missing").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    assert_eq!(json(response).await["code"], "not_found");
+}
+
+#[tokio::test]
+async fn test_concurrency_limit_isolates_routes() {
+    let slow = start_stub(StubOptions::new("slow").delay(Duration::from_millis(500))).await;
+    let fast = stub("fast").await;
+    let client = gateway(&format!(
+        r#"
+        [[routes]]
+        path = "/slow"
+        method = "GET"
+        timeout = 5
+        upstreams = [{{ url = "{}", weight = 1 }}]
+        concurrency = {{ max_concurrent = 1, queue_size = 0 }}
+
+        [[routes]]
+        path = "/fast"
+        method = "GET"
+        timeout = 5
+        upstreams = [{{ url = "{}", weight = 1 }}]
+        concurrency = {{ max_concurrent = 1 }}
+        "#,
+        slow.url, fast.url
+    ))
+    .await;
+
+    let held = client.get("/proxy/slow").dispatch();
+    let rejected = async {
+        // 等第一个请求先占住名额
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let response = client.get("/proxy/slow").dispatch().await;
+        let status = response.status();
+        (status, json(response).await)
+    };
+    let other = async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        client.get("/proxy/fast").dispatch().await.status()
+    };
+    let (held, (status, body), other) = tokio::join!(held, rejected, other);
+
+    assert_eq!(held.status(), Status::Ok);
+    assert_eq!(status, Status::ServiceUnavailable);
+    assert_eq!(body["code"], "concurrency_limit_exceeded");
+    // 慢路由占满名额不影响其它路由
+    assert_eq!(other, Status::Ok);
+    assert_eq!(slow.hits(), 1);
+}
+
+#[tokio::test]
+async fn test_tenant_quota_and_usage() {
+    let upstream = stub("orders").await;
+    let usage_file = std::env::temp_dir().join(format!("usage-{}.json", rand::random::<u64>()));
+    let client = gateway(&format!(
+        r#"
+        [admin]
+        token = "secret"
+
+        [tenancy]
+        required = true
+        usage_file = "{}"
+
+        [tenancy.tenants.acme]
+        api_keys = ["key-acme"]
+        routes = ["/orders"]
+        daily_quota = 2
+
+        [[routes]]
+        path = "/orders"
+        method = "GET"
+        timeout = 5
+        upstreams = [{{ url = "{}", weight = 1 }}]
+
+        [[routes]]
+        path = "/admin-only"
+        method = "GET"
+        timeout = 5
+        upstreams = [{{ url = "{}", weight = 1 }}]
+        "#,
+        usage_file.display(),
+        upstream.url,
+        upstream.url
+    ))
+    .await;
+    // 响应借用了 client，停机前要先全部释放
+    {
+        let get = |path: &'static str| {
+            client
+                .get(path)
+                .header(Header::new("X-API-Key", "key-acme"))
+                .dispatch()
+        };
+
+        assert_eq!(get("/proxy/orders").await.status(), Status::Ok);
+        assert_eq!(get("/proxy/orders").await.status(), Status::Ok);
+        let response = get("/proxy/orders").await;
+        assert_eq!(response.status(), Status::TooManyRequests);
+        assert_eq!(json(response).await["code"], "quota_exceeded");
+
+        let response = get("/proxy/admin-only").await;
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = client.get("/proxy/orders").dispatch().await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(upstream.hits(), 2);
+
+        let response = client
+            .get("/admin/tenants")
+            .header(Header::new("X-Admin-Token", "secret"))
+            .dispatch()
+            .await;
+        let usage = json(response).await;
+        assert_eq!(usage[0]["tenant"], "acme");
+        assert_eq!(usage[0]["requests_today"], 2);
+        assert_eq!(usage[0]["remaining"], 0);
+        assert_eq!(usage[0]["rejected"], 2);
+    }
+
+    // 停机时用量写入文件
+    client.terminate().await;
+    let saved: Value = serde_json::from_slice(&std::fs::read(&usage_file).unwrap()).unwrap();
+    assert_eq!(saved["acme"]["total_requests"], 2);
+    std::fs::remove_file(usage_file).unwrap();
+}
+
+#[tokio::test]
+async fn test_rbac_and_audit_log() {
+    let upstream = stub("orders").await;
+    let audit = std::env::temp_dir().join(format!("audit-{}.log", rand::random::<u64>()));
+    let client = gateway(&format!(
+        r#"
+        [rbac]
+        deny_by_default = true
+        audit_file = "{}"
+        api_keys = [
+            {{ key = "k-reader", principal = "reporting", roles = ["reader"] }},
+            {{ key = "k-admin", principal = "ops", roles = ["admin"] }},
+        ]
+
+        [[routes]]
+        path = "/orders/*"
+        method = "GET|DELETE"
+        timeout = 5
+        upstreams = [{{ url = "{}", weight = 1 }}]
+        required_roles = ["reader", "admin"]
+        method_policies.DELETE = {{ required_roles = ["admin"] }}
+
+        [[routes]]
+        path = "/open"
+        method = "GET"
+        timeout = 5
+        upstreams = [{{ url = "{}", weight = 1 }}]
+        "#,
+        audit.display(),
+        upstream.url,
+        upstream.url
+    ))
+    .await;
+    let status = |method: &str, path: &'static str, key: Option<&'static str>| {
+        let mut request = match method {
+            "DELETE" => client.delete(path),
+            _ => client.get(path),
+        };
+        if let Some(key) = key {
+            request = request.header(Header::new("X-API-Key", key));
+        }
+        async move { request.dispatch().await.status() }
+    };
+
+    assert_eq!(
+        status("GET", "/proxy/orders/1", Some("k-reader")).await,
+        Status::Ok
+    );
+    assert_eq!(
+        status("DELETE", "/proxy/orders/1", Some("k-reader")).await,
+        Status::Forbidden
+    );
+    assert_eq!(
+        status("DELETE", "/proxy/orders/1", Some("k-admin")).await,
+        Status::Ok
+    );
+    assert_eq!(
+        status("GET", "/proxy/orders/1", None).await,
+        Status::Unauthorized
+    );
+    // 没有配置要求的路由在 deny_by_default 下被拒绝
+    assert_eq!(
+        status("GET", "/proxy/open", Some("k-admin")).await,
+        Status::Forbidden
+    );
+    assert_eq!(upstream.hits(), 2);
+
+    let records: Vec<Value> = std::fs::read_to_string(&audit)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let summary: Vec<(Value, Value, Value)> = records
+        .iter()
+        .map(|r| {
+            (
+                r["principal"].clone(),
+                r["outcome"].clone(),
+                r["reason"].clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("reporting".into(), "allow".into(), "allowed".into()),
+            ("reporting".into(), "deny".into(), "missing_role".into()),
+            ("ops".into(), "allow".into(), "allowed".into()),
+            (Value::Null, "deny".into(), "unauthenticated".into()),
+            ("ops".into(), "deny".into(), "no_policy".into()),
+        ]
+    );
+    assert_eq!(records[1]["route"], "/orders/*");
+    assert_eq!(records[1]["method"], "DELETE");
+    std::fs::remove_file(audit).unwrap();
+}
+
+#[tokio::test]
+async fn test_api_versions() {
+    let v1 = stub("v1").await;
+    let v2 = stub("v2").await;
+    let client = gateway(&format!(
+        r#"
+        [[routes]]
+        path = "/users"
+        method = "GET"
+        timeout = 5
+        cors = {{ allowed_origins = ["https://app.example.com"] }}
+
+        [routes.versioning]
+        default = "v2"
+
+        [[routes.versioning.versions]]
+        name = "v1"
+        upstreams = [{{ url = "{}", weight = 1 }}]
+        deprecated = true
+        sunset = "2999-01-01"
+
+        [[routes.versioning.versions]]
+        name = "v2"
+        upstreams = [{{ url = "{}", weight = 1 }}]
+
+        [[routes.versioning.versions]]
+        name = "v0"
+        sunset = "2000-01-01"
+        "#,
+        v1.url, v2.url
+    ))
+    .await;
+
+    let response = client.get("/proxy/users").dispatch().await;
+    assert_eq!(response.headers().get_one("API-Version"), Some("v2"));
+    assert_eq!(response.headers().get_one("Deprecation"), None);
+    assert_eq!(json(response).await["upstream"], "v2");
+
+    // 路径里带版本时同样使用路由自己的 CORS 策略
+    let response = client
+        .get("/proxy/v1/users")
+        .header(Header::new("Origin", "https://app.example.com"))
+        .dispatch()
+        .await;
+    assert_eq!(
+        response.headers().get_one("Access-Control-Allow-Origin"),
+        Some("https://app.example.com")
+    );
+    assert_eq!(response.headers().get_one("Deprecation"), Some("true"));
+    assert_eq!(
+        response.headers().get_one("Sunset"),
+        Some("Tue, 01 Jan 2999 00:00:00 GMT")
+    );
+    assert_eq!(json(response).await["upstream"], "v1");
+
+    let response = client
+        .get("/proxy/users")
+        .header(Header::new("Accept-Version", "1"))
+        .dispatch()
+        .await;
+    assert_eq!(json(response).await["upstream"], "v1");
+    let response = client
+        .get("/proxy/users")
+        .header(Header::new("Accept", "application/json; version=1"))
+        .dispatch()
+        .await;
+    assert_eq!(json(response).await["upstream"], "v1");
+    let response = client.get("/proxy/users?version=v1").dispatch().await;
+    assert_eq!(json(response).await["upstream"], "v1");
+
+    let response = client.get("/proxy/v3/users").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+    let response = client.get("/proxy/users?version=3").dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+    let response = client.get("/proxy/v0/users").dispatch().await;
+    assert_eq!(response.status(), Status::Gone);
+    assert_eq!(json(response).await["code"], "version_retired");
+
+    // 路由自己没有 upstreams，就绪状态按各版本的上游统计
+    let response = client.get("/readyz").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    let body = json(response).await;
+    let versions: Vec<&str> = body["routes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["version"].as_str().unwrap())
+        .collect();
+    assert_eq!(versions, vec!["v1", "v2"]);
+    let body = json(client.get("/health/details").dispatch().await).await;
+    assert_eq!(body["upstreams"].as_array().unwrap().len(), 2);
+
+    let metrics: Value = client
+        .get("/metrics")
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    let count = |key: &str| metrics["counters"][key].clone();
+    assert_eq!(
+        count(r#"api_version_requests_total{route="/users",version="v1",source="path"}"#),
+        1
+    );
+    assert_eq!(
+        count(r#"api_version_requests_total{route="/users",version="v2",source="default"}"#),
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_body_conversion() {
+    // 旧式上游：收 XML / 表单，回 XML / 表单，并把收到的内容带回来
+    let upstream = common::start_typed_server(|request| {
+        let received = String::from_utf8_lossy(&request.body).replace('&', "&amp;");
+        let content_type = request
+            .header("Content-Type")
+            .unwrap_or_default()
+            .to_string();
+        match request.path.as_str() {
+            "/xml" => (
+                200,
+                "text/xml",
+                format!(
+                    "<response status=\"ok\"><type>{}</type><received>{}</received></response>",
+                    content_type,
+                    received.replace('<', "&lt;")
+                ),
+            ),
+            "/form" => (
+                200,
+                "application/x-www-form-urlencoded",
+                "result=ok&id=1&id=2".to_string(),
+            ),
+            _ => (200, "text/xml", "<broken>".to_string()),
+        }
+    })
+    .await;
+    let client = gateway(&format!(
+        r#"
+        [[routes]]
+        path = "/xml"
+        method = "POST"
+        timeout = 5
+        upstreams = [{{ url = "{url}/xml", weight = 1 }}]
+        convert = {{ upstream = "xml", xml_root = "order" }}
+
+        [[routes]]
+        path = "/form"
+        method = "POST"
+        timeout = 5
+        upstreams = [{{ url = "{url}/form", weight = 1 }}]
+        convert = {{ upstream = "form" }}
+
+        [[routes]]
+        path = "/broken"
+        method = "POST"
+        timeout = 5
+        upstreams = [{{ url = "{url}/broken", weight = 1 }}]
+        convert = {{ upstream = "xml" }}
+        "#,
+        url = upstream
+    ))
+    .await;
+    let post = |path: &'static str, body: &'static str| {
+        client
+            .post(path)
+            .header(ContentType::JSON)
+            .body(body)
+            .dispatch()
+    };
+
+    let response = post("/proxy/xml", r#"{"id":7,"item":"a&b"}"#).await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    let body = json(response).await;
+    assert_eq!(body["@status"], "ok");
+    assert_eq!(body["type"], "application/xml; charset=utf-8");
+    assert_eq!(
+        body["received"],
+        r#"<?xml version="1.0" encoding="UTF-8"?><order><id>7</id><item>a&amp;b</item></order>"#
+    );
+
+    let response = post("/proxy/form", r#"{"q":"x y"}"#).await;
+    assert_eq!(
+        json(response).await,
+        serde_json::json!({ "result": "ok", "id": ["1", "2"] })
+    );
+
+    // 请求体无法转换 -> 400，上游响应无法转换 -> 502
+    let response = post("/proxy/form", r#"{"nested":{"a":1}}"#).await;
+    assert_eq!(response.status(), Status::BadRequest);
+    let message = json(response).await["message"].clone();
+    assert!(message
+        .as_str()
+        .unwrap()
+        .starts_with("Cannot convert request body to form"));
+    let response = post("/proxy/xml", "not json").await;
+    assert_eq!(response.status(), Status::BadRequest);
+    let response = post("/proxy/broken", "{}").await;
+    assert_eq!(response.status(), Status::BadGateway);
+    assert_eq!(json(response).await["code"], "upstream_error");
+}
+
+#[tokio::test]
+async fn test_resumable_upload() {
+    // 上游第一次返回 503，之后把收到的文件和头带回来
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let counter = attempts.clone();
+    let upstream = common::start_server(move |request| {
+        if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+            return (503, "{}".to_string());
+        }
+        let body = serde_json::json!({
+            "body": String::from_utf8_lossy(&request.body),
+            "content_type": request.header("Content-Type"),
+            "disposition": request.header("Content-Disposition"),
+            "digest": request.header("Content-Digest"),
+        });
+        (201, body.to_string())
+    })
+    .await;
+    let (v1, v2) = (stub("v1").await, stub("v2").await);
+    let dir = std::env::temp_dir().join(format!("uploads-{}", rand::random::<u64>()));
+    let client = gateway(&format!(
+        r#"
+        [uploads]
+        dir = "{}"
+
+        [access]
+        deny = ["203.0.113.0/24"]
+
+        [[routes]]
+        path = "/files"
+        method = "POST"
+        timeout = 5
+        upstreams = [{{ url = "{}", weight = 1 }}]
+        upload = {{ max_size = 16, max_chunk_size = 8 }}
+
+        [[routes]]
+        path = "/docs"
+        method = "POST"
+        timeout = 5
+        upload = {{ max_size = 16, max_chunk_size = 8 }}
+
+        [routes.versioning]
+        default = "v1"
+
+        [[routes.versioning.versions]]
+        name = "v1"
+        upstreams = [{{ url = "{}", weight = 1 }}]
+
+        [[routes.versioning.versions]]
+        name = "v2"
+        upstreams = [{{ url = "{}", weight = 1 }}]
+        "#,
+        dir.display(),
+        upstream,
+        v1.url,
+        v2.url
+    ))
+    .await;
+    let create = |length: &str| {
+        client
+            .post("/proxy/files")
+            .header(Header::new("Tus-Resumable", "1.0.0"))
+            .header(Header::new("Upload-Length", length.to_string()))
+            .header(Header::new(
+                "Upload-Metadata",
+                "filename aGVsbG8udHh0,filetype dGV4dC9wbGFpbg==",
+            ))
+    };
+    let patch = |location: &str, offset: u64, chunk: &'static [u8]| {
+        client
+            .patch(location.to_string())
+            .header(Header::new(
+                "Content-Type",
+                "application/offset+octet-stream",
+            ))
+            .header(Header::new("Upload-Offset", offset.to_string()))
+            .body(chunk)
+    };
+    let sha256 = |data: &[u8]| format!("sha256 {}", BASE64.encode(Sha256::digest(data)));
+    let offset = |location: String| {
+        let client = &client;
+        async move {
+            let response = client.head(location).dispatch().await;
+            if response.status() != Status::Ok {
+                return None;
+            }
+            let offset = response.headers().get_one("Upload-Offset")?;
+            Some(offset.parse::<u64>().unwrap())
+        }
+    };
+
+    let response = client.post("/proxy/files").dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+    let response = create("17").dispatch().await;
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+
+    let response = create("12").dispatch().await;
+    assert_eq!(response.status(), Status::Created);
+    assert_eq!(response.headers().get_one("Tus-Resumable"), Some("1.0.0"));
+    let location = response.headers().get_one("Location").unwrap().to_string();
+    assert!(location.starts_with("/uploads/"));
+    assert_eq!(offset(location.clone()).await, Some(0));
+
+    let response = client
+        .patch(location.clone())
+        .header(ContentType::JSON)
+        .header(Header::new("Upload-Offset", "0"))
+        .body("{}")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::UnsupportedMediaType);
+
+    // 已经拿到上传 ID 的客户端被加入全局黑名单后不能继续上传
+    let denied = "203.0.113.9:4000".parse().unwrap();
+    let response = patch(&location, 0, b"hello ")
+        .remote(denied)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+    let response = client
+        .head(location.clone())
+        .remote(denied)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let response = patch(&location, 0, b"hello ")
+        .header(Header::new("Upload-Checksum", sha256(b"hello ")))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(response.headers().get_one("Upload-Offset"), Some("6"));
+
+    // 重发已经收到的分块：偏移不一致
+    let response = patch(&location, 0, b"hello ").dispatch().await;
+    assert_eq!(response.status(), Status::Conflict);
+    assert_eq!(json(response).await["code"], "upload_offset_mismatch");
+    // 传输中损坏的分块被丢弃
+    let response = patch(&location, 6, b"world!")
+        .header(Header::new("Upload-Checksum", sha256(b"w0rld!")))
+        .dispatch()
+        .await;
+    assert_eq!(response.status().code, 460);
+    let response = patch(&location, 6, b"world!!!!").dispatch().await;
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+    assert_eq!(offset(location.clone()).await, Some(6));
+
+    // 最后一块到齐后转发；上游失败时保留文件
+    let response = patch(&location, 6, b"world!").dispatch().await;
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    assert_eq!(offset(location.clone()).await, Some(12));
+
+    // 在末尾偏移发送空分块重试转发
+    let response = patch(&location, 12, b"").dispatch().await;
+    assert_eq!(response.status(), Status::Created);
+    assert_eq!(response.headers().get_one("Upload-Offset"), Some("12"));
+    let body = json(response).await;
+    assert_eq!(body["body"], "hello world!");
+    assert_eq!(body["content_type"], "text/plain");
+    assert_eq!(body["disposition"], "attachment; filename=\"hello.txt\"");
+    let digest = BASE64.encode(Sha256::digest(b"hello world!"));
+    assert_eq!(body["digest"], format!("sha-256=:{}:", digest));
+    assert_eq!(offset(location.clone()).await, None);
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+
+    // 取消上传
+    let response = create("4").dispatch().await;
+    let location = response.headers().get_one("Location").unwrap().to_string();
+    let response = client.delete(location.clone()).dispatch().await;
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(offset(location.clone()).await, None);
+    let response = patch(&location, 0, b"data").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    // 创建时协商出的 API 版本决定完成后发往哪组上游
+    let response = client
+        .post("/proxy/v2/docs")
+        .header(Header::new("Upload-Length", "4"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+    let location = response.headers().get_one("Location").unwrap().to_string();
+    let response = patch(&location, 0, b"data").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(json(response).await["upstream"], "v2");
+    assert_eq!((v1.hits(), v2.hits()), (0, 1));
+
+    let metrics = json(client.get("/metrics").dispatch().await).await;
+    assert_eq!(
+        metrics["counters"]["uploads_forwarded_total{route=\"/files\",status=\"201\"}"],
+        1
+    );
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_truncate_fault_closes_connection_after_headers() {
+    let upstream = stub("truncate").await;
+    let addr = common::launch(&format!(
+        r#"
+        [[routes]]
+        path = "/truncate"
+        method = "GET"
+        timeout = 5
+        upstreams = [{{ url = "{}", weight = 1 }}]
+        fault = {{ truncate = {{ percentage = 100 }} }}
+        "#,
+        upstream.url
+    ))
+    .await;
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /proxy/truncate HTTP/1.1\r\nHost: gateway\r\n\r\n")
+        .await
+        .unwrap();
+    // keep-alive 的请求，连接被关闭说明响应没有发完；最多收到响应头，收不到响应体
+    let mut received = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut received))
+        .await
+        .expect("connection closed")
+        .ok();
+    let received = String::from_utf8_lossy(&received).to_lowercase();
+    if !received.is_empty() {
+        assert!(received.contains("content-length: 1\r\n"), "{}", received);
+        assert!(received.ends_with("\r\n\r\n"), "{}", received);
+    }
+    assert_eq!(upstream.hits(), 0);
+}
+
+#[test]
+fn test_upload_rejects_ineffective_settings() {
+    let route = |settings: &str| {
+        common::try_config(&format!(
+            r#"
+            [[routes]]
+            path = "/files"
+            method = "POST"
+            timeout = 5
+            upstreams = [{{ url = "http://127.0.0.1:1", weight = 1 }}]
+            {}
+            "#,
+            settings
+        ))
+        .err()
+        .map(|e| e.to_string())
+        .unwrap_or_default()
+    };
+    let upload = "upload = { max_size = 16, max_chunk_size = 8 }";
+    assert_eq!(route(upload), "");
+    for setting in [
+        "decompress_request = true",
+        r#"transform = { request = { add_headers = { "X-A" = "1" } } }"#,
+        r#"convert = { upstream = "xml" }"#,
+        "concurrency = { max_concurrent = 1 }",
+        r#"mock = { status = 200, body = "ok" }"#,
+    ] {
+        let error = route(&format!("{}\n{}", upload, setting));
+        let name = setting.split(' ').next().unwrap();
+        assert!(error.contains(name), "{}: {}", setting, error);
+    }
+    let error = route("upload = { max_size = 16, max_chunk_size = 32 }");
+    assert!(error.contains("max_chunk_size"), "{}", error);
+    let error = route("upload = { max_size = 16, max_chunk_size = 0 }");
+    assert!(error.contains("max_chunk_size"), "{}", error);
+    let error = common::try_config(
+        r#"
+        [[routes]]
+        path = "/files"
+        method = "PUT"
+        timeout = 5
+        upstreams = [{ url = "http://127.0.0.1:1", weight = 1 }]
+        upload = { max_size = 16, max_chunk_size = 8 }
+        "#,
+    )
+    .unwrap_err();
+    assert!(error.to_string().contains("POST"), "{}", error);
+}
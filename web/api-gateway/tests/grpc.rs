@@ -0,0 +1,353 @@
+mod common;
+
+use common::gateway;
+use rocket::http::Status;
+use serde_json::Value;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::{Channel, Server};
+use tonic::Code;
+use tonic_health::pb::health_check_response::ServingStatus;
+use tonic_health::pb::health_client::HealthClient;
+use tonic_health::pb::HealthCheckRequest;
+
+// 本地 tonic 服务：只提供 grpc.health.v1，"svc.up" 在线、"svc.down" 不在线，
+// "svc.node" 的状态由参数决定，用来区分同一路由下的多个上游
+async fn tonic_server(node: tonic_health::ServingStatus) -> String {
+    let (reporter, service) = tonic_health::server::health_reporter();
+    reporter.set_service_status("svc.node", node).await;
+    reporter
+        .set_service_status("svc.up", tonic_health::ServingStatus::Serving)
+        .await;
+    reporter
+        .set_service_status("svc.down", tonic_health::ServingStatus::NotServing)
+        .await;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        // reporter 需要一直存活，否则状态会被清掉
+        let _reporter = reporter;
+        Server::builder()
+            .add_service(service)
+            .serve_with_incoming(TcpIncoming::from(listener))
+            .await
+            .unwrap();
+    });
+    url
+}
+
+async fn free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    listener.local_addr().unwrap().port()
+}
+
+// gRPC 端口在 liftoff 后才打开，连接失败时稍后重试
+async fn connect(port: u16) -> HealthClient<Channel> {
+    for _ in 0..50 {
+        let endpoint = Channel::from_shared(format!("http://127.0.0.1:{}", port)).unwrap();
+        if let Ok(channel) = endpoint.connect().await {
+            return HealthClient::new(channel);
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("gRPC listener did not start on port {}", port);
+}
+
+async fn check(client: &mut HealthClient<Channel>, service: &str) -> Result<i32, Code> {
+    client
+        .check(HealthCheckRequest {
+            service: service.to_string(),
+        })
+        .await
+        .map(|response| response.into_inner().status)
+        .map_err(|status| status.code())
+}
+
+#[tokio::test]
+async fn test_grpc_proxy_and_health() {
+    let upstream = tonic_server(tonic_health::ServingStatus::Serving).await;
+    let port = free_port().await;
+    let http = gateway(&format!(
+        r#"
+        [grpc]
+        port = {port}
+
+        [[routes]]
+        path = "/grpc.health.v1.Health/Check"
+        method = "POST"
+        protocol = "grpc"
+        timeout = 5
+        upstreams = [{{ url = "{upstream}", weight = 1 }}]
+        health_check = {{ grpc = true, service = "svc.up", interval = 1 }}
+
+        [[routes]]
+        path = "/unused.Service/*"
+        method = "POST"
+        protocol = "grpc"
+        timeout = 5
+        upstreams = [{{ url = "{upstream}", weight = 1 }}]
+        health_check = {{ grpc = true, service = "svc.down", interval = 1, unhealthy_threshold = 1 }}
+        "#
+    ))
+    .await;
+    let mut client = connect(port).await;
+
+    // 正常调用经网关转发到 tonic 服务
+    assert_eq!(
+        check(&mut client, "svc.up").await,
+        Ok(ServingStatus::Serving as i32)
+    );
+    // 上游在 trailers 里返回的 grpc-status 原样透传
+    assert_eq!(check(&mut client, "svc.missing").await, Err(Code::NotFound));
+
+    // 没有路由的方法由网关返回 UNIMPLEMENTED
+    let mut watch = client.clone();
+    let result = watch
+        .watch(HealthCheckRequest {
+            service: "svc.up".to_string(),
+        })
+        .await;
+    assert_eq!(result.err().map(|s| s.code()), Some(Code::Unimplemented));
+
+    // gRPC 路由不会在 HTTP 端口上匹配
+    let response = http
+        .post("/proxy/grpc.health.v1.Health/Check")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    // gRPC 健康检查的结果反映到 /readyz
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let response = http.get("/readyz").dispatch().await;
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    let body: Value = response.into_json().await.unwrap();
+    let healthy = |path: &str| {
+        body["routes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|r| r["path"] == path)
+            .unwrap()["healthy_upstreams"]
+            .clone()
+    };
+    assert_eq!(healthy("/grpc.health.v1.Health/Check"), 1);
+    assert_eq!(healthy("/unused.Service/*"), 0);
+}
+
+#[tokio::test]
+async fn test_grpc_skips_not_serving_upstream() {
+    let serving = tonic_server(tonic_health::ServingStatus::Serving).await;
+    let not_serving = tonic_server(tonic_health::ServingStatus::NotServing).await;
+    let port = free_port().await;
+    let _http = gateway(&format!(
+        r#"
+        [grpc]
+        port = {port}
+
+        [[routes]]
+        path = "/grpc.health.v1.Health/Check"
+        method = "POST"
+        protocol = "grpc"
+        timeout = 5
+        upstreams = [{{ url = "{serving}", weight = 1 }}, {{ url = "{not_serving}", weight = 1 }}]
+        health_check = {{ grpc = true, service = "svc.node", interval = 1, unhealthy_threshold = 1 }}
+        "#
+    ))
+    .await;
+    let mut client = connect(port).await;
+
+    // 等第一轮探测把 NOT_SERVING 的上游标记为不健康，之后轮询只会落到在线的上游
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    for _ in 0..6 {
+        assert_eq!(
+            check(&mut client, "svc.node").await,
+            Ok(ServingStatus::Serving as i32)
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_grpc_unavailable_upstream() {
+    let closed = {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    };
+    let port = free_port().await;
+    let _http = gateway(&format!(
+        r#"
+        [grpc]
+        port = {port}
+
+        [[routes]]
+        path = "/grpc.health.v1.Health/*"
+        method = "POST"
+        protocol = "grpc"
+        timeout = 1
+        upstreams = [{{ url = "{closed}", weight = 1 }}]
+        "#
+    ))
+    .await;
+    let mut client = connect(port).await;
+
+    assert_eq!(check(&mut client, "").await, Err(Code::Unavailable));
+}
+
+#[tokio::test]
+async fn test_grpc_requires_roles() {
+    let upstream = tonic_server(tonic_health::ServingStatus::Serving).await;
+    let port = free_port().await;
+    let _http = gateway(&format!(
+        r#"
+        [grpc]
+        port = {port}
+
+        [rbac]
+        api_keys = [{{ key = "k-reader", principal = "reporting", roles = ["reader"] }}]
+
+        [[routes]]
+        path = "/grpc.health.v1.Health/Check"
+        method = "POST"
+        protocol = "grpc"
+        timeout = 5
+        upstreams = [{{ url = "{upstream}", weight = 1 }}]
+        required_roles = ["reader"]
+        "#
+    ))
+    .await;
+    let mut client = connect(port).await;
+
+    assert_eq!(
+        check(&mut client, "svc.up").await,
+        Err(Code::Unauthenticated)
+    );
+
+    let mut request = tonic::Request::new(HealthCheckRequest {
+        service: "svc.up".to_string(),
+    });
+    request
+        .metadata_mut()
+        .insert("x-api-key", "k-reader".parse().unwrap());
+    let status = client.check(request).await.unwrap().into_inner().status;
+    assert_eq!(status, ServingStatus::Serving as i32);
+}
+
+#[test]
+fn test_grpc_rejects_http_only_settings() {
+    for setting in [
+        r#"signature = { keys = { k1 = "secret" } }"#,
+        "concurrency = { max_concurrent = 1 }",
+        "fault = { abort = { status = 503, percentage = 100 } }",
+        r#"method_policies = { POST = { required_roles = ["admin"] } }"#,
+        r#"upstream_auth = { token_url = "http://127.0.0.1:1/token", client_id = "gw", client_secret = "s" }"#,
+        r#"mirror = { url = "http://127.0.0.1:1" }"#,
+        r#"transform = { request = { add_headers = { "X-A" = "1" } } }"#,
+        r#"convert = { upstream = "xml" }"#,
+        r#"mock = { status = 200, body = "ok" }"#,
+        r#"aggregate = { calls = [] }"#,
+        r#"versioning = { versions = [{ name = "v1" }] }"#,
+        "upload = { max_size = 16, max_chunk_size = 8 }",
+        "decompress_request = true",
+        r#"openapi = { summary = "health" }"#,
+        r#"cors = { allowed_origins = ["https://app.example.com"] }"#,
+        r#"sticky = { cookie = "gw" }"#,
+    ] {
+        let error = common::try_config(&format!(
+            r#"
+            [[routes]]
+            path = "/grpc.health.v1.Health/Check"
+            method = "POST"
+            protocol = "grpc"
+            timeout = 5
+            upstreams = [{{ url = "http://127.0.0.1:1", weight = 1 }}]
+            {}
+            "#,
+            setting
+        ))
+        .err()
+        .map(|e| e.to_string())
+        .unwrap_or_default();
+        let name = setting.split(' ').next().unwrap();
+        assert!(
+            error.contains(&format!("{} is not supported on grpc routes", name)),
+            "{}: {}",
+            setting,
+            error
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_grpc_client_ip_from_trusted_proxy() {
+    let upstream = tonic_server(tonic_health::ServingStatus::Serving).await;
+    let port = free_port().await;
+    // 网关前面是本机的受信代理，客户端 IP 取自 X-Forwarded-For
+    let toml = format!(
+        r#"
+        [server]
+        host = "127.0.0.1"
+        port = 0
+        workers = 2
+        trusted_proxies = ["127.0.0.1/32"]
+
+        [logging]
+        level = "warn"
+        format = "text"
+
+        [grpc]
+        port = {port}
+
+        [access]
+        deny = ["203.0.113.0/24"]
+
+        [[routes]]
+        path = "/grpc.health.v1.Health/Check"
+        method = "POST"
+        protocol = "grpc"
+        timeout = 5
+        upstreams = [{{ url = "{upstream}", weight = 1 }}]
+        "#
+    );
+    let settings = config::Config::builder()
+        .add_source(config::File::from_str(&toml, config::FileFormat::Toml))
+        .build()
+        .unwrap();
+    let http = rocket::local::asynchronous::Client::tracked(api_gateway::rocket(
+        api_gateway::parse_config(settings).unwrap(),
+    ))
+    .await
+    .unwrap();
+    let mut client = connect(port).await;
+
+    let call = |forwarded_for: &'static str| {
+        let mut client = client.clone();
+        async move {
+            let mut request = tonic::Request::new(HealthCheckRequest {
+                service: "svc.up".to_string(),
+            });
+            request
+                .metadata_mut()
+                .insert("x-forwarded-for", forwarded_for.parse().unwrap());
+            client.check(request).await.map_err(|status| status.code())
+        }
+    };
+    assert_eq!(
+        call("203.0.113.9").await.err(),
+        Some(Code::PermissionDenied)
+    );
+    assert!(call("198.51.100.7").await.is_ok());
+    assert!(check(&mut client, "svc.up").await.is_ok());
+
+    let metrics: Value = http
+        .get("/metrics")
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(
+        metrics["counters"]["access_denied_total{scope=\"global\",route=\"\"}"],
+        1
+    );
+}
@@ -0,0 +1,291 @@
+mod common;
+
+use common::{gateway, start_server, start_stub, stub, StubOptions};
+use rocket::http::{Header, Status};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// 桩 OAuth 服务：/introspect 只认 "good-token"，/token 每次签发新的 token
+struct OAuthStub {
+    url: String,
+    introspections: Arc<AtomicUsize>,
+    issued: Arc<AtomicUsize>,
+}
+
+async fn oauth_server() -> OAuthStub {
+    let introspections = Arc::new(AtomicUsize::new(0));
+    let issued = Arc::new(AtomicUsize::new(0));
+    let (i, t) = (introspections.clone(), issued.clone());
+    let url = start_server(move |request| {
+        // 两个接口都要求网关用 client_id / client_secret 做 Basic 认证（gateway:secret）
+        if request.header("Authorization") != Some("Basic Z2F0ZXdheTpzZWNyZXQ=") {
+            return (401, json!({ "error": "invalid_client" }).to_string());
+        }
+        match request.path.as_str() {
+            "/introspect" => {
+                i.fetch_add(1, Ordering::SeqCst);
+                let body = match request.form("token").as_deref() {
+                    Some("good-token") => json!({
+                        "active": true,
+                        "sub": "alice",
+                        "client_id": "web",
+                        "scope": "orders:read",
+                    }),
+                    _ => json!({ "active": false }),
+                };
+                (200, body.to_string())
+            }
+            "/token" => {
+                assert_eq!(
+                    request.form("grant_type").as_deref(),
+                    Some("client_credentials")
+                );
+                let n = t.fetch_add(1, Ordering::SeqCst) + 1;
+                let body = json!({
+                    "access_token": format!("svc-token-{}", n),
+                    "token_type": "Bearer",
+                    "expires_in": 3600,
+                });
+                (200, body.to_string())
+            }
+            _ => (404, "{}".to_string()),
+        }
+    })
+    .await;
+    OAuthStub {
+        url,
+        introspections,
+        issued,
+    }
+}
+
+#[tokio::test]
+async fn test_introspection_auth() {
+    let oauth = oauth_server().await;
+    let upstream = stub("orders").await;
+    let client = gateway(&format!(
+        r#"
+        [[routes]]
+        path = "/orders"
+        method = "GET"
+        timeout = 5
+        upstreams = [{{ url = "{}", weight = 1 }}]
+        auth = {{ mode = "introspection", endpoint = "{}/introspect", client_id = "gateway", client_secret = "secret" }}
+        "#,
+        upstream.url, oauth.url
+    ))
+    .await;
+    let get = |token: &str| {
+        client
+            .get("/proxy/orders")
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Auth-Subject", "mallory"))
+    };
+
+    let response = client.get("/proxy/orders").dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    let response = get("bad-token").dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    for _ in 0..3 {
+        let response = get("good-token").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let body: Value = response.into_json().await.unwrap();
+        // 身份头由网关填写，覆盖客户端伪造的值
+        assert_eq!(body["headers"]["x-auth-subject"], "alice");
+        assert_eq!(body["headers"]["x-auth-scope"], "orders:read");
+    }
+    // 有效和无效的结果都被缓存
+    let response = get("bad-token").dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(oauth.introspections.load(Ordering::SeqCst), 2);
+    assert_eq!(upstream.hits(), 3);
+}
+
+#[tokio::test]
+async fn test_introspection_endpoint_down() {
+    let upstream = stub("orders").await;
+    let client = gateway(&format!(
+        r#"
+        [[routes]]
+        path = "/orders"
+        method = "GET"
+        timeout = 5
+        upstreams = [{{ url = "{}", weight = 1 }}]
+        auth = {{ mode = "introspection", endpoint = "{}introspect", client_id = "gateway", client_secret = "secret" }}
+        "#,
+        upstream.url,
+        common::closed_url().await
+    ))
+    .await;
+
+    let response = client
+        .get("/proxy/orders")
+        .header(Header::new("Authorization", "Bearer good-token"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::BadGateway);
+    assert_eq!(upstream.hits(), 0);
+}
+
+#[tokio::test]
+async fn test_client_credentials_upstream_auth() {
+    let oauth = oauth_server().await;
+    let upstream = stub("billing").await;
+    let rejecting = start_stub(StubOptions::new("rejecting").status(401)).await;
+    let client = gateway(&format!(
+        r#"
+        [[routes]]
+        path = "/billing"
+        method = "GET"
+        timeout = 5
+        upstreams = [{{ url = "{}", weight = 1 }}]
+        upstream_auth = {{ token_url = "{oauth}/token", client_id = "gateway", client_secret = "secret", scope = "billing" }}
+
+        [[routes]]
+        path = "/rejecting"
+        method = "GET"
+        timeout = 5
+        upstreams = [{{ url = "{}", weight = 1 }}]
+        upstream_auth = {{ token_url = "{oauth}/token", client_id = "gateway", client_secret = "secret" }}
+        "#,
+        upstream.url,
+        rejecting.url,
+        oauth = oauth.url
+    ))
+    .await;
+
+    for _ in 0..3 {
+        let response = client
+            .get("/proxy/billing")
+            .header(Header::new("Authorization", "Bearer client-token"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let body: Value = response.into_json().await.unwrap();
+        assert_eq!(body["headers"]["authorization"], "Bearer svc-token-1");
+    }
+    assert_eq!(oauth.issued.load(Ordering::SeqCst), 1);
+
+    // 上游拒绝 token 后重新获取
+    let response = client.get("/proxy/rejecting").dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    let response = client.get("/proxy/rejecting").dispatch().await;
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["headers"]["authorization"], "Bearer svc-token-3");
+    assert_eq!(oauth.issued.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn test_missing_secret_env_var_fails_config_load() {
+    let error = common::try_config(
+        r#"
+        [[routes]]
+        path = "/orders"
+        method = "GET"
+        timeout = 5
+        upstreams = [{ url = "http://127.0.0.1:1", weight = 1 }]
+        auth = { mode = "introspection", endpoint = "http://127.0.0.1:1/introspect", client_id = "gateway", client_secret = "env:GATEWAY_TEST_UNSET_SECRET" }
+        "#,
+    )
+    .unwrap_err();
+    assert!(
+        error.to_string().contains("GATEWAY_TEST_UNSET_SECRET"),
+        "{}",
+        error
+    );
+}
+
+// HS256 签名的测试 JWT
+fn jwt(claims: Value, secret: &str) -> String {
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use hmac::{Hmac, Mac};
+
+    let signed = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(json!({ "alg": "HS256", "typ": "JWT" }).to_string()),
+        URL_SAFE_NO_PAD.encode(claims.to_string())
+    );
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(signed.as_bytes());
+    format!(
+        "{}.{}",
+        signed,
+        URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+    )
+}
+
+#[tokio::test]
+async fn test_jwt_auth_and_roles() {
+    let upstream = stub("admin").await;
+    let client = gateway(&format!(
+        r#"
+        [rbac]
+        jwt_roles_claim = "roles"
+
+        [[routes]]
+        path = "/admin"
+        method = "GET"
+        timeout = 5
+        upstreams = [{{ url = "{}", weight = 1 }}]
+        auth = {{ mode = "jwt", secret = "jwt-secret", issuer = "idp" }}
+        required_roles = ["admin"]
+        "#,
+        upstream.url
+    ))
+    .await;
+    let exp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 60;
+    let get = |token: String| {
+        client
+            .get("/proxy/admin")
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch()
+    };
+
+    let admin = json!({ "sub": "bob", "iss": "idp", "exp": exp, "roles": ["admin"] });
+    assert_eq!(
+        get(jwt(admin.clone(), "jwt-secret")).await.status(),
+        Status::Ok
+    );
+    // 角色不够、签名不对、缺少 exp 都不放行
+    let reader = json!({ "sub": "amy", "iss": "idp", "exp": exp, "roles": ["reader"] });
+    assert_eq!(
+        get(jwt(reader, "jwt-secret")).await.status(),
+        Status::Forbidden
+    );
+    assert_eq!(
+        get(jwt(admin, "forged")).await.status(),
+        Status::Unauthorized
+    );
+    let no_exp = json!({ "sub": "bob", "iss": "idp", "roles": ["admin"] });
+    assert_eq!(
+        get(jwt(no_exp, "jwt-secret")).await.status(),
+        Status::Unauthorized
+    );
+    assert_eq!(upstream.hits(), 1);
+}
+
+#[test]
+fn test_jwt_roles_claim_requires_auth() {
+    let error = common::try_config(
+        r#"
+        [rbac]
+        jwt_roles_claim = "roles"
+
+        [[routes]]
+        path = "/admin"
+        method = "GET"
+        timeout = 5
+        upstreams = [{ url = "http://127.0.0.1:1", weight = 1 }]
+        required_roles = ["admin"]
+        "#,
+    )
+    .unwrap_err();
+    assert!(error.to_string().contains("jwt_roles_claim"), "{}", error);
+}